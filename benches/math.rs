@@ -0,0 +1,140 @@
+//! Timings of the V4/M4 arithmetic and of the intersection-heavy paths
+//! built on it, against the scalar fallback in the same run. For the
+//! whole renderer, compare a run with `cargo bench --features scalar`.
+
+use raytracer_challenge::linalg::{scalar,Float,M4,V4,SIMD};
+use raytracer_challenge::ray::Ray;
+use raytracer_challenge::sceneparser;
+use raytracer_challenge::shape::{BaseShape,Hits};
+use raytracer_challenge::sphere::Sphere;
+use raytracer_challenge::transform::Transform;
+use raytracer_challenge::Camera;
+
+use std::hint::black_box;
+use std::path::Path;
+use std::time::Instant;
+
+/// Run `f` `n` times after a warm-up and print the time per call, in ns.
+fn bench<F: FnMut()>(name: &str, n: u32, mut f: F) -> f64 {
+    for _ in 0..n / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..n {
+        f();
+    }
+    let ns = start.elapsed().as_nanos() as f64 / n as f64;
+
+    println!("{:<28} {:>12.2} ns", name, ns);
+    ns
+}
+
+fn compare<F: FnMut(), G: FnMut()>(name: &str, n: u32, f: F, g: G) {
+    let simd = bench(name, n, f);
+    let plain = bench(&format!("{} (scalar)", name), n, g);
+    println!("{:<28} {:>12.2}x", "", plain / simd);
+}
+
+fn arithmetic() {
+    // pseudo-random points, so that nothing folds away
+    let pts: Vec<V4> = (0..1024).map(|i| {
+        let f = i as Float;
+        V4::new_point((f * 0.37).sin(), (f * 0.11).cos(), f * 0.001)
+    }).collect();
+    let b = V4::new_vector(0.5, 3.0, -1.0);
+    let m = Transform::new().translate(1.0, 2.0, 3.0).rotate_y(0.5).scale(2.0, 1.0, 0.5).matrix;
+
+    const N: u32 = 20_000;
+
+    compare("V4 add", N, || {
+        black_box(pts.iter().fold(b, |acc, p| acc + *p));
+    }, || {
+        black_box(pts.iter().fold(b, |acc, p| scalar::add(&acc, p)));
+    });
+    compare("V4 scale", N, || {
+        black_box(pts.iter().fold(b, |acc, p| acc * p.x()));
+    }, || {
+        black_box(pts.iter().fold(b, |acc, p| scalar::scale(&acc, p.x())));
+    });
+    compare("V4 dot", N, || {
+        black_box(pts.iter().map(|p| V4::dot(p, &b)).sum::<Float>());
+    }, || {
+        black_box(pts.iter().map(|p| scalar::dot(p, &b)).sum::<Float>());
+    });
+    compare("V4 cross", N, || {
+        black_box(pts.iter().fold(b, |acc, p| V4::cross(&acc, p)));
+    }, || {
+        black_box(pts.iter().fold(b, |acc, p| scalar::cross(&acc, p)));
+    });
+    compare("M4 transpose_mul", N, || {
+        black_box(pts.iter().fold(b, |acc, p| acc + m.transpose_mul(*p)));
+    }, || {
+        black_box(pts.iter().fold(b, |acc, p| scalar::add(&acc, &scalar::tmvmul(&m, p))));
+    });
+    compare("M4 * M4", N / 4, || {
+        black_box(pts.iter().fold(m, |acc, p| M4::mmul(&acc, &Transform::new().translate(p.x(), 0.0, 0.0).matrix)));
+    }, || {
+        black_box(pts.iter().fold(m, |acc, p| scalar::mmul(&acc, &Transform::new().translate(p.x(), 0.0, 0.0).matrix)));
+    });
+}
+
+/// `Sphere::intersect` after the transformation in `Shape::intersect`,
+/// with the scalar fallback.
+fn sphere_intersect_scalar(mt: &M4, ray: &Ray) -> Hits {
+    let origin = scalar::tmvmul(mt, &ray.origin);
+    let direction = scalar::tmvmul(mt, &ray.direction);
+
+    let s2r = scalar::sub(&origin, &V4::new_point(0.0, 0.0, 0.0));
+
+    let a = scalar::dot(&direction, &direction);
+    let b = 2.0 * scalar::dot(&direction, &s2r);
+    let c = scalar::dot(&s2r, &s2r) - 1.0;
+
+    let d = b*b - 4.0*a*c;
+
+    let mut v = Hits::new();
+
+    if d < 0.0 {
+        return v
+    }
+
+    v.push( (-b - d.sqrt()) / (2.0*a) );
+    v.push( (-b + d.sqrt()) / (2.0*a) );
+
+    v
+}
+
+fn paths() {
+    let (camera, world, _) = sceneparser::read_yaml_scene_file(Path::new("examples/sphere_grid.yaml")).unwrap();
+
+    let ray = Ray::new(V4::new_point(0.0, 9.0, -14.0), V4::new_vector(0.1, -0.55, 1.0).normalize());
+    // shapes keep the transpose of their inverse transformation
+    let mt = world.shapes()[1].transform().invert().transpose();
+
+    compare("ray transform", 20_000_000, || {
+        black_box(black_box(ray).apply_transposed(&mt));
+    }, || {
+        let ray = black_box(ray);
+        black_box(Ray { origin: scalar::tmvmul(&mt, &ray.origin), direction: scalar::tmvmul(&mt, &ray.direction), time: ray.time });
+    });
+    compare("sphere intersect", 10_000_000, || {
+        black_box(Sphere().intersect(&black_box(ray).apply_transposed(&mt)));
+    }, || {
+        black_box(sphere_intersect_scalar(&mt, &black_box(ray)));
+    });
+
+    bench("world color_at (101 shapes)", 50_000, || { black_box(world.color_at(&black_box(ray))); });
+
+    let small = Camera::new(160, 120, camera.field_of_view(), camera.transform());
+    let ms = bench("render 160x120", 5, || { black_box(small.render(&world)); }) / 1e6;
+    println!("{:<28} {:>12.2} ms", "", ms);
+}
+
+fn main() {
+    println!("SIMD arithmetic: {}", if SIMD { "on" } else { "off" });
+    println!();
+    arithmetic();
+    println!();
+    paths();
+}
@@ -0,0 +1,64 @@
+//! Heap allocations and render times of the example scenes. Every scene
+//! is rendered at 160x120 with its own camera position; allocations are
+//! counted by a wrapper around the system allocator.
+
+use raytracer_challenge::sceneparser;
+use raytracer_challenge::Camera;
+
+use std::alloc::{GlobalAlloc,Layout,System};
+use std::hint::black_box;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const SCENES: [&str; 6] = [
+    "chapter10", "chapter11", "chapter11b", "chapter12", "chapter13", "sphere_grid"
+];
+
+const RUNS: u32 = 5;
+
+fn main() {
+    println!("{:<12} {:>14} {:>12}", "scene", "allocs/pixel", "ms/render");
+
+    for name in SCENES.iter() {
+        let file = format!("examples/{}.yaml", name);
+        let (camera, world, _) = sceneparser::read_yaml_scene_file(Path::new(&file)).unwrap();
+        let camera = Camera::new(160, 120, camera.field_of_view(), camera.transform());
+
+        // the first render also warms up
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        black_box(camera.render(&world));
+        let allocs = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            black_box(camera.render(&world));
+        }
+        let ms = start.elapsed().as_secs_f64() * 1e3 / RUNS as f64;
+
+        println!("{:<12} {:>14.2} {:>12.2}", name, allocs as f64 / (160.0 * 120.0), ms);
+    }
+}
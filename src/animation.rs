@@ -0,0 +1,101 @@
+//! Interpolation between keyframes.
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull-Rom spline through the keyframes.
+    Spline
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "spline" => Some(Interpolation::Spline),
+            _ => None
+        }
+    }
+}
+
+/// Slope at keyframe `i`: central difference inside, one-sided at the ends.
+fn tangent(keys: &[(f64,f64)], i: usize) -> f64 {
+    let a = if i > 0 { i - 1 } else { i };
+    let b = if i + 1 < keys.len() { i + 1 } else { i };
+
+    (keys[b].1 - keys[a].1) / (keys[b].0 - keys[a].0)
+}
+
+/// The value at time `t` of a curve through the (time, value) keyframes,
+/// which must be sorted by time with no time twice. Before the first and
+/// after the last keyframe, the value stays constant.
+pub fn sample(keys: &[(f64,f64)], t: f64, interpolation: Interpolation) -> f64 {
+    let last = keys.len() - 1;
+
+    if t <= keys[0].0 {
+        return keys[0].1
+    }
+    if t >= keys[last].0 {
+        return keys[last].1
+    }
+
+    let i = keys.iter().rposition(|k| k.0 <= t).unwrap();
+    let (t0, p0) = keys[i];
+    let (t1, p1) = keys[i + 1];
+
+    let h = t1 - t0;
+    let s = (t - t0) / h;
+
+    match interpolation {
+        Interpolation::Linear => p0 + (p1 - p0) * s,
+        Interpolation::Spline => {
+            let (m0, m1) = (tangent(keys, i) * h, tangent(keys, i + 1) * h);
+            let (s2, s3) = (s * s, s * s * s);
+
+            (2.0*s3 - 3.0*s2 + 1.0) * p0 + (s3 - 2.0*s2 + s) * m0 + (-2.0*s3 + 3.0*s2) * p1 + (s3 - s2) * m1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+
+    #[test]
+    fn linear() {
+        let keys = [ (0.0, 1.0), (10.0, 3.0), (20.0, -1.0) ];
+
+        assert_eq!(sample(&keys, -5.0, Interpolation::Linear), 1.0);
+        assert_eq!(sample(&keys, 5.0, Interpolation::Linear), 2.0);
+        assert_eq!(sample(&keys, 10.0, Interpolation::Linear), 3.0);
+        assert_eq!(sample(&keys, 15.0, Interpolation::Linear), 1.0);
+        assert_eq!(sample(&keys, 25.0, Interpolation::Linear), -1.0);
+        assert_eq!(sample(&[ (4.0, 2.0) ], 5.0, Interpolation::Linear), 2.0);
+    }
+
+    #[test]
+    fn spline() {
+        // passes through the keyframes, smoothly
+        let keys = [ (0.0, 0.0), (10.0, 10.0), (20.0, 0.0), (30.0, 10.0) ];
+
+        for k in keys.iter() {
+            assert!(approx_eq!(f64, sample(&keys, k.0, Interpolation::Spline), k.1, epsilon = 1e-9));
+        }
+
+        assert!(sample(&keys, 9.0, Interpolation::Spline) > sample(&keys, 9.0, Interpolation::Linear));
+        assert!(approx_eq!(f64, sample(&keys, 15.0, Interpolation::Spline), 5.0, epsilon = 1e-9));
+
+        let slope = |t: f64| (sample(&keys, t + 1e-6, Interpolation::Spline) - sample(&keys, t - 1e-6, Interpolation::Spline)) / 2e-6;
+        assert!(approx_eq!(f64, slope(10.0), 0.0, epsilon = 1e-4));
+
+        // two keyframes make a straight line
+        let keys = [ (0.0, 2.0), (4.0, 6.0) ];
+        assert!(approx_eq!(f64, sample(&keys, 1.0, Interpolation::Spline), 3.0, epsilon = 1e-9));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Interpolation::from_name("spline"), Some(Interpolation::Spline));
+        assert_eq!(Interpolation::from_name("cubic"), None);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::linalg::Float;
+use crate::world::HitRecord;
+
+/// Arbitrary output variables: auxiliary render passes recorded from the
+/// primary ray hit alongside the beauty image. Values are stored as-is
+/// (e.g. normals in [-1,1], raw IDs), so float formats are the best fit.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Aov {
+    /// See `Camera::depth`; infinite where nothing was hit
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId
+}
+
+impl Aov {
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "depth"      => Some(Aov::Depth),
+            "normal"     => Some(Aov::Normal),
+            "albedo"     => Some(Aov::Albedo),
+            "objectid"   => Some(Aov::ObjectId),
+            "materialid" => Some(Aov::MaterialId),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth      => "depth",
+            Aov::Normal     => "normal",
+            Aov::Albedo     => "albedo",
+            Aov::ObjectId   => "objectid",
+            Aov::MaterialId => "materialid"
+        }
+    }
+
+    /// The pass value for a primary ray of `camera`. Rays that miss
+    /// produce black, except in the depth pass, where they are infinitely
+    /// far away.
+    pub fn value(&self, camera: &Camera, hit: Option<&HitRecord>) -> Color {
+        let hit = match hit {
+            Some(h) => h,
+            None if *self == Aov::Depth => return Color::new(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            None => return Color::BLACK
+        };
+
+        match self {
+            Aov::Depth => {
+                let z = camera.depth(hit);
+                Color::new(z, z, z)
+            },
+            Aov::Normal => Color::from(hit.normal),
+            Aov::Albedo => hit.albedo,
+            Aov::ObjectId => {
+                let id = hit.object_id as Float;
+                Color::new(id, id, id)
+            },
+            Aov::MaterialId => {
+                let id = hit.material_id as Float;
+                Color::new(id, id, id)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Projection;
+    use crate::linalg::V4;
+
+    #[test]
+    fn values() {
+        // looking down -z from the origin
+        let camera = Camera::new_default(10, 10);
+
+        let hit = HitRecord {
+            distance: Float::sqrt(5.0),
+            point: V4::new_point(1.0, 0.0, -2.0),
+            normal: V4::new_vector(0.0, 1.0, 0.0),
+            albedo: Color::RED,
+            object_id: 3,
+            material_id: 7
+        };
+
+        assert_eq!(Aov::Depth.value(&camera, Some(&hit)), Color::new(2.0, 2.0, 2.0));
+        assert_eq!(Aov::Normal.value(&camera, Some(&hit)), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(Aov::Albedo.value(&camera, Some(&hit)), Color::RED);
+        assert_eq!(Aov::ObjectId.value(&camera, Some(&hit)), Color::new(3.0, 3.0, 3.0));
+        assert_eq!(Aov::MaterialId.value(&camera, Some(&hit)), Color::new(7.0, 7.0, 7.0));
+
+        assert_eq!(Aov::Depth.value(&camera, None), Color::new(Float::INFINITY, Float::INFINITY, Float::INFINITY));
+        assert_eq!(Aov::Normal.value(&camera, None), Color::BLACK);
+    }
+
+    #[test]
+    fn panorama_depth() {
+        let mut camera = Camera::new_default(20, 10);
+        camera.set_projection(Projection::Equirectangular);
+
+        let hit = HitRecord {
+            distance: 2.5,
+            point: V4::new_point(0.0, 0.0, 2.5),
+            normal: V4::new_vector(0.0, 0.0, -1.0),
+            albedo: Color::RED,
+            object_id: 1,
+            material_id: 1
+        };
+
+        // behind the camera, but still 2.5 away
+        assert_eq!(Aov::Depth.value(&camera, Some(&hit)), Color::new(2.5, 2.5, 2.5));
+    }
+
+    #[test]
+    fn names() {
+        for aov in [ Aov::Depth, Aov::Normal, Aov::Albedo, Aov::ObjectId, Aov::MaterialId ].iter() {
+            assert_eq!(Aov::from_name(aov.name()), Some(*aov));
+        }
+
+        assert_eq!(Aov::from_name("beauty"), None);
+    }
+}
@@ -0,0 +1,673 @@
+use crate::linalg::Float;
+use crate::mesh;
+use crate::sceneparser::ParseError;
+
+use yaml_rust::{yaml,Yaml};
+
+use std::error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+
+// Material values the book uses where a scene doesn't give any.
+const MATERIAL_DEFAULTS: &[(&str, Float)] = &[
+    ("ambient", 0.1), ("diffuse", 0.9), ("specular", 0.9), ("shininess", 200.0)
+];
+
+// Book material keys and ours.
+const MATERIAL_KEYS: &[(&str, &str)] = &[
+    ("ambient", "ambient"), ("diffuse", "diffuse"), ("specular", "specular"), ("shininess", "shininess"),
+    ("reflective", "reflective"), ("transparency", "transparency"), ("refractive-index", "refractive_index")
+];
+
+const TYPE_NUMBER: &str = "number";
+const TYPE_V3: &str = "3 numbers";
+const TYPE_TRANSFORM: &str = "[ operation, arguments... ] or name of a transformation";
+
+fn key(s: &str) -> Yaml {
+    Yaml::String(String::from(s))
+}
+
+fn real(v: Float) -> Yaml {
+    Yaml::Real(format!("{:?}", v))
+}
+
+fn read_number(node: &Yaml) -> Result<Float> {
+    match node {
+        Yaml::Real(s) => Ok(s.parse::<Float>()?),
+        Yaml::Integer(i) => Ok(*i as Float),
+        Yaml::BadValue => Err(ParseError::Missing.into()),
+        _ => Err(ParseError::WrongType(TYPE_NUMBER).into())
+    }
+}
+
+fn number(node: &Yaml) -> Result<Yaml> {
+    Ok(real(read_number(node)?))
+}
+
+fn degrees(node: &Yaml) -> Result<Yaml> {
+    Ok(real(read_number(node)?.to_degrees()))
+}
+
+fn integer(node: &Yaml) -> Result<Yaml> {
+    match node {
+        Yaml::Integer(_) => Ok(node.clone()),
+        _ => Err(ParseError::WrongType("integer").into())
+    }
+}
+
+fn v3(node: &Yaml) -> Result<Yaml> {
+    match node {
+        Yaml::Array(v) if v.len() == 3 => {
+            let v = v.iter().map(number).collect::<Result<Vec<Yaml>>>()?;
+            Ok(Yaml::Array(v))
+        },
+        _ => Err(ParseError::WrongType(TYPE_V3).into())
+    }
+}
+
+/// Convert `node[name]` with `f`, if it is there.
+fn convert_opt(node: &Yaml, name: &'static str, f: fn(&Yaml) -> Result<Yaml>) -> Result<Option<Yaml>> {
+    match &node[name] {
+        Yaml::BadValue => Ok(None),
+        val => match f(val) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(ParseError::In(name, e).into())
+        }
+    }
+}
+
+fn convert(node: &Yaml, name: &'static str, f: fn(&Yaml) -> Result<Yaml>) -> Result<Yaml> {
+    match convert_opt(node, name, f)? {
+        Some(v) => Ok(v),
+        None => Err(ParseError::MissingElem(name).into())
+    }
+}
+
+fn convert_camera(node: &Yaml) -> Result<Yaml> {
+    let mut camera = yaml::Hash::new();
+
+    camera.insert(key("width"), convert(node, "width", integer)?);
+    camera.insert(key("height"), convert(node, "height", integer)?);
+    camera.insert(key("field_of_view"), convert(node, "field-of-view", degrees)?);
+    camera.insert(key("from"), convert(node, "from", v3)?);
+    camera.insert(key("to"), convert(node, "to", v3)?);
+
+    if let Some(up) = convert_opt(node, "up", v3)? {
+        camera.insert(key("up"), up);
+    }
+
+    Ok(Yaml::Hash(camera))
+}
+
+fn convert_light(node: &Yaml) -> Result<Yaml> {
+    let mut light = yaml::Hash::new();
+
+    light.insert(key("position"), convert(node, "at", v3)?);
+
+    if let Some(intensity) = convert_opt(node, "intensity", v3)? {
+        light.insert(key("intensity"), intensity);
+    }
+
+    let mut point = yaml::Hash::new();
+    point.insert(key("point"), Yaml::Hash(light));
+
+    Ok(Yaml::Hash(point))
+}
+
+/// The book applies transformations in list order, we compose them right
+/// to left, so the list is reversed. Names refer to defined lists.
+fn convert_transforms(node: &Yaml) -> Result<Yaml> {
+    let list = match node {
+        Yaml::Array(v) => v,
+        _ => return Err(ParseError::WrongType("array").into())
+    };
+
+    let mut out = Vec::new();
+
+    for item in list.iter().rev() {
+        let op = match item {
+            Yaml::String(name) => {
+                out.push(Yaml::String(format!(".xf.{}", name)));
+                continue
+            },
+            Yaml::Array(op) if !op.is_empty() => op,
+            _ => return Err(ParseError::WrongType(TYPE_TRANSFORM).into())
+        };
+
+        let name = match op[0].as_str() {
+            Some(s) => s,
+            None => return Err(ParseError::WrongType(TYPE_TRANSFORM).into())
+        };
+
+        let args = op[1..].iter().map(read_number).collect::<Result<Vec<Float>>>()?;
+        let values = || Yaml::Array(args.iter().map(|&a| real(a)).collect());
+
+        let (ours, val) = match (name, args.len()) {
+            ("translate", 3) => ("translate", values()),
+            ("scale", 3) => ("scale", values()),
+            ("rotate-x", 1) => ("rotate_x", real(args[0].to_degrees())),
+            ("rotate-y", 1) => ("rotate_y", real(args[0].to_degrees())),
+            ("rotate-z", 1) => ("rotate_z", real(args[0].to_degrees())),
+            ("shear", 6) => ("shear", values()),
+            _ => return Err(ParseError::UnknownValue(format!("{} with {} arguments", name, args.len())).into())
+        };
+
+        let mut t = yaml::Hash::new();
+        t.insert(key(ours), val);
+        out.push(Yaml::Hash(t));
+    }
+
+    Ok(Yaml::Array(out))
+}
+
+/// `blended` patterns take a list of two `patterns` instead of `colors`.
+fn convert_pattern(node: &Yaml) -> Result<Yaml> {
+    let kind = match &node["type"] {
+        Yaml::String(s) => match s.as_str() {
+            "stripes" => "stripes",
+            "checkers" => "checkerboard",
+            "rings" => "ring",
+            "gradient" => "gradient",
+            "blended" => "blended",
+            _ => return Err(ParseError::In("type", ParseError::UnknownValue(s.clone()).into()).into())
+        },
+        Yaml::BadValue => return Err(ParseError::MissingElem("type").into()),
+        _ => return Err(ParseError::WrongTypeFor("type", "string").into())
+    };
+
+    let (list, convert_item, type_list): (_, fn(&Yaml) -> Result<Yaml>, _) = match kind {
+        "blended" => ("patterns", convert_pattern, "2 patterns"),
+        _ => ("colors", v3, "2 colors")
+    };
+
+    let items = match &node[list] {
+        Yaml::Array(v) if v.len() == 2 => v,
+        Yaml::BadValue => return Err(ParseError::MissingElem(list).into()),
+        _ => return Err(ParseError::WrongTypeFor(list, type_list).into())
+    };
+
+    let mut pattern = yaml::Hash::new();
+
+    for (name, item) in [ "a", "b" ].iter().zip(items.iter()) {
+        match convert_item(item) {
+            Ok(c) => pattern.insert(key(name), c),
+            Err(e) => return Err(ParseError::In(list, e).into())
+        };
+    }
+
+    if let Some(t) = convert_opt(node, "transform", convert_transforms)? {
+        pattern.insert(key("transformations"), t);
+    }
+
+    let mut texture = yaml::Hash::new();
+    texture.insert(key(kind), Yaml::Hash(pattern));
+
+    Ok(Yaml::Hash(texture))
+}
+
+/// Materials that don't extend another one get the book's defaults for
+/// anything they leave out.
+fn convert_material(node: &Yaml, defaults: bool) -> Result<yaml::Hash> {
+    let mut mat = yaml::Hash::new();
+
+    if let Some(color) = convert_opt(node, "color", v3)? {
+        let mut texture = yaml::Hash::new();
+        texture.insert(key("color"), color);
+        mat.insert(key("texture"), Yaml::Hash(texture));
+    }
+    if let Some(pattern) = convert_opt(node, "pattern", convert_pattern)? {
+        mat.insert(key("texture"), pattern);
+    }
+
+    for (book, ours) in MATERIAL_KEYS.iter() {
+        if let Some(v) = convert_opt(node, book, number)? {
+            mat.insert(key(ours), v);
+        }
+    }
+
+    if defaults {
+        let texture = key("texture");
+
+        if !mat.contains_key(&texture) {
+            let mut white = yaml::Hash::new();
+            white.insert(key("color"), Yaml::Array(vec![ real(1.0), real(1.0), real(1.0) ]));
+            mat.insert(texture, Yaml::Hash(white));
+        }
+
+        for (name, val) in MATERIAL_DEFAULTS.iter() {
+            mat.entry(key(name)).or_insert_with(|| real(*val));
+        }
+    }
+
+    Ok(mat)
+}
+
+fn convert_material_ref(node: &Yaml) -> Result<Yaml> {
+    match node {
+        Yaml::String(name) => Ok(Yaml::String(format!(".mat.{}", name))),
+        Yaml::BadValue => Ok(Yaml::Hash(convert_material(&Yaml::BadValue, true)?)),
+        matnode => match convert_material(matnode, true) {
+            Ok(m) => Ok(Yaml::Hash(m)),
+            Err(e) => Err(ParseError::In("material", e).into())
+        }
+    }
+}
+
+/// The shape in `node`, or for groups and OBJ files, the shapes in them.
+/// Shapes in a group get the group's transformations after their own and
+/// its material if they have none. OBJ files are read relative to `dir`.
+fn convert_shapes(kind: &str, node: &Yaml, dir: &Path, doc: &mut yaml::Hash, out: &mut Vec<Yaml>) -> Result<()> {
+    let mut body = yaml::Hash::new();
+
+    let kind = match kind {
+        "sphere" | "cube" | "plane" => kind,
+        "cylinder" | "cone" => {
+            for name in [ "min", "max" ].iter() {
+                if let Some(v) = convert_opt(node, name, number)? {
+                    body.insert(key(name), v);
+                }
+            }
+
+            let closed = match &node["closed"] {
+                Yaml::Boolean(b) => *b,
+                Yaml::BadValue => false,
+                _ => return Err(ParseError::WrongTypeFor("closed", "boolean").into())
+            };
+
+            match (kind, closed) {
+                ("cylinder", true) => "cylinder",
+                ("cylinder", false) => "pipe",
+                (_, true) => "cone",
+                (_, false) => "open_cone"
+            }
+        },
+        "group" => return convert_group(node, dir, doc, out),
+        "obj" => return convert_obj(node, dir, doc, out),
+        _ => return Err(ParseError::UnknownValue(format!("shape {}", kind)).into())
+    };
+
+    body.insert(key("material"), convert_material_ref(&node["material"])?);
+
+    if let Some(t) = convert_opt(node, "transform", convert_transforms)? {
+        body.insert(key("transformations"), t);
+    }
+
+    // "shadow: false" has no equivalent and is ignored
+
+    let mut shape = yaml::Hash::new();
+    shape.insert(key(kind), Yaml::Hash(body));
+
+    out.push(Yaml::Hash(shape));
+
+    Ok(())
+}
+
+fn convert_group(node: &Yaml, dir: &Path, doc: &mut yaml::Hash, out: &mut Vec<Yaml>) -> Result<()> {
+    let children = match &node["children"] {
+        Yaml::Array(v) => v,
+        Yaml::BadValue => return Err(ParseError::MissingElem("children").into()),
+        _ => return Err(ParseError::WrongTypeFor("children", "array").into())
+    };
+
+    let (material, transform) = (key("material"), key("transform"));
+
+    for (i, child) in children.iter().enumerate() {
+        let res = match (&child["add"], child) {
+            (Yaml::String(kind), Yaml::Hash(kv)) => {
+                let mut kv = kv.clone();
+
+                if let Some(m) = node.as_hash().and_then(|h| h.get(&material)) {
+                    kv.entry(material.clone()).or_insert_with(|| m.clone());
+                }
+
+                // applied after the child's own, which for the book means
+                // later in the list
+                if let Yaml::Array(outer) = &node["transform"] {
+                    let mut list = kv.get(&transform).and_then(|t| t.as_vec()).cloned().unwrap_or_default();
+                    list.extend(outer.iter().cloned());
+                    kv.insert(transform.clone(), Yaml::Array(list));
+                }
+
+                convert_shapes(kind, &Yaml::Hash(kv), dir, doc, out)
+            },
+            _ => Err(ParseError::WrongType("add command").into())
+        };
+
+        if let Err(e) = res {
+            return Err(ParseError::In("children", ParseError::Item(i, e).into()).into())
+        }
+    }
+
+    Ok(())
+}
+
+/// The triangles of an OBJ file, sharing one material.
+fn convert_obj(node: &Yaml, dir: &Path, doc: &mut yaml::Hash, out: &mut Vec<Yaml>) -> Result<()> {
+    let file = match &node["file"] {
+        Yaml::String(s) => s,
+        Yaml::BadValue => return Err(ParseError::MissingElem("file").into()),
+        _ => return Err(ParseError::WrongTypeFor("file", "string").into())
+    };
+
+    let triangles = File::open(dir.join(file)).and_then(|f| mesh::parse_obj(&mut BufReader::new(f)));
+    let triangles = match triangles {
+        Ok(t) => t,
+        Err(e) => return Err(ParseError::In("file", format!("{}: {}", file, e).into()).into())
+    };
+
+    let material = match convert_material_ref(&node["material"])? {
+        Yaml::Hash(m) => {
+            let name = format!(".mat.#{}", doc.len());
+            doc.insert(key(&name), Yaml::Hash(m));
+            Yaml::String(name)
+        },
+        m => m
+    };
+
+    let transformations = convert_opt(node, "transform", convert_transforms)?;
+
+    for t in triangles {
+        let mut body = yaml::Hash::new();
+
+        for (name, p) in [ "p1", "p2", "p3" ].iter().zip(t.points().iter()) {
+            body.insert(key(name), Yaml::Array(vec![ real(p.x()), real(p.y()), real(p.z()) ]));
+        }
+
+        body.insert(key("material"), material.clone());
+
+        if let Some(t) = &transformations {
+            body.insert(key("transformations"), t.clone());
+        }
+
+        let mut shape = yaml::Hash::new();
+        shape.insert(key("triangle"), Yaml::Hash(body));
+        out.push(Yaml::Hash(shape));
+    }
+
+    Ok(())
+}
+
+fn convert_define(name: &str, node: &Yaml, doc: &mut yaml::Hash) -> Result<()> {
+    match &node["value"] {
+        Yaml::Array(_) => {
+            if !node["extend"].is_badvalue() {
+                return Err(ParseError::In("extend", "only materials can be extended".into()).into())
+            }
+
+            doc.insert(Yaml::String(format!(".xf.{}", name)), convert(node, "value", convert_transforms)?);
+        },
+        Yaml::Hash(_) => {
+            let base = match &node["extend"] {
+                Yaml::String(s) => Some(s),
+                Yaml::BadValue => None,
+                _ => return Err(ParseError::WrongTypeFor("extend", "string").into())
+            };
+
+            let mut mat = match convert_material(&node["value"], base.is_none()) {
+                Ok(m) => m,
+                Err(e) => return Err(ParseError::In("value", e).into())
+            };
+
+            if let Some(base) = base {
+                mat.insert(key("extend"), Yaml::String(format!(".mat.{}", base)));
+            }
+
+            doc.insert(Yaml::String(format!(".mat.{}", name)), Yaml::Hash(mat));
+        },
+        Yaml::BadValue => return Err(ParseError::MissingElem("value").into()),
+        _ => return Err(ParseError::WrongTypeFor("value", "material or transformation list").into())
+    }
+
+    Ok(())
+}
+
+/// Whether a parsed scene document is in the book's format.
+pub fn is_book_scene(doc: &Yaml) -> bool {
+    matches!(doc, Yaml::Array(_))
+}
+
+/// Translate a scene in the book's format (a list of `add:` and `define:`
+/// commands) into ours. Defined materials become ".mat.<name>", defined
+/// transformations ".xf.<name>". OBJ files are read relative to `dir`.
+pub fn convert_scene(commands: &[Yaml], dir: &Path) -> Result<yaml::Hash> {
+    let mut doc = yaml::Hash::new();
+    let mut lights = Vec::new();
+    let mut shapes = Vec::new();
+
+    for (i, item) in commands.iter().enumerate() {
+        let res = match (&item["add"], &item["define"]) {
+            (Yaml::String(kind), _) => match kind.as_str() {
+                "camera" => convert_camera(item).map(|c| { doc.insert(key("camera"), c); }),
+                "light" => convert_light(item).map(|l| lights.push(l)),
+                _ => convert_shapes(kind, item, dir, &mut doc, &mut shapes)
+            },
+            (_, Yaml::String(name)) => convert_define(name, item, &mut doc),
+            _ => Err(ParseError::WrongType("add or define command").into())
+        };
+
+        if let Err(e) = res {
+            return Err(ParseError::Item(i, e).into())
+        }
+    }
+
+    doc.insert(key("lights"), Yaml::Array(lights));
+    doc.insert(key("shapes"), Yaml::Array(shapes));
+
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::color::Color;
+    use crate::linalg::{Float,M4,V4};
+    use crate::material::Texture;
+    use crate::ray::Ray;
+    use crate::sceneparser::{load_yaml_scene,read_scene,read_yaml_scene_config};
+    use crate::shape::Primitive;
+
+    use float_cmp::*;
+
+    use std::fs;
+
+    const SCENE: &str = "
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1.0471975
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+
+- add: light
+  at: [ -10, 10, -10 ]
+  intensity: [ 1, 1, 1 ]
+
+- define: white-material
+  value:
+    color: [ 1, 1, 1 ]
+    diffuse: 0.7
+    reflective: 0.1
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [ 0.5, 0.8, 0.9 ]
+
+- define: standard-transform
+  value:
+    - [ scale, 2, 2, 2 ]
+    - [ translate, 1, 0, 0 ]
+
+- add: sphere
+  material: blue-material
+  transform:
+    - standard-transform
+
+- add: plane
+  material:
+    pattern:
+      type: checkers
+      colors:
+        - [ 1, 1, 1 ]
+        - [ 0, 0, 0 ]
+    specular: 0
+  transform:
+    - [ rotate-x, 1.5707963 ]
+    - [ translate, 0, 0, 10 ]
+
+- add: cylinder
+  min: 0
+  max: 1
+  closed: true
+  shadow: false
+  transform:
+    - [ shear, 1, 0, 0, 0, 0, 0 ]
+";
+
+    #[test]
+    fn convert_book_scene() {
+        let (camera, world) = read_yaml_scene_config(SCENE).unwrap();
+
+        assert_eq!(camera.width(), 40);
+
+        // field of view in radians, ours in degrees
+        let reference = Camera::new(40, 20, Float::to_radians(60.0), &M4::identity());
+        let dir = camera.debug_pixel(&world, 0, 0).unwrap().ray.direction;
+        let refdir = reference.debug_pixel(&world, 0, 0).unwrap().ray.direction;
+        assert!(approx_eq!(Float, dir.x(), -refdir.x(), epsilon = 0.0001));
+        assert!(approx_eq!(Float, dir.y(), refdir.y(), epsilon = 0.0001));
+
+        assert_eq!(world.lights().len(), 1);
+        assert_eq!(world.shapes().len(), 3);
+
+        let sphere = &world.shapes()[0];
+        let mat = sphere.material();
+
+        match mat.texture {
+            Texture::Color(c) => assert_eq!(c.b, 0.9),
+            _ => panic!("material texture is not a color")
+        }
+        assert_eq!(mat.diffuse, 0.7);
+        assert_eq!(mat.reflective, 0.1);
+        assert_eq!(mat.shininess, 200.0);
+
+        // scaled first, then moved: spans x = -1 .. 3
+        let r = Ray::new(V4::new_point(-5.0, 0.0, 0.0), V4::new_vector(1.0, 0.0, 0.0));
+        let xs = sphere.intersect(&r);
+        assert!(approx_eq!(Float, xs[0], 4.0, epsilon = 0.0001));
+        assert!(approx_eq!(Float, xs[1], 8.0, epsilon = 0.0001));
+
+        // a wall 10 units behind the origin
+        let plane = &world.shapes()[1];
+        let r = Ray::new(V4::new_point(0.0, 0.0, 0.0), V4::new_vector(0.0, 0.0, 1.0));
+        assert!(approx_eq!(Float, plane.intersect(&r)[0], 10.0, epsilon = 0.0001));
+        assert_eq!(plane.material().specular, 0.0);
+        assert_eq!(plane.material().ambient, 0.1);
+
+        // sheared so that x moves with y: halfway up, the axis is at x = 0.5
+        let cylinder = &world.shapes()[2];
+        let r = Ray::new(V4::new_point(-5.0, 0.5, 0.0), V4::new_vector(1.0, 0.0, 0.0));
+        assert!(approx_eq!(Float, cylinder.intersect(&r)[0], 4.5, epsilon = 0.0001));
+    }
+
+    const BONUS: &str = "
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1.0471975
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+
+- add: cone
+  min: -1
+  max: 0
+  closed: true
+  material:
+    pattern:
+      type: blended
+      patterns:
+        - type: gradient
+          colors: [ [ 1, 1, 1 ], [ 0, 0, 0 ] ]
+        - type: stripes
+          colors: [ [ 1, 0, 0 ], [ 0, 0, 1 ] ]
+          transform:
+            - [ scale, 0.5, 0.5, 0.5 ]
+
+- add: group
+  material:
+    color: [ 1, 0, 0 ]
+  transform:
+    - [ translate, 0, 0, 5 ]
+  children:
+    - add: sphere
+      transform:
+        - [ scale, 2, 2, 2 ]
+    - add: group
+      children:
+        - add: cube
+          material:
+            color: [ 0, 1, 0 ]
+
+- add: obj
+  file: square.obj
+  transform:
+    - [ translate, 0, 0, -3 ]
+";
+
+    #[test]
+    fn bonus_scene() {
+        let dir = std::env::temp_dir().join(format!("rt-bonus-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("square.obj"), "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n").unwrap();
+
+        let (_, world) = read_scene(&load_yaml_scene(BONUS, &dir).unwrap()).unwrap();
+
+        assert_eq!(world.shapes().len(), 5);
+
+        let cone = &world.shapes()[0];
+        assert_eq!(cone.primitive(), Primitive::Cone { min: -1.0, max: 0.0, closed: true });
+        // half of the white end of the gradient and half of a red stripe
+        assert_eq!(cone.material().color_at(V4::new_point(0.0, 0.0, 0.0)), Color::new(1.0, 0.5, 0.5));
+
+        // moved along with the group, scaled on its own
+        let sphere = &world.shapes()[1];
+        let r = Ray::new(V4::new_point(0.0, 0.0, 0.0), V4::new_vector(0.0, 0.0, 1.0));
+        assert!(approx_eq!(Float, sphere.intersect(&r)[0], 3.0, epsilon = 0.0001));
+        assert_eq!(sphere.material().color_at(V4::new_point(0.0, 0.0, 0.0)), Color::RED);
+
+        let cube = &world.shapes()[2];
+        assert!(approx_eq!(Float, cube.intersect(&r)[0], 4.0, epsilon = 0.0001));
+        assert_eq!(cube.material().color_at(V4::new_point(0.0, 0.0, 0.0)), Color::new(0.0, 1.0, 0.0));
+
+        // the square is split in two triangles
+        for (t, p) in world.shapes()[3..].iter().zip([ (0.5, -0.5), (-0.5, 0.5) ].iter()) {
+            let r = Ray::new(V4::new_point(p.0, p.1, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+            assert_eq!(t.intersect(&r), [ 2.0 ]);
+            assert_eq!(t.material_id(), world.shapes()[3].material_id());
+        }
+
+        let e = read_yaml_scene_config("- add: obj\n  file: missing.obj\n").err().unwrap();
+        assert!(e.to_string().starts_with("In item 1: In file: missing.obj: "), "{}", e);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let e = read_yaml_scene_config("- add: torus\n").err().unwrap();
+        assert_eq!(e.to_string(), "In item 1: unknown value shape torus");
+
+        let e = read_yaml_scene_config("- add: group\n  children:\n    - add: sphere\n    - add: torus\n").err().unwrap();
+        assert_eq!(e.to_string(), "In item 1: In children: In item 2: unknown value shape torus");
+
+        let e = read_yaml_scene_config("- add: light\n  at: [ 0, 0, 0 ]\n- add: light\n  at: [ 1, 2 ]\n").err().unwrap();
+        assert_eq!(e.to_string(), "In item 2: In at: expected 3 numbers");
+
+        let e = read_yaml_scene_config("- add: sphere\n  transform:\n    - [ shear, 1, 0, 0 ]\n").err().unwrap();
+        assert_eq!(e.to_string(), "In item 1: In transform: unknown value shear with 3 arguments");
+    }
+}
@@ -0,0 +1,714 @@
+//! Putting scenes together in code.
+//!
+//! The builders mirror the YAML scene format: `SceneBuilder` takes a camera,
+//! lights, named materials and shapes, and `build()` produces the same
+//! `Camera` and `World` the scene parser would. Transformation methods
+//! compose like `Transform` and like transformation lists in scene files:
+//! `.translate(..).scale(..)` scales first, then translates.
+
+use crate::camera::{Camera,Projection,Stereo};
+use crate::color::Color;
+use crate::cone::Cone;
+use crate::cube::Cube;
+use crate::cylinder::Cylinder;
+use crate::framebuffer::Rect;
+use crate::lighting::LightSource;
+use crate::linalg::{Float,M4,V4};
+use crate::material::{Material,Texture};
+use crate::mesh::Triangle;
+use crate::pattern::{Blended,Checkerboard,Gradient,Pattern,Ring,Stripes,TransformedPattern};
+use crate::plane::Plane;
+use crate::shape::{BaseShape,Shape};
+use crate::sphere::Sphere;
+use crate::tonemap::ToneMap;
+use crate::transform::Transform;
+use crate::world::World;
+
+use std::error;
+use std::fmt;
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+
+#[derive(Debug)]
+pub enum BuildError {
+    NoCamera,
+    EmptyImage,
+    CropOutside,
+    ShutterReversed,
+    StereoProjection,
+    UndefinedMaterial(String)
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::NoCamera
+                => f.write_str("no camera"),
+            BuildError::EmptyImage
+                => f.write_str("image width and height must be positive"),
+            BuildError::CropOutside
+                => f.write_str("crop window outside the image"),
+            BuildError::ShutterReversed
+                => f.write_str("shutter closes before it opens"),
+            BuildError::StereoProjection
+                => f.write_str("stereo needs a perspective or equirectangular projection"),
+            BuildError::UndefinedMaterial(s)
+                => f.write_fmt(format_args!("material {} is not defined", s))
+        }
+    }
+}
+
+impl error::Error for BuildError {}
+
+/// Transformation methods shared by the shape and pattern builders. Each
+/// call is applied before the ones already given.
+pub trait Transformable: Sized {
+    /// Append the transformation matrix `m`.
+    fn transform(self, m: &M4) -> Self;
+
+    fn translate(self, x: Float, y: Float, z: Float) -> Self {
+        self.transform(&Transform::new().translate(x, y, z).matrix)
+    }
+
+    fn scale(self, x: Float, y: Float, z: Float) -> Self {
+        self.transform(&Transform::new().scale(x, y, z).matrix)
+    }
+
+    /// Rotation around the x axis, in radians.
+    fn rotate_x(self, rad: Float) -> Self {
+        self.transform(&Transform::new().rotate_x(rad).matrix)
+    }
+
+    /// Rotation around the y axis, in radians.
+    fn rotate_y(self, rad: Float) -> Self {
+        self.transform(&Transform::new().rotate_y(rad).matrix)
+    }
+
+    /// Rotation around the z axis, in radians.
+    fn rotate_z(self, rad: Float) -> Self {
+        self.transform(&Transform::new().rotate_z(rad).matrix)
+    }
+
+    /// Rotation around an arbitrary axis, in radians.
+    fn rotate(self, axis: V4, rad: Float) -> Self {
+        self.transform(&Transform::new().rotate(&axis, rad).matrix)
+    }
+
+    fn shear(self, xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> Self {
+        self.transform(&Transform::new().shear(xy, xz, yx, yz, zx, zy).matrix)
+    }
+
+    /// Move to `from` and turn the -z axis towards `to`.
+    fn look_at(self, from: V4, to: V4, up: V4) -> Self {
+        self.transform(&Transform::new().look_at(&from, &to, &up).matrix)
+    }
+}
+
+#[derive(Clone,Copy,Debug)]
+pub struct CameraBuilder {
+    width: usize,
+    height: usize,
+    field_of_view: Float,
+    from: V4,
+    to: V4,
+    up: V4,
+    tonemap: ToneMap,
+    crop: Option<Rect>,
+    shutter: (Float,Float),
+    projection: Projection,
+    stereo: Option<Stereo>
+}
+
+impl CameraBuilder {
+    /// A camera at the origin looking down -z, with a 90° field of view.
+    pub fn new(width: usize, height: usize) -> CameraBuilder {
+        CameraBuilder {
+            width,
+            height,
+            field_of_view: 90.0,
+            from: V4::new_point(0.0, 0.0, 0.0),
+            to: V4::new_point(0.0, 0.0, -1.0),
+            up: V4::new_vector(0.0, 1.0, 0.0),
+            tonemap: ToneMap::new(),
+            crop: None,
+            shutter: (0.0, 0.0),
+            projection: Projection::Perspective,
+            stereo: None
+        }
+    }
+
+    /// Horizontal field of view, in degrees like in scene files.
+    pub fn field_of_view(mut self, degrees: Float) -> CameraBuilder {
+        self.field_of_view = degrees;
+        self
+    }
+
+    pub fn look_at(mut self, from: V4, to: V4, up: V4) -> CameraBuilder {
+        self.from = from;
+        self.to = to;
+        self.up = up;
+        self
+    }
+
+    pub fn tonemap(mut self, tonemap: ToneMap) -> CameraBuilder {
+        self.tonemap = tonemap;
+        self
+    }
+
+    pub fn crop(mut self, crop: Rect) -> CameraBuilder {
+        self.crop = Some(crop);
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> CameraBuilder {
+        self.projection = projection;
+        self
+    }
+
+    /// Render both eyes' views into the image, see `Camera::set_stereo()`.
+    pub fn stereo(mut self, stereo: Stereo) -> CameraBuilder {
+        self.stereo = Some(stereo);
+        self
+    }
+
+    /// When the shutter opens and closes, in frames, for motion blur.
+    pub fn shutter(mut self, open: Float, close: Float) -> CameraBuilder {
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn build(&self) -> Result<Camera> {
+        if self.width == 0 || self.height == 0 {
+            return Err(BuildError::EmptyImage.into())
+        }
+        if let Some(crop) = &self.crop {
+            if !crop.fits(self.width, self.height) {
+                return Err(BuildError::CropOutside.into())
+            }
+        }
+        if self.shutter.1 < self.shutter.0 {
+            return Err(BuildError::ShutterReversed.into())
+        }
+        if self.stereo.is_some() && !matches!(self.projection, Projection::Perspective | Projection::Equirectangular) {
+            return Err(BuildError::StereoProjection.into())
+        }
+
+        let vt = Transform::view_transform(&self.from, &self.to, &self.up);
+
+        let mut camera = Camera::new(self.width, self.height, self.field_of_view.to_radians(), &vt.matrix);
+        camera.set_tonemap(self.tonemap);
+        camera.set_crop(self.crop);
+        camera.set_shutter(self.shutter.0, self.shutter.1);
+        camera.set_projection(self.projection);
+        camera.set_stereo(self.stereo);
+
+        Ok(camera)
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct PatternBuilder {
+    pattern: Rc<dyn Pattern>,
+    transform: Transform
+}
+
+impl PatternBuilder {
+    pub fn new<P: Pattern + 'static>(pattern: P) -> PatternBuilder {
+        PatternBuilder {
+            pattern: Rc::new(pattern),
+            transform: Transform::new()
+        }
+    }
+
+    pub fn stripes(a: Color, b: Color) -> PatternBuilder {
+        PatternBuilder::new(Stripes::new(a, b))
+    }
+
+    pub fn checkerboard(a: Color, b: Color) -> PatternBuilder {
+        PatternBuilder::new(Checkerboard::new(a, b))
+    }
+
+    pub fn ring(a: Color, b: Color) -> PatternBuilder {
+        PatternBuilder::new(Ring::new(a, b))
+    }
+
+    pub fn gradient(a: Color, b: Color) -> PatternBuilder {
+        PatternBuilder::new(Gradient::new(a, b))
+    }
+
+    /// The average of two patterns, each with its own transformations.
+    pub fn blended(a: &PatternBuilder, b: &PatternBuilder) -> PatternBuilder {
+        PatternBuilder::new(Blended::new(a.build(), b.build()))
+    }
+
+    pub fn build(&self) -> Rc<dyn Pattern> {
+        if self.transform.matrix == M4::identity() {
+            self.pattern.clone()
+        } else {
+            Rc::new(TransformedPattern::new_from_rc(self.pattern.clone(), &self.transform.matrix))
+        }
+    }
+}
+
+impl Transformable for PatternBuilder {
+    fn transform(mut self, m: &M4) -> PatternBuilder {
+        self.transform = Transform { matrix: M4::mmul(&self.transform.matrix, m) };
+        self
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct MaterialBuilder {
+    material: Material
+}
+
+impl MaterialBuilder {
+    /// The book's default material: white, ambient 0.1, diffuse 0.9,
+    /// specular 0.9, shininess 200.
+    pub fn new() -> MaterialBuilder {
+        MaterialBuilder {
+            material: Material {
+                texture: Texture::Color(Color::WHITE),
+                ambient: 0.1,
+                diffuse: 0.9,
+                specular: 0.9,
+                shininess: 200.0,
+                reflective: 0.0,
+                transparency: 0.0,
+                refractive_index: 1.0
+            }
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> MaterialBuilder {
+        self.material.texture = Texture::Color(color);
+        self
+    }
+
+    pub fn pattern(mut self, pattern: &PatternBuilder) -> MaterialBuilder {
+        self.material.texture = Texture::Pattern(pattern.build());
+        self
+    }
+
+    pub fn ambient(mut self, v: Float) -> MaterialBuilder {
+        self.material.ambient = v;
+        self
+    }
+
+    pub fn diffuse(mut self, v: Float) -> MaterialBuilder {
+        self.material.diffuse = v;
+        self
+    }
+
+    pub fn specular(mut self, v: Float) -> MaterialBuilder {
+        self.material.specular = v;
+        self
+    }
+
+    pub fn shininess(mut self, v: Float) -> MaterialBuilder {
+        self.material.shininess = v;
+        self
+    }
+
+    pub fn reflective(mut self, v: Float) -> MaterialBuilder {
+        self.material.reflective = v;
+        self
+    }
+
+    pub fn transparency(mut self, v: Float) -> MaterialBuilder {
+        self.material.transparency = v;
+        self
+    }
+
+    pub fn refractive_index(mut self, v: Float) -> MaterialBuilder {
+        self.material.refractive_index = v;
+        self
+    }
+
+    pub fn build(&self) -> Material {
+        self.material.clone()
+    }
+}
+
+impl Default for MaterialBuilder {
+    fn default() -> MaterialBuilder {
+        MaterialBuilder::new()
+    }
+}
+
+impl From<Material> for MaterialBuilder {
+    fn from(material: Material) -> MaterialBuilder {
+        MaterialBuilder { material }
+    }
+}
+
+enum MaterialRef {
+    Named(String),
+    Inline(Material)
+}
+
+pub struct ShapeBuilder {
+    base: Box<dyn BaseShape>,
+    transform: Transform,
+    material: MaterialRef,
+    motion: Vec<(Float,M4)>
+}
+
+impl ShapeBuilder {
+    /// A shape with the default material.
+    pub fn new(base: Box<dyn BaseShape>) -> ShapeBuilder {
+        ShapeBuilder {
+            base,
+            transform: Transform::new(),
+            material: MaterialRef::Inline(MaterialBuilder::new().build()),
+            motion: Vec::new()
+        }
+    }
+
+    pub fn sphere() -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Sphere()))
+    }
+
+    pub fn cube() -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Cube()))
+    }
+
+    pub fn plane() -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Plane()))
+    }
+
+    /// A cylinder with end caps.
+    pub fn cylinder(min: Float, max: Float) -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Cylinder::new_closed(min, max)))
+    }
+
+    /// A cylinder without end caps.
+    pub fn pipe(min: Float, max: Float) -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Cylinder::new_truncated(min, max)))
+    }
+
+    /// A double cone with end caps.
+    pub fn cone(min: Float, max: Float) -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Cone::new_closed(min, max)))
+    }
+
+    /// A double cone without end caps.
+    pub fn open_cone(min: Float, max: Float) -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Cone::new_truncated(min, max)))
+    }
+
+    pub fn triangle(p1: V4, p2: V4, p3: V4) -> ShapeBuilder {
+        ShapeBuilder::new(Box::new(Triangle::new(p1, p2, p3)))
+    }
+
+    pub fn material(mut self, material: &MaterialBuilder) -> ShapeBuilder {
+        self.material = MaterialRef::Inline(material.build());
+        self
+    }
+
+    /// Use a material defined with `SceneBuilder::material()`.
+    pub fn named_material(mut self, name: &str) -> ShapeBuilder {
+        self.material = MaterialRef::Named(String::from(name));
+        self
+    }
+
+    /// Make the shape move while the shutter is open: its whole
+    /// transformation at each of the given times, see `Shape::with_motion()`.
+    pub fn motion(mut self, keys: &[(Float,M4)]) -> ShapeBuilder {
+        self.motion = keys.to_vec();
+        self
+    }
+}
+
+impl Transformable for ShapeBuilder {
+    fn transform(mut self, m: &M4) -> ShapeBuilder {
+        self.transform = Transform { matrix: M4::mmul(&self.transform.matrix, m) };
+        self
+    }
+}
+
+/// A whole scene.
+///
+/// ```
+/// use raytracer_challenge::{Color,SceneBuilder,V4};
+/// use raytracer_challenge::builder::{CameraBuilder,MaterialBuilder,ShapeBuilder,Transformable};
+///
+/// let (camera, world) = SceneBuilder::new()
+///     .camera(CameraBuilder::new(40, 20)
+///         .field_of_view(60.0)
+///         .look_at(V4::new_point(0.0, 1.5, -5.0), V4::new_point(0.0, 1.0, 0.0), V4::new_vector(0.0, 1.0, 0.0)))
+///     .light(V4::new_point(-10.0, 10.0, -10.0), Color::WHITE)
+///     .material("red", MaterialBuilder::new().color(Color::RED).specular(0.3))
+///     .shape(ShapeBuilder::plane())
+///     .shape(ShapeBuilder::sphere().named_material("red").translate(0.0, 1.0, 0.0))
+///     .build()
+///     .unwrap();
+///
+/// let image = camera.render(&world);
+/// assert_eq!(image.width, 40);
+/// ```
+#[derive(Default)]
+pub struct SceneBuilder {
+    camera: Option<CameraBuilder>,
+    lights: Vec<LightSource>,
+    materials: Vec<(String,Material)>,
+    shapes: Vec<ShapeBuilder>
+}
+
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    pub fn camera(mut self, camera: CameraBuilder) -> SceneBuilder {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn light(mut self, position: V4, intensity: Color) -> SceneBuilder {
+        self.lights.push(LightSource { pos: position, intensity });
+        self
+    }
+
+    /// Define (or redefine) a named material that shapes can refer to.
+    pub fn material(mut self, name: &str, material: MaterialBuilder) -> SceneBuilder {
+        let material = material.build();
+
+        match self.materials.iter_mut().find(|(n, _)| n == name) {
+            Some(entry) => entry.1 = material,
+            None => self.materials.push((String::from(name), material))
+        }
+
+        self
+    }
+
+    pub fn shape(mut self, shape: ShapeBuilder) -> SceneBuilder {
+        self.shapes.push(shape);
+        self
+    }
+
+    /// The world without a camera. Material IDs are assigned like for scene
+    /// files: one per named material in use, one per inline material.
+    pub fn build_world(self) -> Result<World> {
+        let mut world = World::new();
+        let mut used: Vec<Option<&str>> = Vec::new();
+
+        for light in self.lights.iter() {
+            world.add_light(light);
+        }
+
+        for shape in self.shapes.into_iter() {
+            let (material, name) = match &shape.material {
+                MaterialRef::Inline(m) => (m, None),
+                MaterialRef::Named(name) => match self.materials.iter().find(|(n, _)| n == name) {
+                    Some((n, m)) => (m, Some(n.as_str())),
+                    None => return Err(BuildError::UndefinedMaterial(name.clone()).into())
+                }
+            };
+
+            let id = match name.and_then(|name| used.iter().position(|n| *n == Some(name))) {
+                Some(p) => p + 1,
+                None => {
+                    used.push(name);
+                    used.len()
+                }
+            };
+
+            let obj = Shape::new(shape.base, material, &shape.transform.matrix)
+                .with_material_id(id as u32)
+                .with_motion(&shape.motion);
+            world.add_shape(Rc::new(obj));
+        }
+
+        Ok(world)
+    }
+
+    pub fn build(self) -> Result<(Camera,World)> {
+        let camera = match &self.camera {
+            Some(c) => c.build()?,
+            None => return Err(BuildError::NoCamera.into())
+        };
+
+        Ok((camera, self.build_world()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::camera::StereoLayout;
+    use crate::ray::Ray;
+    use crate::sceneparser;
+
+    use float_cmp::*;
+
+    const SCENE: &str =
+"
+camera:
+  width: 60
+  height: 40
+  field_of_view: 45.0
+  from: [ 0.0, 2.0, -6.0 ]
+  to: [ 0.0, 1.0, 0.0 ]
+lights:
+  - point:
+      position: [ -5.0, 8.0, -8.0 ]
+      intensity: [ 1.0, 0.9, 0.8 ]
+.mat.shiny:
+  texture:
+    color: [ 0.2, 0.4, 0.9 ]
+  ambient: 0.1
+  diffuse: 0.7
+  specular: 0.9
+  shininess: 200.0
+  reflective: 0.3
+shapes:
+  - plane:
+      material:
+        texture:
+          checkerboard:
+            a: [ 1.0, 1.0, 1.0 ]
+            b: [ 0.0, 0.0, 0.0 ]
+            transformations:
+              - scale: [ 0.5, 0.5, 0.5 ]
+        ambient: 0.1
+        diffuse: 0.9
+        specular: 0.9
+        shininess: 200.0
+  - sphere:
+      material: .mat.shiny
+      transformations:
+        - translate: [ 0.0, 1.0, 0.0 ]
+        - scale: [ 0.5, 1.0, 0.5 ]
+  - cylinder:
+      min: 0.0
+      max: 2.0
+      material: .mat.shiny
+      transformations:
+        - translate: [ 2.0, 0.0, 1.0 ]
+";
+
+    fn built() -> (Camera,World) {
+        SceneBuilder::new()
+            .camera(CameraBuilder::new(60, 40)
+                .field_of_view(45.0)
+                .look_at(V4::new_point(0.0, 2.0, -6.0), V4::new_point(0.0, 1.0, 0.0), V4::new_vector(0.0, 1.0, 0.0)))
+            .light(V4::new_point(-5.0, 8.0, -8.0), Color::new(1.0, 0.9, 0.8))
+            .material("shiny", MaterialBuilder::new()
+                .color(Color::new(0.2, 0.4, 0.9))
+                .diffuse(0.7)
+                .reflective(0.3))
+            .shape(ShapeBuilder::plane()
+                .material(&MaterialBuilder::new()
+                    .pattern(&PatternBuilder::checkerboard(Color::WHITE, Color::BLACK).scale(0.5, 0.5, 0.5))))
+            .shape(ShapeBuilder::sphere()
+                .named_material("shiny")
+                .translate(0.0, 1.0, 0.0)
+                .scale(0.5, 1.0, 0.5))
+            .shape(ShapeBuilder::cylinder(0.0, 2.0)
+                .named_material("shiny")
+                .translate(2.0, 0.0, 1.0))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn matches_scene_file() {
+        let (camera, world) = built();
+        let (pcamera, pworld) = sceneparser::read_yaml_scene_config(SCENE).unwrap();
+
+        assert_eq!(camera.transform(), pcamera.transform());
+        assert!(approx_eq!(Float, camera.field_of_view(), pcamera.field_of_view(), ulps = 2));
+
+        assert_eq!(world.lights().len(), 1);
+        assert_eq!(world.lights()[0].pos, pworld.lights()[0].pos);
+        assert_eq!(world.lights()[0].intensity, pworld.lights()[0].intensity);
+        assert_eq!(world.shapes().len(), pworld.shapes().len());
+
+        for (a, b) in world.shapes().iter().zip(pworld.shapes().iter()) {
+            assert_eq!(a.transform(), b.transform());
+            assert_eq!(a.primitive(), b.primitive());
+            assert_eq!(a.material_id(), b.material_id());
+        }
+
+        let fb = camera.render(&world);
+        let pfb = camera.render(&pworld);
+        for y in 0..fb.height {
+            for x in 0..fb.width {
+                assert_eq!(fb.at(x, y), pfb.at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn material_ids() {
+        let world = SceneBuilder::new()
+            .material("a", MaterialBuilder::new())
+            .material("b", MaterialBuilder::new().color(Color::RED))
+            .shape(ShapeBuilder::sphere().named_material("b"))
+            .shape(ShapeBuilder::sphere())
+            .shape(ShapeBuilder::sphere().named_material("b"))
+            .shape(ShapeBuilder::sphere().named_material("a"))
+            .build_world()
+            .unwrap();
+
+        let ids: Vec<u32> = world.shapes().iter().map(|s| s.material_id()).collect();
+        assert_eq!(ids, vec![ 1, 2, 1, 3 ]);
+    }
+
+    #[test]
+    fn transform_order() {
+        let world = SceneBuilder::new()
+            .shape(ShapeBuilder::sphere().translate(0.0, 0.0, 5.0).scale(2.0, 2.0, 2.0))
+            .build_world()
+            .unwrap();
+
+        let ray = Ray {
+            origin: V4::new_point(0.0, 0.0, -5.0),
+            direction: V4::new_vector(0.0, 0.0, 1.0),
+            time: 0.0
+        };
+        assert_eq!(world.shapes()[0].intersect(&ray), [ 8.0, 12.0 ]);
+    }
+
+    #[test]
+    fn motion() {
+        let keys = [ (0.0, M4::identity()), (1.0, Transform::new().translate(0.0, 0.0, 2.0).matrix) ];
+        let world = SceneBuilder::new()
+            .shape(ShapeBuilder::sphere().motion(&keys))
+            .build_world()
+            .unwrap();
+
+        let ray = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+
+        assert_eq!(world.shapes()[0].intersect(&ray.with_time(0.5)), [ 5.0, 7.0 ]);
+    }
+
+    #[test]
+    fn errors() {
+        let e = SceneBuilder::new().build().err().unwrap();
+        assert_eq!(e.to_string(), "no camera");
+
+        let e = SceneBuilder::new()
+            .camera(CameraBuilder::new(10, 10))
+            .shape(ShapeBuilder::cube().named_material("glass"))
+            .build()
+            .err().unwrap();
+        assert_eq!(e.to_string(), "material glass is not defined");
+
+        let e = CameraBuilder::new(10, 10).crop(Rect { x0: 5, y0: 0, x1: 12, y1: 4 }).build().unwrap_err();
+        assert_eq!(e.to_string(), "crop window outside the image");
+
+        let e = CameraBuilder::new(10, 10).shutter(0.5, 0.0).build().unwrap_err();
+        assert_eq!(e.to_string(), "shutter closes before it opens");
+
+        let stereo = Stereo { layout: StereoLayout::SideBySide, eye_distance: 0.1, convergence: None };
+        let e = CameraBuilder::new(10, 10).projection(Projection::Orthographic { width: 2.0 }).stereo(stereo).build().unwrap_err();
+        assert_eq!(e.to_string(), "stereo needs a perspective or equirectangular projection");
+        assert!(CameraBuilder::new(10, 10).stereo(stereo).build().is_ok());
+
+        assert!(CameraBuilder::new(0, 10).build().is_err());
+    }
+}
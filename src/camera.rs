@@ -1,78 +1,431 @@
-use crate::canvas::Canvas;
+use crate::aov::Aov;
 use crate::color::Color;
-use crate::linalg::{V4,M4};
+use crate::framebuffer::{FrameBuffer,Rect};
+use crate::linalg::{consts,Float,M4,V4};
 use crate::ray::Ray;
-use crate::world::World;
+use crate::raytree::RayNode;
+use crate::rng::Rng;
+use crate::tonemap::ToneMap;
+use crate::world::{HitRecord,World};
 
-use image;
+/// How a fisheye lens maps the angle from the view direction to the
+/// distance from the image center.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle
+    Equidistant,
+    /// Equal solid angles get equal image areas
+    Equisolid
+}
+
+impl FisheyeMapping {
+    pub fn from_name(name: &str) -> Option<FisheyeMapping> {
+        match name {
+            "equidistant" => Some(FisheyeMapping::Equidistant),
+            "equisolid"   => Some(FisheyeMapping::Equisolid),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FisheyeMapping::Equidistant => "equidistant",
+            FisheyeMapping::Equisolid   => "equisolid"
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel rays along the view direction, through a view `width`
+    /// units wide.
+    Orthographic { width: Float },
+    /// The field of view spans the longer image side and may be up to 360°.
+    Fisheye(FisheyeMapping),
+    /// A 360° by 180° panorama, longitude across and latitude down.
+    Equirectangular
+}
+
+impl Projection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective       => "perspective",
+            Projection::Orthographic {..} => "orthographic",
+            Projection::Fisheye(_)        => "fisheye",
+            Projection::Equirectangular   => "equirectangular"
+        }
+    }
+}
+
+/// How the two views of a stereo image are packed into it.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum StereoLayout {
+    /// Left eye on the left
+    SideBySide,
+    /// Left eye on top
+    OverUnder
+}
+
+impl StereoLayout {
+    pub fn from_name(name: &str) -> Option<StereoLayout> {
+        match name {
+            "side_by_side" => Some(StereoLayout::SideBySide),
+            "over_under"   => Some(StereoLayout::OverUnder),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side_by_side",
+            StereoLayout::OverUnder  => "over_under"
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    /// Distance between the eyes, in world units
+    pub eye_distance: Float,
+    /// Distance at which the views meet, so that things there appear at
+    /// screen depth; None for parallel views
+    pub convergence: Option<Float>
+}
+
+/// Half width and height of the image plane of a perspective view w by h
+/// pixels, at distance 1.
+fn half_size(w: Float, h: Float, fov: Float) -> (Float,Float) {
+    let hv = (fov / 2.0).tan();
+    let aspect = w / h;
+
+    let halfw = if aspect >= 1.0 { hv } else { hv * aspect };
+    let halfh = if aspect >= 1.0 { hv / aspect } else { hv };
+
+    (halfw, halfh)
+}
 
 #[derive(Clone,Copy,Debug)]
 pub struct Camera {
     width: usize,
     height: usize,
-    transform_i: M4,
-    half_width: f32,
-    half_height: f32,
-    pixel_size: f32
+    field_of_view: Float,
+    transform: M4,
+    /// The transposed inverse, see `M4::transpose_mul`
+    transform_i_t: M4,
+    half_width: Float,
+    half_height: Float,
+    pixel_size: Float,
+    tonemap: ToneMap,
+    crop: Option<Rect>,
+    shutter: (Float,Float),
+    projection: Projection,
+    stereo: Option<Stereo>
 }
 
 impl Camera {
-    pub fn new(hsize: usize, vsize: usize, fov: f32, transform: &M4) -> Camera {
-        let hv = (fov / 2.0).tan();
-        let aspect = (hsize as f32) / (vsize as f32);
-
-        let halfw = if aspect >= 1.0 { hv } else { hv * aspect };
-        let halfh = if aspect >= 1.0 { hv / aspect } else { hv };
+    pub fn new(hsize: usize, vsize: usize, fov: Float, transform: &M4) -> Camera {
+        let (halfw, halfh) = half_size(hsize as Float, vsize as Float, fov);
 
         Camera {
             width: hsize,
             height: vsize,
-            transform_i: transform.invert(),
+            field_of_view: fov,
+            transform: *transform,
+            transform_i_t: transform.invert().transpose(),
             half_width: halfw,
             half_height: halfh,
-            pixel_size: (halfw * 2.0) / (hsize as f32)
+            pixel_size: (halfw * 2.0) / (hsize as Float),
+            tonemap: ToneMap::new(),
+            crop: None,
+            shutter: (0.0, 0.0),
+            projection: Projection::Perspective,
+            stereo: None
         }
     }
 
     pub fn new_default(hsize: usize, vsize: usize) -> Camera {
         let trans = M4::identity();
 
-        Camera::new(hsize, vsize, std::f32::consts::FRAC_PI_2, &trans)
+        Camera::new(hsize, vsize, consts::FRAC_PI_2, &trans)
+    }
+
+    pub fn tonemap(&self) -> &ToneMap {
+        &self.tonemap
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: ToneMap) {
+        self.tonemap = tonemap
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection
     }
 
-    fn ray(&self, x: usize, y: usize) -> Ray {
-        let xoff = ((x as f32) + 0.5) * self.pixel_size;
-        let yoff = ((y as f32) + 0.5) * self.pixel_size;
+    pub fn stereo(&self) -> Option<Stereo> {
+        self.stereo
+    }
+
+    /// Render left- and right-eye views into one image, each on half of
+    /// it. Only perspective and equirectangular projections can do stereo.
+    pub fn set_stereo(&mut self, stereo: Option<Stereo>) {
+        assert!(stereo.is_none() || matches!(self.projection, Projection::Perspective | Projection::Equirectangular));
+
+        self.stereo = stereo;
+
+        let (w, h) = self.eye_size();
+        let (halfw, halfh) = half_size(w, h, self.field_of_view);
+
+        self.half_width = halfw;
+        self.half_height = halfh;
+        self.pixel_size = (halfw * 2.0) / w;
+    }
 
-        let wx = self.half_width - xoff;
-        let wy = self.half_height - yoff;
+    /// Size in pixels of the view of one eye, or of the whole image without
+    /// stereo.
+    pub fn eye_size(&self) -> (Float,Float) {
+        let (w, h) = (self.width as Float, self.height as Float);
 
-        let pxp = &self.transform_i * V4::new_point(wx, wy, -1.0);
-        let origin = &self.transform_i * V4::new_point(0.0, 0.0, 0.0);
+        match self.stereo.map(|s| s.layout) {
+            None => (w, h),
+            Some(StereoLayout::SideBySide) => (w / 2.0, h),
+            Some(StereoLayout::OverUnder) => (w, h / 2.0)
+        }
+    }
+
+    /// Field of view across the longer side of the view, in radians.
+    /// Orthographic and equirectangular projections don't use it.
+    pub fn field_of_view(&self) -> Float {
+        self.field_of_view
+    }
+
+    /// The view transformation.
+    pub fn transform(&self) -> &M4 {
+        &self.transform
+    }
+
+    /// Depth of a primary ray's hit, for the depth pass: how far in front
+    /// of the camera it is along the view direction, like a z buffer. The
+    /// fisheye and equirectangular projections have no single view
+    /// direction, so there it is the distance from the eye.
+    pub fn depth(&self, hit: &HitRecord) -> Float {
+        match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => -(self.transform * hit.point).z(),
+            Projection::Fisheye(_) | Projection::Equirectangular => hit.distance
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
 
-        Ray {
-            origin: origin,
-            direction: (pxp - origin).normalize()
+    /// Restrict rendering to a part of the image. The framing stays that
+    /// of the full image; the crop must fit into it.
+    pub fn set_crop(&mut self, crop: Option<Rect>) {
+        if let Some(rect) = &crop {
+            assert!(rect.fits(self.width, self.height));
         }
+
+        self.crop = crop
+    }
+
+    pub fn crop(&self) -> Option<Rect> {
+        self.crop
+    }
+
+    /// Set when the shutter opens and closes, in frames relative to the
+    /// rendered frame. Rays are cast at times in between, so that moving
+    /// shapes blur.
+    pub fn set_shutter(&mut self, open: Float, close: Float) {
+        assert!(open <= close);
+
+        self.shutter = (open, close)
+    }
+
+    pub fn shutter(&self) -> (Float,Float) {
+        self.shutter
+    }
+
+    /// The part of the image to render: the crop window or the whole image.
+    pub fn region(&self) -> Rect {
+        self.crop.unwrap_or(Rect { x0: 0, y0: 0, x1: self.width, y1: self.height })
     }
 
-    pub fn render_to_canvas(&self, world: &World) -> Canvas {
-        let mut canvas = Canvas::new(self.width, self.height, Color::BLACK);
+    fn ray(&self, x: usize, y: usize) -> Option<Ray> {
+        self.ray_at(x, y, 0.5, 0.5, 0.5)
+    }
+
+    /// Origin and a second point in camera space of the ray through the
+    /// point (px,py) of a w by h view, for the left (`eye` = 1) or right
+    /// (-1) eye, or without stereo (0). In camera space, the view is down -z
+    /// with +x to the left of the image. None outside the image circle of a
+    /// fisheye.
+    fn project(&self, px: Float, py: Float, w: Float, h: Float, eye: Float) -> Option<(V4,V4)> {
+        // pixels right of and above the center
+        let (u, v) = (px - w / 2.0, h / 2.0 - py);
+
+        // the left eye is to the left of the view direction, i.e. at +x
+        // straight ahead
+        let mut left = V4::new_vector(1.0, 0.0, 0.0);
+
+        let dir = match self.projection {
+            Projection::Perspective => {
+                let xoff = px * self.pixel_size;
+                let yoff = py * self.pixel_size;
+
+                // not normalized: converging views meet on a plane
+                V4::new_vector(self.half_width - xoff, self.half_height - yoff, -1.0)
+            },
+            Projection::Orthographic { width } => {
+                let scale = width / w;
+                let origin = V4::new_point(-u * scale, v * scale, 0.0);
+                return Some((origin, origin + V4::new_vector(0.0, 0.0, -1.0)))
+            },
+            Projection::Fisheye(mapping) => {
+                let r = (u*u + v*v).sqrt() / (w.max(h) / 2.0);
+                let half_fov = self.field_of_view / 2.0;
+
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => {
+                        let s = r * (half_fov / 2.0).sin();
+                        if s > 1.0 {
+                            return None
+                        }
+                        2.0 * s.asin()
+                    }
+                };
+
+                if theta > consts::PI {
+                    return None
+                }
+
+                let (sin, cos) = theta.sin_cos();
+                let len = (u*u + v*v).sqrt().max(Float::MIN_POSITIVE);
+
+                V4::new_vector(-u / len * sin, v / len * sin, -cos)
+            },
+            Projection::Equirectangular => {
+                let longitude = u / w * 2.0 * consts::PI;
+                let latitude = v / h * consts::PI;
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let ray = self.ray(x, y);
-                canvas.set(x, y, world.color_at(&ray));
+                // the eyes circle around the camera as it looks around
+                left = V4::new_vector(longitude.cos(), 0.0, -longitude.sin());
+
+                V4::new_vector(-longitude.sin() * latitude.cos(), latitude.sin(), -longitude.cos() * latitude.cos())
             }
+        };
+
+        let origin = V4::new_point(0.0, 0.0, 0.0);
+
+        match self.stereo {
+            Some(stereo) if eye != 0.0 => {
+                let offset = left * (eye * stereo.eye_distance / 2.0);
+
+                match stereo.convergence {
+                    Some(c) => Some((origin + offset, origin + dir * c)),
+                    None => Some((origin + offset, origin + offset + dir))
+                }
+            },
+            _ => Some((origin, origin + dir))
         }
+    }
 
-        canvas
+    /// Ray through the position (dx,dy) inside pixel (x,y), cast at the
+    /// point dt of the shutter interval, with dx, dy and dt in [0,1).
+    fn ray_at(&self, x: usize, y: usize, dx: Float, dy: Float, dt: Float) -> Option<Ray> {
+        let (mut px, mut py) = ((x as Float) + dx, (y as Float) + dy);
+        let (w, h) = self.eye_size();
+
+        // the left eye's view comes first
+        let eye = match self.stereo.map(|s| s.layout) {
+            None => 0.0,
+            Some(StereoLayout::SideBySide) if px < w => 1.0,
+            Some(StereoLayout::SideBySide) => { px -= w; -1.0 },
+            Some(StereoLayout::OverUnder) if py < h => 1.0,
+            Some(StereoLayout::OverUnder) => { py -= h; -1.0 }
+        };
+
+        let (origin, pxp) = self.project(px, py, w, h, eye)?;
+
+        let pxp = self.transform_i_t.transpose_mul(pxp);
+        let origin = self.transform_i_t.transpose_mul(origin);
+
+        Some(Ray {
+            origin,
+            direction: (pxp - origin).normalize(),
+            time: self.shutter.0 + (self.shutter.1 - self.shutter.0) * dt
+        })
     }
 
-    pub fn render(&self, world: &World) -> image::RgbImage {
-        image::RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
-                image::Rgb::from(world.color_at(&self.ray(x as usize, y as usize)))
-            })
+    pub fn render(&self, world: &World) -> FrameBuffer {
+        self.render_with_aovs(world, &[]).0
+    }
+
+    /// Ray tree of the center sample of pixel (x,y); None if no ray goes
+    /// through it.
+    pub fn debug_pixel(&self, world: &World, x: usize, y: usize) -> Option<RayNode> {
+        self.ray(x, y).map(|ray| world.debug_trace(&ray))
+    }
+
+    /// Render the beauty image plus one framebuffer per requested AOV.
+    /// Pixels outside the crop window are left black.
+    pub fn render_with_aovs(&self, world: &World, aovs: &[Aov]) -> (FrameBuffer, Vec<FrameBuffer>) {
+        let mut fb = FrameBuffer::new(self.width, self.height);
+        let mut aov_fbs = vec![FrameBuffer::new(self.width, self.height); aovs.len()];
+
+        self.render_rect(world, &self.region(), None, &mut fb, aovs, &mut aov_fbs);
+
+        (fb, aov_fbs)
+    }
+
+    /// Add one sample per pixel in `rect` to `fb`, which covers the whole
+    /// image. Without an RNG the sample goes through the pixel center in
+    /// the middle of the shutter interval, otherwise through a random
+    /// position inside the pixel at a random time. AOVs are
+    /// recorded if `aovs` is non-empty.
+    pub(crate) fn render_rect(&self,
+            world: &World,
+            rect: &Rect,
+            mut rng: Option<&mut Rng>,
+            fb: &mut FrameBuffer,
+            aovs: &[Aov],
+            aov_fbs: &mut [FrameBuffer])
+    {
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let ray = match rng.as_mut() {
+                    Some(rng) => {
+                        let (dx, dy) = (rng.next_f32() as Float, rng.next_f32() as Float);
+                        let dt = if self.shutter.0 < self.shutter.1 { rng.next_f32() as Float } else { 0.5 };
+
+                        self.ray_at(x, y, dx, dy, dt)
+                    },
+                    None => self.ray(x, y)
+                };
+
+                let (color, hit) = match ray {
+                    Some(ray) if aovs.is_empty() => (world.color_at(&ray), None),
+                    Some(ray) => world.trace(&ray),
+                    None => (Color::BLACK, None)
+                };
+
+                fb.add_sample(x, y, color);
+
+                for (aov, aov_fb) in aovs.iter().zip(aov_fbs.iter_mut()) {
+                    aov_fb.add_sample(x, y, aov.value(self, hit.as_ref()));
+                }
+            }
+        }
     }
 }
 
@@ -87,16 +440,16 @@ mod tests {
     #[test]
     fn pixelsize() {
         let c = Camera::new_default(200, 125);
-        assert_eq!(c.pixel_size, 0.01);
+        assert!(approx_eq!(Float, c.pixel_size, 0.01, ulps = 2));
 
         let c = Camera::new_default(125, 200);
-        assert_eq!(c.pixel_size, 0.01);
+        assert!(approx_eq!(Float, c.pixel_size, 0.01, ulps = 2));
     }
 
     #[test]
     fn ray_center() {
         let c = Camera::new_default(201, 101);
-        let r = c.ray(100, 50);
+        let r = c.ray(100, 50).unwrap();
 
         assert!(approx_eq!(V4, r.origin, V4::new_point(0.0, 0.0, 0.0)));
         assert!(approx_eq!(V4, r.direction, V4::new_vector(0.0, 0.0, -1.0)));
@@ -105,24 +458,176 @@ mod tests {
     #[test]
     fn ray_corner() {
         let c = Camera::new_default(201, 101);
-        let r = c.ray(0, 0);
+        let r = c.ray(0, 0).unwrap();
 
         assert!(approx_eq!(V4, r.origin, V4::new_point(0.0, 0.0, 0.0)));
         assert!(approx_eq!(V4, r.direction, V4::new_vector(0.66519, 0.33259, -0.66851), epsilon = 0.0001));
     }
 
+    #[test]
+    fn perspective_arithmetic() {
+        // renders depend on the exact rounding of the book's formula
+        let t = Transform::new().rotate_y(0.3).translate(1.0, -2.0, 5.0);
+        let c = Camera::new(160, 120, 1.1, &t.matrix);
+        let t_i = t.matrix.invert();
+
+        for &(x, y) in [ (0, 0), (17, 93), (159, 119) ].iter() {
+            let xoff = ((x as Float) + 0.5) * c.pixel_size;
+            let yoff = ((y as Float) + 0.5) * c.pixel_size;
+
+            let pxp = t_i * V4::new_point(c.half_width - xoff, c.half_height - yoff, -1.0);
+            let origin = t_i * V4::new_point(0.0, 0.0, 0.0);
+
+            let r = c.ray(x, y).unwrap();
+            assert_eq!(r.origin, origin);
+            assert_eq!(r.direction, (pxp - origin).normalize());
+        }
+    }
+
     #[test]
     fn ray_trans() {
         let t = Transform::new()
-            .rotate_y(std::f32::consts::FRAC_PI_4)
+            .rotate_y(consts::FRAC_PI_4)
             .translate(0.0, -2.0, 5.0);
 
-        let c = Camera::new(201, 101, std::f32::consts::FRAC_PI_2, &t.matrix);
-        let r = c.ray(100, 50);
+        let c = Camera::new(201, 101, consts::FRAC_PI_2, &t.matrix);
+        let r = c.ray(100, 50).unwrap();
 
-        let sq2half = 0.5 * std::f32::consts::SQRT_2;
+        let sq2half = 0.5 * consts::SQRT_2;
 
         assert!(approx_eq!(V4, r.origin, V4::new_point(0.0, 2.0, -5.0)));
         assert!(approx_eq!(V4, r.direction, V4::new_vector(sq2half, 0.0, -sq2half), epsilon = 0.0001));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn crop_keeps_framing() {
+        let mut c = Camera::new_default(201, 101);
+        let full = c.ray(10, 20).unwrap();
+
+        c.set_crop(Some(Rect { x0: 5, y0: 15, x1: 50, y1: 30 }));
+
+        assert_eq!(c.region(), Rect { x0: 5, y0: 15, x1: 50, y1: 30 });
+        assert!(approx_eq!(V4, c.ray(10, 20).unwrap().direction, full.direction));
+
+        c.set_crop(None);
+
+        assert_eq!(c.region(), Rect { x0: 0, y0: 0, x1: 201, y1: 101 });
+    }
+
+    #[test]
+    fn shutter() {
+        let mut c = Camera::new_default(201, 101);
+        assert_eq!(c.ray(10, 20).unwrap().time, 0.0);
+
+        c.set_shutter(-0.25, 0.75);
+
+        assert_eq!(c.ray(10, 20).unwrap().time, 0.25);
+        assert_eq!(c.ray_at(10, 20, 0.5, 0.5, 0.0).unwrap().time, -0.25);
+        assert!(approx_eq!(V4, c.ray_at(10, 20, 0.5, 0.5, 0.9).unwrap().direction, c.ray(10, 20).unwrap().direction));
+    }
+
+    #[test]
+    fn orthographic() {
+        let mut c = Camera::new_default(200, 100);
+        c.set_projection(Projection::Orthographic { width: 4.0 });
+
+        let r = c.ray_at(0, 0, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(V4, r.origin, V4::new_point(2.0, 1.0, 0.0)));
+        assert!(approx_eq!(V4, r.direction, V4::new_vector(0.0, 0.0, -1.0)));
+
+        let r = c.ray(100, 50).unwrap();
+        assert!(approx_eq!(V4, r.origin, V4::new_point(-0.01, -0.01, 0.0), epsilon = 0.0001));
+    }
+
+    #[test]
+    fn fisheye() {
+        let sq2half = 0.5 * consts::SQRT_2;
+
+        for mapping in [ FisheyeMapping::Equidistant, FisheyeMapping::Equisolid ].iter() {
+            let mut c = Camera::new(200, 100, consts::PI, &M4::identity());
+            c.set_projection(Projection::Fisheye(*mapping));
+
+            // the field of view spans the image width
+            let r = c.ray_at(100, 50, 0.0, 0.0, 0.0).unwrap();
+            assert!(approx_eq!(V4, r.direction, V4::new_vector(0.0, 0.0, -1.0)));
+            let r = c.ray_at(0, 50, 0.0, 0.0, 0.0).unwrap();
+            assert!(approx_eq!(V4, r.direction, V4::new_vector(1.0, 0.0, 0.0), epsilon = 0.0001));
+            let r = c.ray_at(100, 0, 0.0, 0.0, 0.0).unwrap();
+            assert!(r.direction.x() == 0.0 && r.direction.y() > 0.6);
+        }
+
+        // halfway out: 45° for equidistant, less for equisolid
+        let mut c = Camera::new(200, 100, consts::PI, &M4::identity());
+        c.set_projection(Projection::Fisheye(FisheyeMapping::Equidistant));
+        let r = c.ray_at(150, 50, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(V4, r.direction, V4::new_vector(-sq2half, 0.0, -sq2half), epsilon = 0.0001));
+
+        c.set_projection(Projection::Fisheye(FisheyeMapping::Equisolid));
+        let r = c.ray_at(150, 50, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(Float, r.direction.z(), -(1.0 - 2.0 * 0.125), epsilon = 0.0001));
+
+        // nothing outside the image circle
+        let mut c = Camera::new(100, 100, 2.0 * consts::PI, &M4::identity());
+        c.set_projection(Projection::Fisheye(FisheyeMapping::Equisolid));
+        assert!(c.ray(0, 0).is_none());
+        assert!(c.ray(50, 1).is_some());
+    }
+
+    #[test]
+    fn equirectangular() {
+        let mut c = Camera::new_default(200, 100);
+        c.set_projection(Projection::Equirectangular);
+
+        let dir = |x, y| c.ray_at(x, y, 0.0, 0.0, 0.0).unwrap().direction;
+
+        assert!(approx_eq!(V4, dir(100, 50), V4::new_vector(0.0, 0.0, -1.0)));
+        assert!(approx_eq!(V4, dir(150, 50), V4::new_vector(-1.0, 0.0, 0.0), epsilon = 0.0001));
+        assert!(approx_eq!(V4, dir(0, 50), V4::new_vector(0.0, 0.0, 1.0), epsilon = 0.0001));
+        assert!(approx_eq!(V4, dir(100, 0), V4::new_vector(0.0, 1.0, 0.0), epsilon = 0.0001));
+        let sq2half = 0.5 * consts::SQRT_2;
+        assert!(approx_eq!(V4, dir(100, 75), V4::new_vector(0.0, -sq2half, -sq2half), epsilon = 0.0001));
+    }
+
+    #[test]
+    fn stereo() {
+        let mut c = Camera::new_default(200, 100);
+        c.set_stereo(Some(Stereo { layout: StereoLayout::SideBySide, eye_distance: 0.2, convergence: None }));
+
+        assert_eq!(c.eye_size(), (100.0, 100.0));
+
+        // parallel views, left eye on the left
+        let l = c.ray_at(50, 50, 0.0, 0.0, 0.0).unwrap();
+        let r = c.ray_at(150, 50, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(V4, l.origin, V4::new_point(0.1, 0.0, 0.0)));
+        assert!(approx_eq!(V4, r.origin, V4::new_point(-0.1, 0.0, 0.0)));
+        assert!(approx_eq!(V4, l.direction, V4::new_vector(0.0, 0.0, -1.0)));
+        assert!(approx_eq!(V4, r.direction, V4::new_vector(0.0, 0.0, -1.0)));
+
+        // converging views meet at the convergence plane
+        c.set_stereo(Some(Stereo { layout: StereoLayout::SideBySide, eye_distance: 0.2, convergence: Some(2.0) }));
+
+        for (x, y) in [ (50, 50), (20, 70) ].iter() {
+            let l = c.ray_at(*x, *y, 0.0, 0.0, 0.0).unwrap();
+            let r = c.ray_at(*x + 100, *y, 0.0, 0.0, 0.0).unwrap();
+            let (tl, tr) = (-2.0 / l.direction.z(), -2.0 / r.direction.z());
+            assert!(approx_eq!(V4, l.position(tl), r.position(tr), epsilon = 0.0001));
+        }
+
+        // panoramas: the eyes turn with the view
+        let mut c = Camera::new_default(200, 200);
+        c.set_projection(Projection::Equirectangular);
+        c.set_stereo(Some(Stereo { layout: StereoLayout::OverUnder, eye_distance: 0.2, convergence: None }));
+
+        assert_eq!(c.eye_size(), (200.0, 100.0));
+
+        let l = c.ray_at(100, 50, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(V4, l.origin, V4::new_point(0.1, 0.0, 0.0)));
+        assert!(approx_eq!(V4, l.direction, V4::new_vector(0.0, 0.0, -1.0)));
+
+        let l = c.ray_at(150, 50, 0.0, 0.0, 0.0).unwrap();
+        let r = c.ray_at(150, 150, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(V4, l.origin, V4::new_point(0.0, 0.0, -0.1), epsilon = 0.0001));
+        assert!(approx_eq!(V4, r.origin, V4::new_point(0.0, 0.0, 0.1), epsilon = 0.0001));
+        assert!(approx_eq!(V4, r.direction, V4::new_vector(-1.0, 0.0, 0.0), epsilon = 0.0001));
+    }
+}
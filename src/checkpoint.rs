@@ -0,0 +1,206 @@
+use crate::aov::Aov;
+use crate::framebuffer::FrameBuffer;
+
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader,BufWriter,Read,Write};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+// The framebuffers are stored at the build's float precision, so f64
+// builds write a version of their own.
+#[cfg(not(feature = "f64"))]
+const VERSION: u32 = 2;
+#[cfg(feature = "f64")]
+const VERSION: u32 = 0x8002;
+
+/// Saved state of a progressive render: everything needed to continue it
+/// where it stopped.
+#[derive(Clone,Debug)]
+pub struct Checkpoint {
+    pub passes: u32,
+    pub row: usize,
+    pub rng_state: u64,
+    pub aovs: Vec<Aov>,
+    pub fb: FrameBuffer,
+    pub aov_fbs: Vec<FrameBuffer>
+}
+
+/// Fingerprint of the scene description, to refuse resuming a checkpoint
+/// with a different scene. This is 64-bit FNV-1a, which unlike std's
+/// hashers gives the same value with every toolchain.
+pub fn scene_hash(scene: &str) -> u64 {
+    scene.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+fn read_u32(input: &mut dyn Read) -> Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(input: &mut dyn Read) -> Result<u64> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+impl Checkpoint {
+    pub fn write(&self, scene_hash: u64, output: &mut dyn Write) -> Result<()> {
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+        output.write_all(&scene_hash.to_le_bytes())?;
+        output.write_all(&self.passes.to_le_bytes())?;
+        output.write_all(&(self.row as u64).to_le_bytes())?;
+        output.write_all(&self.rng_state.to_le_bytes())?;
+
+        output.write_all(&(self.aovs.len() as u32).to_le_bytes())?;
+        for aov in self.aovs.iter() {
+            let name = aov.name().as_bytes();
+            output.write_all(&(name.len() as u32).to_le_bytes())?;
+            output.write_all(name)?;
+        }
+
+        self.fb.write_raw(output)?;
+
+        for fb in self.aov_fbs.iter() {
+            fb.write_raw(output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a checkpoint of a render of `size` (width, height).
+    pub fn read(scene_hash: u64, size: (usize, usize), input: &mut dyn Read) -> Result<Checkpoint> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err("not a checkpoint file".into())
+        }
+        if read_u32(input)? != VERSION {
+            return Err("unsupported checkpoint version".into())
+        }
+        if read_u64(input)? != scene_hash {
+            return Err("checkpoint was made for a different scene".into())
+        }
+
+        let passes = read_u32(input)?;
+        let row = read_u64(input)? as usize;
+        let rng_state = read_u64(input)?;
+
+        let naovs = read_u32(input)?;
+        let mut aovs = Vec::new();
+
+        for _ in 0..naovs {
+            let len = read_u32(input)? as usize;
+
+            if len > 64 {
+                return Err("corrupt checkpoint: AOV name too long".into())
+            }
+
+            let mut name = vec![0; len];
+            input.read_exact(&mut name)?;
+
+            let name = String::from_utf8(name)?;
+
+            match Aov::from_name(&name) {
+                Some(aov) => aovs.push(aov),
+                None => return Err(format!("unknown AOV {} in checkpoint", name).into())
+            }
+        }
+
+        let (width, height) = size;
+        let fb = FrameBuffer::read_raw(input, width, height)?;
+        let mut aov_fbs = Vec::new();
+
+        for _ in 0..naovs {
+            aov_fbs.push(FrameBuffer::read_raw(input, width, height)?);
+        }
+
+        Ok(Checkpoint { passes, row, rng_state, aovs, fb, aov_fbs })
+    }
+
+    /// Write to a temporary file first and rename it, so an interrupted
+    /// save never destroys the previous checkpoint.
+    pub fn save(&self, scene_hash: u64, name: &str) -> Result<()> {
+        let tmp = format!("{}.tmp", name);
+
+        {
+            let mut output = BufWriter::new(File::create(&tmp)?);
+            self.write(scene_hash, &mut output)?;
+            output.flush()?;
+        }
+
+        fs::rename(&tmp, name)?;
+
+        Ok(())
+    }
+
+    pub fn load(scene_hash: u64, size: (usize, usize), name: &str) -> Result<Checkpoint> {
+        let mut input = BufReader::new(File::open(name)?);
+        Checkpoint::read(scene_hash, size, &mut input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn make_checkpoint() -> Checkpoint {
+        let mut fb = FrameBuffer::new(4, 3);
+        fb.add_sample(1, 2, Color::new(0.25, 0.5, 2.0));
+
+        let mut depth = FrameBuffer::new(4, 3);
+        depth.add_sample(3, 0, Color::new(7.0, 7.0, 7.0));
+
+        Checkpoint {
+            passes: 3,
+            row: 2,
+            rng_state: 0x1234_5678_9abc_def0,
+            aovs: vec![ Aov::Depth ],
+            fb,
+            aov_fbs: vec![ depth ]
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let cp = make_checkpoint();
+        let hash = scene_hash("camera: {}");
+
+        let mut buf = Vec::new();
+        cp.write(hash, &mut buf).unwrap();
+
+        let rt = Checkpoint::read(hash, (4, 3), &mut buf.as_slice()).unwrap();
+
+        assert_eq!(rt.passes, 3);
+        assert_eq!(rt.row, 2);
+        assert_eq!(rt.rng_state, cp.rng_state);
+        assert_eq!(rt.aovs, vec![ Aov::Depth ]);
+        assert_eq!(rt.fb.at(1, 2), Color::new(0.25, 0.5, 2.0));
+        assert_eq!(rt.aov_fbs[0].at(3, 0), Color::new(7.0, 7.0, 7.0));
+    }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(scene_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(scene_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(scene_hash("foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn wrong_scene() {
+        let cp = make_checkpoint();
+
+        let mut buf = Vec::new();
+        cp.write(scene_hash("a"), &mut buf).unwrap();
+
+        assert!(Checkpoint::read(scene_hash("b"), (4, 3), &mut buf.as_slice()).is_err());
+        assert!(Checkpoint::read(scene_hash("a"), (4, 3), &mut &buf[..20]).is_err());
+        assert!(Checkpoint::read(scene_hash("a"), (3, 4), &mut buf.as_slice()).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::linalg::V4;
+use crate::linalg::{Float,V4};
 
 use image::Rgb;
 use std::convert::From;
@@ -6,13 +6,13 @@ use std::convert::From;
 
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub struct Color {
-    pub r: f32,
-    pub g: f32,
-    pub b: f32
+    pub r: Float,
+    pub g: Float,
+    pub b: Float
 }
 
 impl Color {
-    pub fn new(r: f32, g: f32, b: f32) -> Color {
+    pub fn new(r: Float, g: Float, b: Float) -> Color {
         Color { r: r, g: g, b: b }
     }
 
@@ -35,7 +35,7 @@ impl From<Color> for V4 {
 
 impl From<Color> for Rgb<u8> {
     fn from(c: Color) -> Rgb<u8> {
-        fn to_u8(v: f32) -> u8 {
+        fn to_u8(v: Float) -> u8 {
             unsafe {
                 (v.max(0.0).min(1.0) * 255.0).to_int_unchecked::<u8>()
             }
@@ -0,0 +1,187 @@
+use crate::linalg::{EPSILON,Float,V4};
+use crate::ray::Ray;
+use crate::shape::{BaseShape,Hits,Primitive};
+
+/// A double cone around the y axis, with radius |y| at height y.
+pub struct Cone {
+    min: Float,
+    max: Float,
+    is_closed: bool
+}
+
+impl Cone {
+    pub fn new() -> Cone {
+        Cone { min: Float::MIN, max: Float::MAX, is_closed: false }
+    }
+
+    pub fn new_closed(min: Float, max: Float) -> Cone {
+        Cone { min, max, is_closed: true }
+    }
+
+    pub fn new_truncated(min: Float, max: Float) -> Cone {
+        Cone { min, max, is_closed: false }
+    }
+}
+
+impl Default for Cone {
+    fn default() -> Cone {
+        Cone::new()
+    }
+}
+
+fn check_cap(y: Float, ray: &Ray) -> Option<Float> {
+    if ray.direction.y().abs() < EPSILON {
+        return None
+    }
+
+    let t = (y - ray.origin.y()) / ray.direction.y();
+
+    let x = ray.origin.x() + t * ray.direction.x();
+    let z = ray.origin.z() + t * ray.direction.z();
+
+    if x*x + z*z <= y*y {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+impl BaseShape for Cone {
+    fn intersect(&self, ray: &Ray) -> Hits {
+        let (o_x, o_y, o_z) = (ray.origin.x(), ray.origin.y(), ray.origin.z());
+        let (d_x, d_y, d_z) = (ray.direction.x(), ray.direction.y(), ray.direction.z());
+
+        let a = d_x*d_x - d_y*d_y + d_z*d_z;
+        let b = 2.0*o_x*d_x - 2.0*o_y*d_y + 2.0*o_z*d_z;
+        let c = o_x*o_x - o_y*o_y + o_z*o_z;
+
+        let mut ts = Hits::new();
+
+        if a.abs() < EPSILON {
+            // parallel to one half of the cone: hits the other half once
+            if b.abs() >= EPSILON {
+                ts.push(-c / (2.0*b));
+            }
+        } else {
+            // rays grazing the cone come out slightly negative
+            let d = b*b - 4.0*a*c;
+            let d = if d < 0.0 && d > -EPSILON { 0.0 } else { d };
+
+            if d >= 0.0 {
+                let t0 = (-b - d.sqrt()) / (2.0*a);
+                let t1 = (-b + d.sqrt()) / (2.0*a);
+
+                ts.push(t0.min(t1));
+                ts.push(t0.max(t1));
+            }
+        }
+
+        let mut ret = Hits::new();
+
+        for t in ts {
+            let y = o_y + t * d_y;
+
+            if self.min < y && y < self.max {
+                ret.push(t)
+            }
+        }
+
+        if self.is_closed {
+            if let Some(t) = check_cap(self.min, ray) {
+                ret.push(t)
+            }
+            if let Some(t) = check_cap(self.max, ray) {
+                ret.push(t)
+            }
+        }
+
+        ret
+    }
+
+    fn normal_at(&self, p: V4) -> V4 {
+        let d = p.x()*p.x() + p.z()*p.z();
+
+        if p.y() >= (self.max - EPSILON) && d < self.max*self.max {
+            return V4::new_vector(0.0,  1.0, 0.0)
+        }
+        if p.y() <= (self.min + EPSILON) && d < self.min*self.min {
+            return V4::new_vector(0.0, -1.0, 0.0)
+        }
+
+        let y = if p.y() > 0.0 { -d.sqrt() } else { d.sqrt() };
+
+        V4::new_vector(p.x(), y, p.z())
+    }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Cone { min: self.min, max: self.max, closed: self.is_closed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use float_cmp::*;
+
+    #[test]
+    fn cone_intersect() {
+        let tests = [
+            (V4::new_point(0.0, 0.0, -5.0), V4::new_vector( 0.0,  0.0, 1.0), 5.0,     5.0),
+            (V4::new_point(0.0, 0.0, -5.0), V4::new_vector( 1.0,  1.0, 1.0), 8.66025, 8.66025),
+            (V4::new_point(1.0, 1.0, -5.0), V4::new_vector(-0.5, -1.0, 1.0), 4.55006, 49.44994)
+        ];
+
+        let c = Cone::new();
+
+        for t in &tests {
+            let xs = c.intersect(&Ray::new(t.0, t.1.normalize()));
+
+            assert_eq!(xs.len(), 2);
+
+            assert!(approx_eq!(Float, xs[0], t.2, epsilon = 0.001));
+            assert!(approx_eq!(Float, xs[1], t.3, epsilon = 0.001));
+        }
+    }
+
+    #[test]
+    fn cone_parallel() {
+        let c = Cone::new();
+        let xs = c.intersect(&Ray::new(V4::new_point(0.0, 0.0, -1.0), V4::new_vector(0.0, 1.0, 1.0).normalize()));
+
+        assert_eq!(xs.len(), 1);
+        assert!(approx_eq!(Float, xs[0], 0.35355, epsilon = 0.0001));
+    }
+
+    #[test]
+    fn cone_closed() {
+        let tests = [
+            (V4::new_point(0.0, 0.0, -5.0),  V4::new_vector(0.0, 1.0, 0.0), 0),
+            (V4::new_point(0.0, 0.0, -0.25), V4::new_vector(0.0, 1.0, 1.0), 2),
+            (V4::new_point(0.0, 0.0, -0.25), V4::new_vector(0.0, 1.0, 0.0), 4)
+        ];
+
+        let c = Cone::new_closed(-0.5, 0.5);
+
+        for t in &tests {
+            assert_eq!(c.intersect(&Ray::new(t.0, t.1.normalize())).len(), t.2)
+        }
+    }
+
+    #[test]
+    fn cone_normal() {
+        let c = Cone::new();
+
+        assert_eq!(c.normal_at(V4::new_point( 0.0,  0.0, 0.0)), V4::new_vector( 0.0, 0.0, 0.0));
+        assert_eq!(c.normal_at(V4::new_point( 1.0,  1.0, 1.0)), V4::new_vector( 1.0, -Float::sqrt(2.0), 1.0));
+        assert_eq!(c.normal_at(V4::new_point(-1.0, -1.0, 0.0)), V4::new_vector(-1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn cone_closed_normal() {
+        let c = Cone::new_closed(-1.0, 2.0);
+
+        assert_eq!(c.normal_at(V4::new_point(0.5,  2.0, 0.0)), V4::new_vector(0.0,  1.0, 0.0));
+        assert_eq!(c.normal_at(V4::new_point(0.5, -1.0, 0.0)), V4::new_vector(0.0, -1.0, 0.0));
+    }
+}
@@ -1,7 +1,15 @@
+use raytracer_challenge::aov::Aov;
+use raytracer_challenge::framebuffer::Rect;
+use raytracer_challenge::imageio;
+use raytracer_challenge::linalg::Float;
+use raytracer_challenge::tonemap::{Encoding,ToneOperator};
+
 use getopts::Options;
 
 use std::fmt;
 use std::error;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum ConfigError {
@@ -34,6 +42,28 @@ fn setup_opts() -> Options {
     let mut opts = Options::new();
 
     opts.optopt("o", "output", "set output file name", "NAME");
+    opts.optopt("f", "format", "output format: png, ppm, ppm-ascii, pfm, jpg, bmp, tga (default: from file name)", "FMT");
+    opts.optopt("", "exposure", "exposure adjustment in stops (overrides scene)", "EV");
+    opts.optopt("", "tonemap", "tone operator: clamp, reinhard, aces (overrides scene)", "OP");
+    opts.optopt("", "encoding", "output transfer encoding: linear, srgb (overrides scene)", "ENC");
+    opts.optopt("", "aov", "comma-separated auxiliary passes to write: depth, normal, albedo, objectid, materialid", "LIST");
+    opts.optflag("", "aov-layers", "store auxiliary passes as layers of the (EXR) output file instead of separate files");
+    opts.optflag("", "denoise", "run the albedo/normal-guided denoiser before tone mapping");
+    opts.optopt("s", "samples", "number of progressive passes (samples per pixel; default 1, unlimited with --time-limit)", "N");
+    opts.optopt("", "time-limit", "stop refining after this many seconds", "SECS");
+    opts.optopt("", "snapshot-every", "write the current estimate every SECS seconds", "SECS");
+    opts.optopt("", "snapshot-passes", "write the current estimate every N passes", "N");
+    opts.optopt("", "checkpoint", "periodically save the render state to FILE (and on Ctrl-C)", "FILE");
+    opts.optopt("", "checkpoint-every", "seconds between checkpoints (default 60)", "SECS");
+    opts.optflag("", "resume", "continue the render saved in the --checkpoint file");
+    opts.optopt("", "frames", "render frames START to END of an animation into numbered files (e.g. render_0001.png)", "START..END");
+    opts.optopt("", "region", "render only the pixels x0..x1, y0..y1 (overrides the camera crop)", "X0,Y0,X1,Y1");
+    opts.optflag("", "region-fullsize", "write a full-size image with everything outside the region black");
+    opts.optflag("", "check", "report problems in the scene file instead of rendering");
+    opts.optopt("", "debug-pixel", "print the ray tree of one pixel instead of rendering", "X,Y");
+    opts.optopt("", "serve", "run as a render worker listening on ADDR (e.g. 0.0.0.0:7878)", "ADDR");
+    opts.optopt("", "workers", "comma-separated worker addresses to distribute the render to", "LIST");
+    opts.optopt("", "tile-size", "tile edge length in pixels for distributed rendering (default 32)", "N");
     opts.optflag("h", "help", "print usage");
 
     opts
@@ -42,7 +72,31 @@ fn setup_opts() -> Options {
 #[derive(Clone,Debug)]
 pub struct Config {
     pub input_file_name: String,
-    pub output_file_name: String
+    pub output_file_name: String,
+    /// From `--format` or the output file name's extension
+    pub output_format: String,
+    pub exposure: Option<Float>,
+    pub tone_operator: Option<ToneOperator>,
+    pub encoding: Option<Encoding>,
+    pub aovs: Vec<Aov>,
+    pub aov_layers: bool,
+    pub denoise: bool,
+    pub samples: u32,
+    pub time_limit: Option<Duration>,
+    pub snapshot_interval: Option<Duration>,
+    pub snapshot_passes: Option<u32>,
+    pub checkpoint_file_name: Option<String>,
+    pub checkpoint_interval: Duration,
+    pub resume: bool,
+    /// First and last frame, inclusive
+    pub frames: Option<(u32,u32)>,
+    pub region: Option<Rect>,
+    pub region_fullsize: bool,
+    pub debug_pixel: Option<(usize,usize)>,
+    pub check: bool,
+    pub serve: Option<String>,
+    pub workers: Vec<String>,
+    pub tile_size: usize
 }
 
 impl Config {
@@ -59,14 +113,181 @@ impl Config {
         }
 
         let output = matches.opt_str("output").unwrap_or(String::from("render.png"));
+        let serve = matches.opt_str("serve");
         let input = match matches.free.first() {
             Some(input) => input.clone(),
+            None if serve.is_some() => String::new(),
             None => return Err(ConfigError::ConfigError(String::from("No input provided")))
         };
 
+        let output_format = match matches.opt_str("format") {
+            Some(fmt) => {
+                if imageio::writer_for_format(&fmt).is_none() {
+                    return Err(ConfigError::ConfigError(format!("Unknown output format {}", fmt)))
+                }
+                fmt
+            },
+            None => String::from(Path::new(&output).extension().and_then(|e| e.to_str()).unwrap_or(""))
+        };
+
+        // check the format from the file name now rather than after rendering
+        let writes_image = serve.is_none() && !matches.opt_present("check") && !matches.opt_present("debug-pixel");
+
+        if writes_image && imageio::writer_for_format(&output_format).is_none() {
+            return Err(ConfigError::ConfigError(format!("Unknown output format for {}, use --format", output)))
+        }
+        if matches.opt_present("aov-layers") && output_format.to_lowercase() != "exr" {
+            return Err(ConfigError::ConfigError(String::from("--aov-layers requires EXR output")))
+        }
+
+        let exposure = matches.opt_get::<Float>("exposure").map_err(|e| ConfigError::Other(e.into()))?;
+        let tone_operator = match matches.opt_str("tonemap") {
+            Some(s) => match ToneOperator::from_name(&s) {
+                Some(op) => Some(op),
+                None => return Err(ConfigError::ConfigError(format!("Unknown tone operator {}", s)))
+            },
+            None => None
+        };
+        let encoding = match matches.opt_str("encoding") {
+            Some(s) => match Encoding::from_name(&s) {
+                Some(enc) => Some(enc),
+                None => return Err(ConfigError::ConfigError(format!("Unknown encoding {}", s)))
+            },
+            None => None
+        };
+
+        let mut aovs = Vec::new();
+        if let Some(list) = matches.opt_str("aov") {
+            for name in list.split(',') {
+                match Aov::from_name(name.trim()) {
+                    Some(aov) => aovs.push(aov),
+                    None => return Err(ConfigError::ConfigError(format!("Unknown AOV {}", name)))
+                }
+            }
+        }
+
+        let seconds = |name: &str| -> Result<Option<Duration>, ConfigError> {
+            match matches.opt_get::<f32>(name) {
+                Ok(Some(v)) if v > 0.0 => Ok(Some(Duration::from_secs_f32(v))),
+                Ok(Some(_)) => Err(ConfigError::ConfigError(format!("--{} must be positive", name))),
+                Ok(None) => Ok(None),
+                Err(e) => Err(ConfigError::Other(e.into()))
+            }
+        };
+
+        let time_limit = seconds("time-limit")?;
+        let snapshot_interval = seconds("snapshot-every")?;
+        let snapshot_passes = matches.opt_get::<u32>("snapshot-passes").map_err(|e| ConfigError::Other(e.into()))?;
+
+        let checkpoint_file_name = matches.opt_str("checkpoint");
+        let checkpoint_interval = seconds("checkpoint-every")?.unwrap_or(Duration::from_secs(60));
+        let resume = matches.opt_present("resume");
+
+        if resume && checkpoint_file_name.is_none() {
+            return Err(ConfigError::ConfigError(String::from("--resume requires --checkpoint")))
+        }
+
+        let default_samples = if time_limit.is_some() { 0 } else { 1 };
+        let samples = matches.opt_get_default::<u32>("samples", default_samples).map_err(|e| ConfigError::Other(e.into()))?;
+
+        // 0 passes means no limit; without a time limit, that would never end
+        if samples == 0 && time_limit.is_none() {
+            return Err(ConfigError::ConfigError(String::from("--samples 0 needs a --time-limit")))
+        }
+
+        let region = match matches.opt_str("region") {
+            Some(s) => {
+                let v: Vec<usize> = s.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+
+                if v.len() != 4 || s.split(',').count() != 4 {
+                    return Err(ConfigError::ConfigError(format!("Invalid region {}", s)))
+                }
+
+                Some(Rect { x0: v[0], y0: v[1], x1: v[2], y1: v[3] })
+            },
+            None => None
+        };
+
+        let frames = match matches.opt_str("frames") {
+            Some(s) => {
+                let (a, b) = match s.find("..") {
+                    Some(p) => (&s[..p], &s[p+2..]),
+                    None => (s.as_str(), s.as_str())
+                };
+
+                match (a.trim().parse::<u32>(), b.trim().parse::<u32>()) {
+                    (Ok(a), Ok(b)) if a <= b => Some((a, b)),
+                    _ => return Err(ConfigError::ConfigError(format!("Invalid frame range {}", s)))
+                }
+            },
+            None => None
+        };
+
+        if let Some((a, b)) = frames {
+            if checkpoint_file_name.is_some() {
+                return Err(ConfigError::ConfigError(String::from("--frames does not support checkpoints")))
+            }
+            if a != b && matches.opt_present("debug-pixel") {
+                return Err(ConfigError::ConfigError(String::from("--debug-pixel needs a single frame")))
+            }
+        }
+
+        let debug_pixel = match matches.opt_str("debug-pixel") {
+            Some(s) => {
+                let v: Vec<usize> = s.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+
+                if v.len() != 2 || s.split(',').count() != 2 {
+                    return Err(ConfigError::ConfigError(format!("Invalid pixel {}", s)))
+                }
+
+                Some((v[0], v[1]))
+            },
+            None => None
+        };
+
+        let workers: Vec<String> = match matches.opt_str("workers") {
+            Some(list) => list.split(',').map(|w| String::from(w.trim())).filter(|w| !w.is_empty()).collect(),
+            None => Vec::new()
+        };
+        let tile_size = matches.opt_get_default::<usize>("tile-size", 32).map_err(|e| ConfigError::Other(e.into()))?;
+
+        if tile_size == 0 {
+            return Err(ConfigError::ConfigError(String::from("--tile-size must be positive")))
+        }
+        if !workers.is_empty() {
+            if samples == 0 || time_limit.is_some() {
+                return Err(ConfigError::ConfigError(String::from("--workers needs a fixed number of --samples")))
+            }
+            if !aovs.is_empty() || matches.opt_present("denoise") || checkpoint_file_name.is_some() {
+                return Err(ConfigError::ConfigError(String::from("--workers does not support AOVs, denoising or checkpoints")))
+            }
+        }
+
         let config = Config {
             input_file_name: input,
-            output_file_name: output
+            output_file_name: output,
+            output_format,
+            exposure,
+            tone_operator,
+            encoding,
+            aovs,
+            aov_layers: matches.opt_present("aov-layers"),
+            denoise: matches.opt_present("denoise"),
+            samples,
+            time_limit,
+            snapshot_interval,
+            snapshot_passes,
+            checkpoint_file_name,
+            checkpoint_interval,
+            resume,
+            frames,
+            region,
+            region_fullsize: matches.opt_present("region-fullsize"),
+            debug_pixel,
+            check: matches.opt_present("check"),
+            serve,
+            workers,
+            tile_size
         };
 
         Ok(config)
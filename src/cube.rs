@@ -1,17 +1,17 @@
-use crate::linalg::V4;
+use crate::linalg::{EPSILON,Float,V4};
 use crate::ray::Ray;
-use crate::shape::BaseShape;
+use crate::shape::{BaseShape,Hits,Primitive};
 
 pub struct Cube ();
 
-fn check_axis(origin: f32, direction: f32) -> (f32, f32) {
+fn check_axis(origin: Float, direction: Float) -> (Float, Float) {
     let tmin_num = -1.0 - origin;
     let tmax_num =  1.0 - origin;
 
-    let (tmin, tmax) = if direction.abs() > 0.0001 {
+    let (tmin, tmax) = if direction.abs() > EPSILON {
         (tmin_num / direction, tmax_num / direction)
     } else {
-        (tmin_num * f32::INFINITY, tmax_num * f32::INFINITY)
+        (tmin_num * Float::INFINITY, tmax_num * Float::INFINITY)
     };
 
     if tmin > tmax {
@@ -22,19 +22,22 @@ fn check_axis(origin: f32, direction: f32) -> (f32, f32) {
 }
 
 impl BaseShape for Cube {
-    fn intersect(&self, r: &Ray) -> Vec<f32> {
+    fn intersect(&self, r: &Ray) -> Hits {
         let (xtmin, xtmax) = check_axis(r.origin.x(), r.direction.x());
         let (ytmin, ytmax) = check_axis(r.origin.y(), r.direction.y());
         let (ztmin, ztmax) = check_axis(r.origin.z(), r.direction.z());
 
-        let tmin = [ xtmin, ytmin, ztmin ].iter().fold(f32::MIN, |a, &b| a.max(b));
-        let tmax = [ xtmax, ytmax, ztmax ].iter().fold(f32::MAX, |a, &b| a.min(b));
+        let tmin = [ xtmin, ytmin, ztmin ].iter().fold(Float::MIN, |a, &b| a.max(b));
+        let tmax = [ xtmax, ytmax, ztmax ].iter().fold(Float::MAX, |a, &b| a.min(b));
 
-        if tmin > tmax {
-            vec![]
-        } else {
-            vec![ tmin, tmax ]
+        let mut v = Hits::new();
+
+        if tmin <= tmax {
+            v.push(tmin);
+            v.push(tmax)
         }
+
+        v
     }
 
     fn normal_at(&self, p: V4) -> V4 {
@@ -52,6 +55,10 @@ impl BaseShape for Cube {
             }
         }
     }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Cube
+    }
 }
 
 #[cfg(test)]
@@ -80,8 +87,8 @@ mod tests {
 
             assert_eq!(xs.len(), 2);
 
-            assert!(approx_eq!(f32, xs[0], x.2, epsilon = 0.0001));
-            assert!(approx_eq!(f32, xs[1], x.3, epsilon = 0.0001));
+            assert!(approx_eq!(Float, xs[0], x.2, epsilon = 0.0001));
+            assert!(approx_eq!(Float, xs[1], x.3, epsilon = 0.0001));
         }
     }
 
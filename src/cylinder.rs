@@ -1,29 +1,35 @@
-use crate::linalg::V4;
+use crate::linalg::{EPSILON,Float,V4};
 use crate::ray::Ray;
-use crate::shape::BaseShape;
+use crate::shape::{BaseShape,Hits,Primitive};
 
 pub struct Cylinder {
-    min: f32,
-    max: f32,
+    min: Float,
+    max: Float,
     is_closed: bool
 }
 
 impl Cylinder {
     pub fn new() -> Cylinder {
-        Cylinder { min: std::f32::MIN, max: std::f32::MAX, is_closed: false }
+        Cylinder { min: Float::MIN, max: Float::MAX, is_closed: false }
     }
 
-    pub fn new_closed(min: f32, max: f32) -> Cylinder {
+    pub fn new_closed(min: Float, max: Float) -> Cylinder {
         Cylinder { min: min, max: max, is_closed: true }
     }
 
-    pub fn new_truncated(min: f32, max: f32) -> Cylinder {
+    pub fn new_truncated(min: Float, max: Float) -> Cylinder {
         Cylinder { min: min, max: max, is_closed: false }
     }
 }
 
-fn check_cap(y: f32, ray: &Ray) -> Option<f32> {
-    if ray.direction.y().abs() < 0.0001 {
+impl Default for Cylinder {
+    fn default() -> Cylinder {
+        Cylinder::new()
+    }
+}
+
+fn check_cap(y: Float, ray: &Ray) -> Option<Float> {
+    if ray.direction.y().abs() < EPSILON {
         return None
     }
 
@@ -40,13 +46,13 @@ fn check_cap(y: f32, ray: &Ray) -> Option<f32> {
 }
 
 impl BaseShape for Cylinder {
-    fn intersect(&self, ray: &Ray) -> Vec<f32> {
+    fn intersect(&self, ray: &Ray) -> Hits {
         let d_x = ray.direction.x();
         let d_z = ray.direction.z();
 
         let a = d_x*d_x + d_z*d_z;
 
-        let mut ret = vec![];
+        let mut ret = Hits::new();
 
         if a > 0.0 {
             let o_x = ray.origin.x();
@@ -91,16 +97,20 @@ impl BaseShape for Cylinder {
         let d = p.x()*p.x() + p.z()*p.z();
 
         if d < 1.0 {
-            if p.y() >= (self.max - 0.0001) {
+            if p.y() >= (self.max - EPSILON) {
                 return V4::new_vector(0.0,  1.0, 0.0)
             }
-            if p.y() <= (self.min + 0.0001) {
+            if p.y() <= (self.min + EPSILON) {
                 return V4::new_vector(0.0, -1.0, 0.0)
             }
         }
 
         V4::new_vector(p.x(), 0.0, p.z())
     }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Cylinder { min: self.min, max: self.max, closed: self.is_closed }
+    }
 }
 
 #[cfg(test)]
@@ -140,8 +150,8 @@ mod tests {
 
             assert_eq!(xs.len(), 2);
 
-            assert!(approx_eq!(f32, xs[0], t.2, epsilon = 0.0001));
-            assert!(approx_eq!(f32, xs[1], t.3, epsilon = 0.0001));
+            assert!(approx_eq!(Float, xs[0], t.2, epsilon = 0.0001));
+            assert!(approx_eq!(Float, xs[1], t.3, epsilon = 0.0001));
         }
     }
 
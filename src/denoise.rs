@@ -0,0 +1,206 @@
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::linalg::Float;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Each
+/// iteration applies a 5x5 B3-spline kernel with holes of 2^i pixels;
+/// the weights fall off with differences in color, normal and albedo so
+/// that geometric and texture edges survive. Works on linear radiance,
+/// i.e. runs before tone mapping.
+#[derive(Clone,Copy,Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    pub sigma_color: Float,
+    pub sigma_normal: Float,
+    pub sigma_albedo: Float
+}
+
+const KERNEL: [Float; 5] = [ 1.0/16.0, 1.0/4.0, 3.0/8.0, 1.0/4.0, 1.0/16.0 ];
+
+fn dist2(a: Color, b: Color) -> Float {
+    let (dr, dg, db) = (a.r - b.r, a.g - b.g, a.b - b.b);
+    dr*dr + dg*dg + db*db
+}
+
+impl Denoiser {
+    pub fn new() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.5,
+            sigma_normal: 0.1,
+            sigma_albedo: 0.1
+        }
+    }
+
+    fn pass(&self, input: &FrameBuffer, albedo: &FrameBuffer, normal: &FrameBuffer, step: usize, sigma_color: Float) -> FrameBuffer {
+        let (w, h) = (input.width, input.height);
+        let mut output = FrameBuffer::new(w, h);
+
+        for y in 0..h {
+            for x in 0..w {
+                let c_p = input.at(x, y);
+                let n_p = normal.at(x, y);
+                let a_p = albedo.at(x, y);
+
+                let mut sum = Color::BLACK;
+                let mut wsum = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let qy = y as isize + (j as isize - 2) * step as isize;
+                    if qy < 0 || qy >= h as isize {
+                        continue
+                    }
+
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x as isize + (i as isize - 2) * step as isize;
+                        if qx < 0 || qx >= w as isize {
+                            continue
+                        }
+
+                        let (qx, qy) = (qx as usize, qy as usize);
+                        let c_q = input.at(qx, qy);
+
+                        let wc = (-dist2(c_p, c_q) / (sigma_color*sigma_color)).exp();
+                        let wn = (-dist2(n_p, normal.at(qx, qy)) / (self.sigma_normal*self.sigma_normal)).exp();
+                        let wa = (-dist2(a_p, albedo.at(qx, qy)) / (self.sigma_albedo*self.sigma_albedo)).exp();
+
+                        let weight = kx * ky * wc * wn * wa;
+
+                        sum.r += c_q.r * weight;
+                        sum.g += c_q.g * weight;
+                        sum.b += c_q.b * weight;
+                        wsum  += weight;
+                    }
+                }
+
+                // the center tap always has weight > 0, so wsum is non-zero
+                output.set(x, y, Color::new(sum.r / wsum, sum.g / wsum, sum.b / wsum));
+            }
+        }
+
+        output
+    }
+
+    /// Filter `color`, guided by the albedo and normal passes of the same
+    /// render. Metadata is carried over to the result.
+    pub fn apply(&self, color: &FrameBuffer, albedo: &FrameBuffer, normal: &FrameBuffer) -> FrameBuffer {
+        let mut result = color.clone();
+        let mut sigma_color = self.sigma_color;
+
+        for i in 0..self.iterations {
+            let mut next = self.pass(&result, albedo, normal, 1 << i, sigma_color);
+
+            for (key, value) in result.metadata() {
+                next.set_metadata(key, value);
+            }
+
+            result = next;
+            sigma_color *= 0.5;
+        }
+
+        result
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform(w: usize, h: usize, c: Color) -> FrameBuffer {
+        let mut fb = FrameBuffer::new(w, h);
+
+        for y in 0..h {
+            for x in 0..w {
+                fb.set(x, y, c);
+            }
+        }
+
+        fb
+    }
+
+    // deterministic checkerboard-ish noise around 0.5
+    fn noisy(w: usize, h: usize) -> FrameBuffer {
+        let mut fb = FrameBuffer::new(w, h);
+
+        for y in 0..h {
+            for x in 0..w {
+                let n = ((x * 7 + y * 13) % 5) as Float * 0.05 - 0.1;
+                fb.set(x, y, Color::new(0.5 + n, 0.5 + n, 0.5 + n));
+            }
+        }
+
+        fb
+    }
+
+    fn variance(fb: &FrameBuffer, x0: usize, x1: usize) -> Float {
+        let mut sum = 0.0;
+        let mut sum2 = 0.0;
+        let mut n = 0.0;
+
+        for y in 0..fb.height {
+            for x in x0..x1 {
+                let v = fb.at(x, y).r;
+                sum += v;
+                sum2 += v*v;
+                n += 1.0;
+            }
+        }
+
+        sum2 / n - (sum / n) * (sum / n)
+    }
+
+    #[test]
+    fn constant_image() {
+        let c = Color::new(0.2, 0.4, 0.6);
+        let fb = uniform(16, 16, c);
+        let a = uniform(16, 16, Color::WHITE);
+        let n = uniform(16, 16, Color::new(0.0, 1.0, 0.0));
+
+        let out = Denoiser::new().apply(&fb, &a, &n);
+
+        for y in 0..16 {
+            for x in 0..16 {
+                let o = out.at(x, y);
+                assert!((o.r - c.r).abs() < 1e-5 && (o.g - c.g).abs() < 1e-5 && (o.b - c.b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn reduces_noise() {
+        let fb = noisy(32, 32);
+        let a = uniform(32, 32, Color::WHITE);
+        let n = uniform(32, 32, Color::new(0.0, 1.0, 0.0));
+
+        let out = Denoiser::new().apply(&fb, &a, &n);
+
+        assert!(variance(&out, 0, 32) < 0.1 * variance(&fb, 0, 32));
+    }
+
+    #[test]
+    fn keeps_albedo_edges() {
+        // left half dark, right half bright, with distinct albedo
+        let mut fb = FrameBuffer::new(16, 8);
+        let mut a = FrameBuffer::new(16, 8);
+        let n = uniform(16, 8, Color::new(0.0, 1.0, 0.0));
+
+        for y in 0..8 {
+            for x in 0..16 {
+                let (c, alb) = if x < 8 { (0.1, Color::BLACK) } else { (0.9, Color::WHITE) };
+                fb.set(x, y, Color::new(c, c, c));
+                a.set(x, y, alb);
+            }
+        }
+
+        let out = Denoiser::new().apply(&fb, &a, &n);
+
+        assert!((out.at(7, 4).r - 0.1).abs() < 1e-3);
+        assert!((out.at(8, 4).r - 0.9).abs() < 1e-3);
+    }
+}
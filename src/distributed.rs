@@ -0,0 +1,496 @@
+use crate::camera::Camera;
+use crate::framebuffer::{FrameBuffer,Rect};
+use crate::rng::Rng;
+use crate::sceneparser;
+use crate::world::World;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io;
+use std::io::{BufReader,BufWriter,Read,Write};
+use std::net::{TcpListener,TcpStream};
+use std::sync::{Arc,Condvar,Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// Protocol: the coordinator sends SCENE once per connection, then any
+// number of TILE requests; the worker answers each with OK/TILE_DATA or
+// ERROR. All integers are little-endian u32. A SCENE with a different
+// VERSION or of more than MAX_SCENE_SIZE bytes gets an ERROR, after which
+// the worker hangs up.
+//
+//   SCENE     VERSION, len, YAML bytes  ->  OK | ERROR len, message
+//   TILE      x0, y0, x1, y1, samples   ->  TILE_DATA FrameBuffer::write_raw | ERROR
+const SCENE: u8 = b'S';
+const TILE: u8 = b'T';
+const OK: u8 = b'K';
+const TILE_DATA: u8 = b'R';
+const ERROR: u8 = b'E';
+
+// Tiles are sent at the build's float precision, so f64 builds speak a
+// version of their own.
+#[cfg(not(feature = "f64"))]
+const VERSION: u32 = 1;
+#[cfg(feature = "f64")]
+const VERSION: u32 = 0x8001;
+
+/// Largest scene description a worker accepts, in bytes.
+const MAX_SCENE_SIZE: usize = 16 << 20;
+
+/// Largest error message read from a worker, in bytes.
+const MAX_MESSAGE_SIZE: usize = 64 << 10;
+
+/// How long to wait for a worker's answer before giving up on it.
+const WORKER_TIMEOUT: Duration = Duration::from_secs(600);
+
+fn read_u8(input: &mut dyn Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Read a string of `len` bytes, where the length has been read already.
+fn read_string_of(input: &mut dyn Read, len: usize) -> Result<String> {
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Read a length-prefixed string of at most `max` bytes.
+fn read_string(input: &mut dyn Read, max: usize) -> Result<String> {
+    let len = read_u32(input)? as usize;
+
+    if len > max {
+        return Err(format!("message of {} bytes is longer than {} bytes", len, max).into())
+    }
+
+    read_string_of(input, len)
+}
+
+fn write_string(output: &mut dyn Write, s: &str) -> io::Result<()> {
+    output.write_all(&(s.len() as u32).to_le_bytes())?;
+    output.write_all(s.as_bytes())
+}
+
+/// Render `samples` passes over `rect`, into the full-size `fb`, and return
+/// the tile. The first pass samples pixel centers; later ones are jittered
+/// with an RNG seeded by the tile position, so a tile comes out the same
+/// on any worker.
+pub fn render_tile(camera: &Camera, world: &World, rect: &Rect, samples: u32, fb: &mut FrameBuffer) -> FrameBuffer {
+    let mut rng = Rng::new(((rect.y0 as u64) << 32) | rect.x0 as u64);
+
+    // start from scratch in case this tile was rendered here before
+    fb.blit(rect.x0, rect.y0, &FrameBuffer::new(rect.width(), rect.height()));
+
+    for pass in 0..samples {
+        let rng = if pass == 0 { None } else { Some(&mut rng) };
+        camera.render_rect(world, rect, rng, fb, &[], &mut []);
+    }
+
+    fb.crop(rect)
+}
+
+fn handle_tile(input: &mut dyn Read, scene: &mut Option<(Camera,World,FrameBuffer)>) -> Result<FrameBuffer> {
+    let mut v = [0; 5];
+    for x in v.iter_mut() {
+        *x = read_u32(input)?;
+    }
+
+    let rect = Rect { x0: v[0] as usize, y0: v[1] as usize, x1: v[2] as usize, y1: v[3] as usize };
+    let samples = v[4];
+
+    let (camera, world, fb) = match scene {
+        Some(s) => s,
+        None => return Err("no scene loaded".into())
+    };
+
+    if rect.x0 >= rect.x1 || rect.y0 >= rect.y1 || rect.x1 > camera.width() || rect.y1 > camera.height() {
+        return Err(format!("invalid tile {:?}", rect).into())
+    }
+
+    Ok(render_tile(camera, world, &rect, samples, fb))
+}
+
+/// Serve one coordinator connection until it hangs up.
+fn handle_connection(stream: TcpStream) -> Result<()> {
+    stream.set_nodelay(true)?;
+
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut output = BufWriter::new(stream);
+
+    let mut scene: Option<(Camera,World,FrameBuffer)> = None;
+
+    loop {
+        let cmd = match read_u8(&mut input) {
+            Ok(c) => c,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into())
+        };
+
+        match cmd {
+            SCENE => {
+                let version = read_u32(&mut input)?;
+
+                // with another version, not even the length can be trusted
+                let len = if version == VERSION { read_u32(&mut input)? as usize } else { 0 };
+
+                let msg = if version != VERSION {
+                    Some(format!("coordinator uses protocol version {:#x}, this worker {:#x} (different float precision?)", version, VERSION))
+                } else if len > MAX_SCENE_SIZE {
+                    Some(format!("scene of {} bytes is larger than {} bytes", len, MAX_SCENE_SIZE))
+                } else {
+                    None
+                };
+
+                if let Some(msg) = msg {
+                    // the scene is left unread, so the connection can't go on
+                    output.write_all(&[ERROR])?;
+                    write_string(&mut output, &msg)?;
+                    output.flush()?;
+
+                    return Err(msg.into())
+                }
+
+                let res = read_string_of(&mut input, len).and_then(|yaml| sceneparser::read_yaml_scene_config(&yaml));
+
+                match res {
+                    Ok((camera, world)) => {
+                        let fb = FrameBuffer::new(camera.width(), camera.height());
+                        scene = Some((camera, world, fb));
+                        output.write_all(&[OK])?;
+                    },
+                    Err(e) => {
+                        output.write_all(&[ERROR])?;
+                        write_string(&mut output, &e.to_string())?;
+                    }
+                }
+            },
+            TILE => {
+                match handle_tile(&mut input, &mut scene) {
+                    Ok(tile) => {
+                        output.write_all(&[TILE_DATA])?;
+                        tile.write_raw(&mut output)?;
+                    },
+                    Err(e) => {
+                        output.write_all(&[ERROR])?;
+                        write_string(&mut output, &e.to_string())?;
+                    }
+                }
+            },
+            c => return Err(format!("unknown command {}", c).into())
+        }
+
+        output.flush()?;
+    }
+}
+
+/// Accept coordinator connections on `listener` forever, one thread each.
+/// A connection that ends in an error is passed to `on_error`, with the
+/// coordinator's address, on that connection's thread.
+pub fn serve_on<F>(listener: TcpListener, on_error: F) -> Result<()>
+where
+    F: Fn(&str, &dyn Error) + Send + Sync + 'static
+{
+    let on_error = Arc::new(on_error);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        let on_error = Arc::clone(&on_error);
+
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream) {
+                on_error(&peer, e.as_ref())
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Coordinator end of one worker connection.
+struct Worker {
+    input: BufReader<TcpStream>,
+    output: BufWriter<TcpStream>
+}
+
+impl Worker {
+    fn connect(addr: &str, scene: &str) -> Result<Worker> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(WORKER_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+
+        let mut worker = Worker {
+            input: BufReader::new(stream.try_clone()?),
+            output: BufWriter::new(stream)
+        };
+
+        worker.output.write_all(&[SCENE])?;
+        worker.output.write_all(&VERSION.to_le_bytes())?;
+        write_string(&mut worker.output, scene)?;
+        worker.output.flush()?;
+
+        match read_u8(&mut worker.input)? {
+            OK => Ok(worker),
+            _ => Err(read_string(&mut worker.input, MAX_MESSAGE_SIZE)?.into())
+        }
+    }
+
+    fn render(&mut self, rect: &Rect, samples: u32) -> Result<FrameBuffer> {
+        self.output.write_all(&[TILE])?;
+        for v in [ rect.x0 as u32, rect.y0 as u32, rect.x1 as u32, rect.y1 as u32, samples ].iter() {
+            self.output.write_all(&v.to_le_bytes())?;
+        }
+        self.output.flush()?;
+
+        match read_u8(&mut self.input)? {
+            TILE_DATA => {
+                Ok(FrameBuffer::read_raw(&mut self.input, rect.width(), rect.height())?)
+            },
+            _ => Err(read_string(&mut self.input, MAX_MESSAGE_SIZE)?.into())
+        }
+    }
+}
+
+struct Queue {
+    tiles: VecDeque<Rect>,
+    remaining: usize
+}
+
+enum Message {
+    Tile(Rect, FrameBuffer),
+    Lost(String, String)
+}
+
+/// Feed tiles from the queue to the worker at `addr` until all tiles are
+/// done. If the worker fails, its tile goes back into the queue.
+fn drive_worker(addr: &str, scene: &str, samples: u32, queue: &(Mutex<Queue>, Condvar), tx: mpsc::Sender<Message>) {
+    let (lock, cvar) = queue;
+
+    let mut worker = match Worker::connect(addr, scene) {
+        Ok(w) => w,
+        Err(e) => {
+            let _ = tx.send(Message::Lost(addr.to_string(), e.to_string()));
+            return
+        }
+    };
+
+    loop {
+        let rect = {
+            let mut q = lock.lock().unwrap();
+
+            loop {
+                if q.remaining == 0 {
+                    return
+                }
+                if let Some(rect) = q.tiles.pop_front() {
+                    break rect
+                }
+                // other workers still have tiles in flight that may come back
+                q = cvar.wait(q).unwrap();
+            }
+        };
+
+        match worker.render(&rect, samples) {
+            Ok(tile) => {
+                lock.lock().unwrap().remaining -= 1;
+                cvar.notify_all();
+                let _ = tx.send(Message::Tile(rect, tile));
+            },
+            Err(e) => {
+                lock.lock().unwrap().tiles.push_back(rect);
+                cvar.notify_all();
+                let _ = tx.send(Message::Lost(addr.to_string(), e.to_string()));
+                return
+            }
+        }
+    }
+}
+
+/// Coordinator mode: render `scene` (the scene YAML text, parsed into
+/// `camera`) on the given workers with `samples` samples per pixel, split
+/// into tiles of `tile_size` pixels square. Tiles of workers that fail or
+/// disconnect are handed to the remaining ones; `on_lost` gets the address
+/// of each such worker and why it was lost.
+pub fn render<F>(scene: &str, camera: &Camera, workers: &[String], tile_size: usize, samples: u32, mut on_lost: F) -> Result<FrameBuffer>
+where
+    F: FnMut(&str, &str)
+{
+    let tiles = camera.region().tiles(tile_size);
+    let ntiles = tiles.len();
+
+    let queue = (Mutex::new(Queue { tiles: tiles.into_iter().collect(), remaining: ntiles }), Condvar::new());
+    let (tx, rx) = mpsc::channel();
+
+    let mut fb = FrameBuffer::new(camera.width(), camera.height());
+
+    let done = thread::scope(|s| {
+        for addr in workers.iter() {
+            let tx = tx.clone();
+            let queue = &queue;
+            s.spawn(move || drive_worker(addr, scene, samples, queue, tx));
+        }
+
+        drop(tx);
+
+        let mut done = 0;
+        let mut alive = workers.len();
+
+        while done < ntiles {
+            match rx.recv() {
+                Ok(Message::Tile(rect, tile)) => {
+                    fb.blit(rect.x0, rect.y0, &tile);
+                    done += 1;
+                },
+                Ok(Message::Lost(addr, e)) => {
+                    on_lost(&addr, &e);
+                    alive -= 1;
+                },
+                Err(_) => break
+            }
+
+            if alive == 0 && done < ntiles {
+                break
+            }
+        }
+
+        done
+    });
+
+    if done < ntiles {
+        return Err(format!("all workers lost, {} of {} tiles missing", ntiles - done, ntiles).into())
+    }
+
+    Ok(fb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE_YAML: &str = "
+camera:
+  width: 20
+  height: 15
+  field_of_view: 60.0
+  from: [ 0.0, 0.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+lights:
+  - point:
+      position: [ -10.0, 10.0, -10.0 ]
+shapes:
+  - sphere:
+      material:
+        texture:
+          color: [ 1.0, 0.2, 1.0 ]
+        ambient: 0.1
+        diffuse: 0.9
+        specular: 0.9
+        shininess: 200.0
+";
+
+    fn start_worker() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || { let _ = serve_on(listener, |_, _| ()); });
+
+        addr
+    }
+
+    #[test]
+    fn tiles_are_deterministic() {
+        let (camera, world) = sceneparser::read_yaml_scene_config(SCENE_YAML).unwrap();
+        let rect = Rect { x0: 4, y0: 3, x1: 12, y1: 9 };
+
+        let mut fb = FrameBuffer::new(camera.width(), camera.height());
+        let a = render_tile(&camera, &world, &rect, 3, &mut fb);
+        let b = render_tile(&camera, &world, &rect, 3, &mut fb);
+
+        assert_eq!(a.samples(0, 0), 3);
+        assert_eq!(b.samples(0, 0), 3);
+        assert_eq!(a.at(5, 4), b.at(5, 4));
+    }
+
+    #[test]
+    fn render_on_workers() {
+        let (camera, world) = sceneparser::read_yaml_scene_config(SCENE_YAML).unwrap();
+        let workers = vec![ start_worker(), start_worker() ];
+
+        let fb = render(SCENE_YAML, &camera, &workers, 8, 1, |_, _| ()).unwrap();
+        let expected = camera.render(&world);
+
+        for y in 0..15 {
+            for x in 0..20 {
+                assert_eq!(fb.at(x, y), expected.at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn scene_too_large() {
+        let mut stream = TcpStream::connect(start_worker()).unwrap();
+
+        stream.write_all(&[SCENE]).unwrap();
+        stream.write_all(&VERSION.to_le_bytes()).unwrap();
+        stream.write_all(&(1u32 << 31).to_le_bytes()).unwrap();
+
+        assert_eq!(read_u8(&mut stream).unwrap(), ERROR);
+        assert!(read_string(&mut stream, MAX_MESSAGE_SIZE).unwrap().contains("larger than"));
+
+        // and the worker hangs up
+        assert!(read_u8(&mut stream).is_err());
+    }
+
+    #[test]
+    fn wrong_version() {
+        let mut stream = TcpStream::connect(start_worker()).unwrap();
+
+        stream.write_all(&[SCENE]).unwrap();
+        stream.write_all(&(VERSION ^ 0x8000).to_le_bytes()).unwrap();
+        write_string(&mut stream, SCENE_YAML).unwrap();
+
+        assert_eq!(read_u8(&mut stream).unwrap(), ERROR);
+        assert!(read_string(&mut stream, MAX_MESSAGE_SIZE).unwrap().contains("protocol version"));
+        assert!(read_u8(&mut stream).is_err());
+    }
+
+    #[test]
+    fn lost_workers() {
+        let (camera, world) = sceneparser::read_yaml_scene_config(SCENE_YAML).unwrap();
+
+        // a "worker" that accepts the scene and then hangs up
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let bad = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut input = stream.try_clone().unwrap();
+
+            read_u8(&mut input).unwrap();
+            read_u32(&mut input).unwrap();
+            read_string(&mut input, MAX_SCENE_SIZE).unwrap();
+            stream.write_all(&[OK]).unwrap();
+        });
+
+        let workers = vec![ bad.clone(), start_worker() ];
+        let mut lost = Vec::new();
+        let fb = render(SCENE_YAML, &camera, &workers, 4, 1, |addr, _| lost.push(addr.to_string())).unwrap();
+
+        assert_eq!(fb.at(10, 7), camera.render(&world).at(10, 7));
+        assert_eq!(fb.samples(19, 14), 1);
+        assert_eq!(lost, vec![ bad.clone() ]);
+
+        // nobody left to render
+        assert!(render(SCENE_YAML, &camera, &[ bad ], 4, 1, |_, _| ()).is_err());
+    }
+}
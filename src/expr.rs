@@ -0,0 +1,308 @@
+//! Arithmetic expressions in scene files, e.g. `2.5 * i + offset`.
+//!
+//! Expressions have `+ - * / %`, `^` (power), parentheses, variables, the
+//! constant `pi`, and the functions sin, cos, tan (in radians), sqrt, abs,
+//! floor, ceil, min and max.
+
+use std::error;
+use std::fmt;
+
+#[derive(Debug,PartialEq)]
+pub(crate) enum ExprError {
+    Syntax(String),
+    Undefined(String),
+    UnknownFunction(String),
+    Arguments(&'static str, usize),
+    TooDeep
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::Syntax(s)
+                => f.write_fmt(format_args!("invalid expression \"{}\"", s)),
+            ExprError::Undefined(s)
+                => f.write_fmt(format_args!("{} is not defined", s)),
+            ExprError::UnknownFunction(s)
+                => f.write_fmt(format_args!("unknown function {}", s)),
+            ExprError::Arguments(s, n)
+                => f.write_fmt(format_args!("{} takes {} argument{}", s, n, if *n == 1 { "" } else { "s" })),
+            ExprError::TooDeep
+                => f.write_fmt(format_args!("expression nested more than {} levels deep", MAX_DEPTH))
+        }
+    }
+}
+
+impl error::Error for ExprError {}
+
+/// Variable bindings; later entries shadow earlier ones.
+pub(crate) type Vars = [(String,f64)];
+
+/// How deeply parentheses, signs and powers may nest before the parser
+/// gives up instead of running out of stack.
+const MAX_DEPTH: usize = 64;
+
+/// Name, number of arguments, implementation.
+type Function = (&'static str, usize, fn(&[f64]) -> f64);
+
+const FUNCTIONS: &[Function] = &[
+    ("sin",   1, |a| a[0].sin()),
+    ("cos",   1, |a| a[0].cos()),
+    ("tan",   1, |a| a[0].tan()),
+    ("sqrt",  1, |a| a[0].sqrt()),
+    ("abs",   1, |a| a[0].abs()),
+    ("floor", 1, |a| a[0].floor()),
+    ("ceil",  1, |a| a[0].ceil()),
+    ("min",   2, |a| a[0].min(a[1])),
+    ("max",   2, |a| a[0].max(a[1]))
+];
+
+pub(crate) fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+/// Recursive-descent evaluator. Returns None on syntax errors; other errors
+/// are kept in `error` so that a syntax error anywhere takes precedence.
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    vars: &'a Vars,
+    error: Option<ExprError>,
+    depth: usize
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<u8> {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1
+        }
+
+        self.s.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn fail(&mut self, e: ExprError) -> f64 {
+        if self.error.is_none() {
+            self.error = Some(e)
+        }
+
+        f64::NAN
+    }
+
+    fn take_while<F: Fn(u8) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while self.pos < self.s.len() && f(self.s[self.pos]) {
+            self.pos += 1
+        }
+
+        std::str::from_utf8(&self.s[start..self.pos]).unwrap()
+    }
+
+    fn expr(&mut self) -> Option<f64> {
+        let mut v = self.term()?;
+
+        loop {
+            if self.eat(b'+') {
+                v += self.term()?
+            } else if self.eat(b'-') {
+                v -= self.term()?
+            } else {
+                return Some(v)
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<f64> {
+        let mut v = self.unary()?;
+
+        loop {
+            if self.eat(b'*') {
+                v *= self.unary()?
+            } else if self.eat(b'/') {
+                v /= self.unary()?
+            } else if self.eat(b'%') {
+                v %= self.unary()?
+            } else {
+                return Some(v)
+            }
+        }
+    }
+
+    /// Every level of nesting passes through here.
+    fn unary(&mut self) -> Option<f64> {
+        if self.depth == MAX_DEPTH {
+            self.error = Some(ExprError::TooDeep);
+            return None
+        }
+
+        self.depth += 1;
+
+        let v = if self.eat(b'-') {
+            self.unary().map(|v| -v)
+        } else if self.eat(b'+') {
+            self.unary()
+        } else {
+            self.power()
+        };
+
+        self.depth -= 1;
+        v
+    }
+
+    fn power(&mut self) -> Option<f64> {
+        let v = self.atom()?;
+
+        if self.eat(b'^') {
+            Some(v.powf(self.unary()?))
+        } else {
+            Some(v)
+        }
+    }
+
+    fn atom(&mut self) -> Option<f64> {
+        match self.peek()? {
+            b'(' => {
+                self.pos += 1;
+                let v = self.expr()?;
+                if self.eat(b')') { Some(v) } else { None }
+            },
+            c if c.is_ascii_digit() || c == b'.' => {
+                let start = self.pos;
+                self.take_while(|c| c.is_ascii_digit() || c == b'.');
+                if self.pos < self.s.len() && (self.s[self.pos] == b'e' || self.s[self.pos] == b'E') {
+                    self.pos += 1;
+                    if self.pos < self.s.len() && (self.s[self.pos] == b'+' || self.s[self.pos] == b'-') {
+                        self.pos += 1
+                    }
+                    self.take_while(|c| c.is_ascii_digit());
+                }
+
+                std::str::from_utf8(&self.s[start..self.pos]).unwrap().parse::<f64>().ok()
+            },
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+
+                if self.eat(b'(') {
+                    let mut args = vec![ self.expr()? ];
+                    while self.eat(b',') {
+                        args.push(self.expr()?)
+                    }
+                    if !self.eat(b')') {
+                        return None
+                    }
+
+                    Some(self.call(name, &args))
+                } else {
+                    Some(self.lookup(name))
+                }
+            },
+            _ => None
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[f64]) -> f64 {
+        match FUNCTIONS.iter().find(|f| f.0 == name) {
+            Some((name, n, _)) if args.len() != *n => self.fail(ExprError::Arguments(name, *n)),
+            Some((_, _, f)) => f(args),
+            None => self.fail(ExprError::UnknownFunction(String::from(name)))
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> f64 {
+        match self.vars.iter().rev().find(|(n, _)| n == name) {
+            Some((_, v)) => *v,
+            None if name == "pi" => std::f64::consts::PI,
+            None => self.fail(ExprError::Undefined(String::from(name)))
+        }
+    }
+}
+
+pub(crate) fn eval(s: &str, vars: &Vars) -> Result<f64, ExprError> {
+    let mut p = Parser { s: s.as_bytes(), pos: 0, vars, error: None, depth: 0 };
+
+    match p.expr() {
+        _ if p.error == Some(ExprError::TooDeep) => Err(ExprError::TooDeep),
+        Some(v) if p.peek().is_none() => match p.error {
+            Some(e) => Err(e),
+            None => Ok(v)
+        },
+        _ => Err(ExprError::Syntax(String::from(s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(v: &[(&str, f64)]) -> Vec<(String,f64)> {
+        v.iter().map(|(n, x)| (String::from(*n), *x)).collect()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let none = vars(&[]);
+
+        assert_eq!(eval("1 + 2 * 3", &none), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3", &none), Ok(9.0));
+        assert_eq!(eval("10 - 4 - 3", &none), Ok(3.0));
+        assert_eq!(eval("12 / 4 / 3", &none), Ok(1.0));
+        assert_eq!(eval("7 % 4", &none), Ok(3.0));
+        assert_eq!(eval("-2 ^ 2", &none), Ok(-4.0));
+        assert_eq!(eval("2 ^ 3 ^ 2", &none), Ok(512.0));
+        assert_eq!(eval("2 ^ -1", &none), Ok(0.5));
+        assert_eq!(eval("1.5e2 + .5", &none), Ok(150.5));
+        assert_eq!(eval("max(1, min(4, 3)) + abs(-2) + floor(1.7)", &none), Ok(6.0));
+        assert!((eval("cos(pi)", &none).unwrap() + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn variables() {
+        let v = vars(&[ ("i", 2.0), ("n_2", 10.0), ("i", 3.0) ]);
+
+        assert_eq!(eval("i * n_2", &v), Ok(30.0));
+        assert_eq!(eval("pi", &vars(&[ ("pi", 3.0) ])), Ok(3.0));
+    }
+
+    #[test]
+    fn errors() {
+        let v = vars(&[ ("i", 1.0) ]);
+
+        assert_eq!(eval("j + 1", &v), Err(ExprError::Undefined(String::from("j"))));
+        assert_eq!(eval("log(i)", &v), Err(ExprError::UnknownFunction(String::from("log"))));
+        assert_eq!(eval("min(i)", &v), Err(ExprError::Arguments("min", 2)));
+        assert_eq!(eval("j +", &v), Err(ExprError::Syntax(String::from("j +"))));
+        assert_eq!(eval("(i", &v), Err(ExprError::Syntax(String::from("(i"))));
+        assert_eq!(eval("reinhard filmic", &v), Err(ExprError::Syntax(String::from("reinhard filmic"))));
+        assert_eq!(eval(".mat.red", &v), Err(ExprError::Syntax(String::from(".mat.red"))));
+        assert_eq!(eval("", &v), Err(ExprError::Syntax(String::new())));
+
+        assert_eq!(eval(&format!("{}1{}", "(".repeat(10), ")".repeat(10)), &v), Ok(1.0));
+        assert_eq!(eval(&format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000)), &v), Err(ExprError::TooDeep));
+        assert_eq!(eval(&"-".repeat(100_000), &v), Err(ExprError::TooDeep));
+        assert_eq!(eval(&"2^".repeat(100_000), &v), Err(ExprError::TooDeep));
+
+        assert_eq!(ExprError::Arguments("sin", 1).to_string(), "sin takes 1 argument");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("i"));
+        assert!(is_identifier("_x2"));
+        assert!(!is_identifier("2x"));
+        assert!(!is_identifier("a-b"));
+        assert!(!is_identifier(""));
+    }
+}
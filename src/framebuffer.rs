@@ -0,0 +1,304 @@
+use crate::color::Color;
+use crate::linalg::Float;
+
+use std::io;
+use std::io::{Read,Write};
+
+/// A pixel rectangle; `x1` and `y1` are exclusive.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Rect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize
+}
+
+impl Rect {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    /// Whether the rectangle is non-empty and inside a `width` x `height`
+    /// image.
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        self.x0 < self.x1 && self.y0 < self.y1 && self.x1 <= width && self.y1 <= height
+    }
+
+    /// Cover the rectangle with tiles of at most `size` pixels square, row
+    /// by row.
+    pub fn tiles(&self, size: usize) -> Vec<Rect> {
+        let mut tiles = Vec::new();
+
+        for y0 in (self.y0..self.y1).step_by(size) {
+            for x0 in (self.x0..self.x1).step_by(size) {
+                tiles.push(Rect { x0, y0, x1: (x0 + size).min(self.x1), y1: (y0 + size).min(self.y1) });
+            }
+        }
+
+        tiles
+    }
+}
+
+/// Floating-point render target. Each pixel accumulates any number of
+/// radiance samples; `at()` returns their average.
+#[derive(Clone,Debug)]
+pub struct FrameBuffer {
+    pub width:  usize,
+    pub height: usize,
+    data:     Vec<Color>,
+    samples:  Vec<u32>,
+    metadata: Vec<(String,String)>
+}
+
+impl FrameBuffer {
+    pub fn new(w: usize, h: usize) -> FrameBuffer {
+        FrameBuffer {
+            width:    w,
+            height:   h,
+            data:     vec![Color::BLACK; w*h],
+            samples:  vec![0; w*h],
+            metadata: Vec::new()
+        }
+    }
+
+    pub fn at(&self, x: usize, y: usize) -> Color {
+        let i = y*self.width + x;
+        let n = self.samples[i];
+
+        if n == 0 {
+            return Color::BLACK
+        }
+
+        let c = self.data[i];
+        let f = 1.0 / (n as Float);
+
+        Color::new(c.r * f, c.g * f, c.b * f)
+    }
+
+    /// Replace all samples of a pixel with a single value.
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        let i = y*self.width + x;
+
+        self.data[i] = color;
+        self.samples[i] = 1
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, color: Color) {
+        let i = y*self.width + x;
+        let c = &mut self.data[i];
+
+        c.r += color.r;
+        c.g += color.g;
+        c.b += color.b;
+
+        self.samples[i] += 1
+    }
+
+    pub fn samples(&self, x: usize, y: usize) -> u32 {
+        self.samples[y*self.width + x]
+    }
+
+    pub fn set_metadata(&mut self, key: &str, value: &str) {
+        match self.metadata.iter_mut().find(|(k,_)| k == key) {
+            Some(entry) => entry.1 = String::from(value),
+            None => self.metadata.push((String::from(key), String::from(value)))
+        }
+    }
+
+    pub fn metadata(&self) -> &[(String,String)] {
+        &self.metadata
+    }
+
+    /// Copy of the pixels (sums and sample counts) inside `rect`.
+    pub fn crop(&self, rect: &Rect) -> FrameBuffer {
+        let mut out = FrameBuffer::new(rect.width(), rect.height());
+
+        for y in rect.y0..rect.y1 {
+            for x in rect.x0..rect.x1 {
+                let i = y*self.width + x;
+                let o = (y - rect.y0)*out.width + (x - rect.x0);
+
+                out.data[o] = self.data[i];
+                out.samples[o] = self.samples[i];
+            }
+        }
+
+        out
+    }
+
+    /// Overwrite the pixels starting at (x0,y0) with the contents of `src`.
+    pub fn blit(&mut self, x0: usize, y0: usize, src: &FrameBuffer) {
+        for y in 0..src.height {
+            for x in 0..src.width {
+                let i = (y0 + y)*self.width + x0 + x;
+                let o = y*src.width + x;
+
+                self.data[i] = src.data[o];
+                self.samples[i] = src.samples[o];
+            }
+        }
+    }
+
+    /// Dump the accumulated sums and sample counts losslessly (metadata is
+    /// not included). Sums take the build's float size, so only builds of
+    /// the same precision can read each other's dumps.
+    pub fn write_raw(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&(self.width as u32).to_le_bytes())?;
+        output.write_all(&(self.height as u32).to_le_bytes())?;
+
+        for (c, n) in self.data.iter().zip(self.samples.iter()) {
+            output.write_all(&c.r.to_le_bytes())?;
+            output.write_all(&c.g.to_le_bytes())?;
+            output.write_all(&c.b.to_le_bytes())?;
+            output.write_all(&n.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a dump of a `width` x `height` image. The size is checked
+    /// first, so that a corrupt one doesn't make it allocate without bounds.
+    pub fn read_raw(input: &mut dyn Read, width: usize, height: usize) -> io::Result<FrameBuffer> {
+        fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+            let mut buf = [0; 4];
+            input.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        }
+        fn read_float(input: &mut dyn Read) -> io::Result<Float> {
+            let mut buf = [0; std::mem::size_of::<Float>()];
+            input.read_exact(&mut buf)?;
+            Ok(Float::from_le_bytes(buf))
+        }
+
+        let w = read_u32(input)? as usize;
+        let h = read_u32(input)? as usize;
+
+        if (w, h) != (width, height) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("image size {}x{}, expected {}x{}", w, h, width, height)))
+        }
+
+        let mut fb = FrameBuffer::new(w, h);
+
+        for i in 0..w*h {
+            let r = read_float(input)?;
+            let g = read_float(input)?;
+            let b = read_float(input)?;
+
+            fb.data[i] = Color::new(r, g, b);
+            fb.samples[i] = read_u32(input)?;
+        }
+
+        Ok(fb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate() {
+        let mut fb = FrameBuffer::new(4, 2);
+
+        assert_eq!(fb.at(3, 1), Color::BLACK);
+        assert_eq!(fb.samples(3, 1), 0);
+
+        fb.add_sample(3, 1, Color::new(1.0, 0.0, 0.5));
+        fb.add_sample(3, 1, Color::new(0.0, 0.0, 0.5));
+
+        assert_eq!(fb.samples(3, 1), 2);
+        assert_eq!(fb.at(3, 1), Color::new(0.5, 0.0, 0.5));
+
+        fb.set(3, 1, Color::RED);
+
+        assert_eq!(fb.samples(3, 1), 1);
+        assert_eq!(fb.at(3, 1), Color::RED);
+    }
+
+    #[test]
+    fn tiles() {
+        let tiles = Rect { x0: 0, y0: 0, x1: 5, y1: 3 }.tiles(2);
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], Rect { x0: 0, y0: 0, x1: 2, y1: 2 });
+        assert_eq!(tiles[2], Rect { x0: 4, y0: 0, x1: 5, y1: 2 });
+        assert_eq!(tiles[5], Rect { x0: 4, y0: 2, x1: 5, y1: 3 });
+        assert_eq!(tiles.iter().map(|t| t.width() * t.height()).sum::<usize>(), 15);
+
+        let tiles = Rect { x0: 3, y0: 1, x1: 6, y1: 2 }.tiles(2);
+
+        assert_eq!(tiles, vec![ Rect { x0: 3, y0: 1, x1: 5, y1: 2 }, Rect { x0: 5, y0: 1, x1: 6, y1: 2 } ]);
+        assert!(tiles[0].fits(5, 2));
+        assert!(!tiles[1].fits(5, 2));
+        assert!(!Rect { x0: 2, y0: 0, x1: 2, y1: 1 }.fits(5, 2));
+    }
+
+    #[test]
+    fn crop_blit() {
+        let mut fb = FrameBuffer::new(4, 4);
+
+        fb.add_sample(1, 2, Color::RED);
+        fb.add_sample(1, 2, Color::BLACK);
+
+        let rect = Rect { x0: 1, y0: 1, x1: 3, y1: 4 };
+        let tile = fb.crop(&rect);
+
+        assert_eq!((tile.width, tile.height), (2, 3));
+        assert_eq!(tile.samples(0, 1), 2);
+        assert_eq!(tile.at(0, 1), Color::new(0.5, 0.0, 0.0));
+
+        let mut out = FrameBuffer::new(4, 4);
+        out.blit(rect.x0, rect.y0, &tile);
+
+        assert_eq!(out.at(1, 2), fb.at(1, 2));
+        assert_eq!(out.samples(1, 2), 2);
+        assert_eq!(out.samples(0, 0), 0);
+    }
+
+    #[test]
+    fn raw_roundtrip() {
+        let mut fb = FrameBuffer::new(3, 2);
+
+        fb.add_sample(0, 0, Color::new(0.1, 0.2, 0.3));
+        fb.add_sample(0, 0, Color::new(0.7, 0.2, 0.3));
+        fb.add_sample(2, 1, Color::new(-1.0, 1e10, 0.0));
+
+        let mut buf = Vec::new();
+        fb.write_raw(&mut buf).unwrap();
+
+        let rt = FrameBuffer::read_raw(&mut buf.as_slice(), 3, 2).unwrap();
+
+        assert_eq!(rt.width, 3);
+        assert_eq!(rt.height, 2);
+        assert_eq!(rt.data, fb.data);
+        assert_eq!(rt.samples, fb.samples);
+    }
+
+    #[test]
+    fn raw_wrong_size() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&100_000u32.to_le_bytes());
+        buf.extend_from_slice(&100_000u32.to_le_bytes());
+
+        let err = FrameBuffer::read_raw(&mut buf.as_slice(), 100_000, 99_999).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "image size 100000x100000, expected 100000x99999");
+    }
+
+    #[test]
+    fn metadata() {
+        let mut fb = FrameBuffer::new(1, 1);
+
+        fb.set_metadata("scene", "a.yaml");
+        fb.set_metadata("samples", "1");
+        fb.set_metadata("scene", "b.yaml");
+
+        assert_eq!(fb.metadata().len(), 2);
+        assert_eq!(fb.metadata()[0], (String::from("scene"), String::from("b.yaml")));
+    }
+}
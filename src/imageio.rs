@@ -0,0 +1,409 @@
+use crate::color::Color;
+use crate::framebuffer::FrameBuffer;
+use crate::linalg::Float;
+use crate::tonemap::ToneMap;
+
+use image::{ImageOutputFormat,RgbImage};
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use std::path::Path;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// `v` as the 32-bit float that the image formats store.
+#[allow(clippy::unnecessary_cast)] // Float is f32 unless built with f64
+fn f32_bytes(v: Float) -> [u8; 4] {
+    (v as f32).to_le_bytes()
+}
+
+/// Serializes a framebuffer into some image file format. Writers for
+/// low dynamic range formats run the pixels through the tone map first,
+/// floating-point formats store the linear values.
+pub trait ImageWriter {
+    fn write(&self, fb: &FrameBuffer, tonemap: &ToneMap, output: &mut dyn Write) -> Result<()>;
+}
+
+pub fn to_rgb_image(fb: &FrameBuffer, tonemap: &ToneMap) -> RgbImage {
+    RgbImage::from_fn(fb.width as u32, fb.height as u32, |x, y| {
+            image::Rgb::from(tonemap.map(fb.at(x as usize, y as usize)))
+        })
+}
+
+/// Netpbm PPM, either ASCII (P3) or binary (P6). Framebuffer metadata
+/// goes into header comments.
+pub struct PpmWriter {
+    pub binary: bool
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, fb: &FrameBuffer, tonemap: &ToneMap, output: &mut dyn Write) -> Result<()> {
+        writeln!(output, "{}", if self.binary { "P6" } else { "P3" })?;
+
+        for (key, value) in fb.metadata() {
+            writeln!(output, "# {}: {}", key, value)?;
+        }
+
+        write!(output, "{} {}\n255\n", fb.width, fb.height)?;
+
+        for y in 0..fb.height {
+            for x in 0..fb.width {
+                let image::Rgb([r, g, b]) = image::Rgb::from(tonemap.map(fb.at(x, y)));
+
+                if self.binary {
+                    output.write_all(&[r, g, b])?;
+                } else {
+                    writeln!(output, "{} {} {}", r, g, b)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Portable float map: little-endian 32-bit RGB, rows stored bottom to top.
+pub struct PfmWriter();
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, fb: &FrameBuffer, _: &ToneMap, output: &mut dyn Write) -> Result<()> {
+        write!(output, "PF\n{} {}\n-1.0\n", fb.width, fb.height)?;
+
+        for y in (0..fb.height).rev() {
+            for x in 0..fb.width {
+                let c: Color = fb.at(x, y);
+
+                for v in [ c.r, c.g, c.b ].iter() {
+                    output.write_all(&f32_bytes(*v))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Any 8-bit RGB format the image crate can encode (PNG, JPEG, BMP, ...).
+pub struct EncodedWriter {
+    pub format: ImageOutputFormat
+}
+
+impl ImageWriter for EncodedWriter {
+    fn write(&self, fb: &FrameBuffer, tonemap: &ToneMap, mut output: &mut dyn Write) -> Result<()> {
+        let img = image::DynamicImage::ImageRgb8(to_rgb_image(fb, tonemap));
+        img.write_to(&mut output, self.format.clone())?;
+
+        Ok(())
+    }
+}
+
+/// Uncompressed scanline OpenEXR with 32-bit float channels. Unlike the
+/// other formats it can hold several layers in one file: the unnamed
+/// layer becomes the R,G,B channels, others "<layer>.R" etc.
+pub struct ExrWriter();
+
+impl ExrWriter {
+    pub fn write_layers(&self, layers: &[(&str, &FrameBuffer)], output: &mut dyn Write) -> Result<()> {
+        let (width, height) = match layers.first() {
+            Some((_, fb)) => (fb.width, fb.height),
+            None => return Err("no layers to write".into())
+        };
+
+        if layers.iter().any(|(_, fb)| fb.width != width || fb.height != height) {
+            return Err("layer sizes differ".into())
+        }
+
+        // EXR wants channels in alphabetical order
+        let mut channels: Vec<(String, usize, usize)> = Vec::new();
+
+        for (l, (name, _)) in layers.iter().enumerate() {
+            for (c, cname) in [ "R", "G", "B" ].iter().enumerate() {
+                let chname = if name.is_empty() { cname.to_string() } else { format!("{}.{}", name, cname) };
+                channels.push((chname, l, c));
+            }
+        }
+
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        fn attribute(header: &mut Vec<u8>, name: &str, typename: &str, value: &[u8]) {
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(typename.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            header.extend_from_slice(value);
+        }
+
+        let mut header = Vec::new();
+
+        header.extend_from_slice(&[ 0x76, 0x2f, 0x31, 0x01 ]);
+        header.extend_from_slice(&2_i32.to_le_bytes());
+
+        let mut chlist = Vec::new();
+        for (chname, _, _) in channels.iter() {
+            chlist.extend_from_slice(chname.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&2_i32.to_le_bytes()); // FLOAT
+            chlist.extend_from_slice(&[ 0, 0, 0, 0 ]);      // pLinear, reserved
+            chlist.extend_from_slice(&1_i32.to_le_bytes()); // x sampling
+            chlist.extend_from_slice(&1_i32.to_le_bytes()); // y sampling
+        }
+        chlist.push(0);
+
+        let mut window = Vec::new();
+        for v in [ 0, 0, width as i32 - 1, height as i32 - 1 ].iter() {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+
+        attribute(&mut header, "channels", "chlist", &chlist);
+        attribute(&mut header, "compression", "compression", &[ 0 ]);
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        attribute(&mut header, "lineOrder", "lineOrder", &[ 0 ]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[ 0; 8 ]);
+        attribute(&mut header, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+        header.push(0);
+
+        let line_size = channels.len() * width * 4;
+        let table_end = header.len() + height * 8;
+
+        output.write_all(&header)?;
+
+        for y in 0..height {
+            let offset = table_end + y * (line_size + 8);
+            output.write_all(&(offset as u64).to_le_bytes())?;
+        }
+
+        for y in 0..height {
+            output.write_all(&(y as i32).to_le_bytes())?;
+            output.write_all(&(line_size as i32).to_le_bytes())?;
+
+            for (_, l, c) in channels.iter() {
+                let fb = layers[*l].1;
+
+                for x in 0..width {
+                    let col = fb.at(x, y);
+                    let v = [ col.r, col.g, col.b ][*c];
+
+                    output.write_all(&f32_bytes(v))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, fb: &FrameBuffer, _: &ToneMap, output: &mut dyn Write) -> Result<()> {
+        self.write_layers(&[ ("", fb) ], output)
+    }
+}
+
+pub fn writer_for_format(name: &str) -> Option<Box<dyn ImageWriter>> {
+    let writer: Box<dyn ImageWriter> = match name.to_lowercase().as_str() {
+        "ppm"        => Box::new(PpmWriter { binary: true }),
+        "ppm-ascii"  => Box::new(PpmWriter { binary: false }),
+        "pfm"        => Box::new(PfmWriter()),
+        "exr"        => Box::new(ExrWriter()),
+        "png"        => Box::new(EncodedWriter { format: ImageOutputFormat::Png }),
+        "jpg" | "jpeg" => Box::new(EncodedWriter { format: ImageOutputFormat::Jpeg(95) }),
+        "bmp"        => Box::new(EncodedWriter { format: ImageOutputFormat::Bmp }),
+        "tga"        => Box::new(EncodedWriter { format: ImageOutputFormat::Tga }),
+        _ => return None
+    };
+
+    Some(writer)
+}
+
+/// Pick a writer from the file name extension.
+pub fn writer_for_file(name: &str) -> Option<Box<dyn ImageWriter>> {
+    Path::new(name).extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(writer_for_format)
+}
+
+/// Derive the file name for an auxiliary pass: "render.png" becomes
+/// "render.depth.png".
+pub fn pass_file_name(name: &str, pass: &str) -> String {
+    let path = Path::new(name);
+
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            let file = format!("{}.{}.{}", stem.to_string_lossy(), pass, ext.to_string_lossy());
+            path.with_file_name(file).to_string_lossy().into_owned()
+        },
+        _ => format!("{}.{}", name, pass)
+    }
+}
+
+/// Derive the file name for one frame of an animation: "render.png"
+/// becomes "render_0001.png".
+pub fn frame_file_name(name: &str, frame: u32) -> String {
+    let path = Path::new(name);
+
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            let file = format!("{}_{:04}.{}", stem.to_string_lossy(), frame, ext.to_string_lossy());
+            path.with_file_name(file).to_string_lossy().into_owned()
+        },
+        _ => format!("{}_{:04}", name, frame)
+    }
+}
+
+pub fn save(fb: &FrameBuffer, tonemap: &ToneMap, name: &str, writer: &dyn ImageWriter) -> Result<()> {
+    let mut output = BufWriter::new(File::create(name)?);
+
+    writer.write(fb, tonemap, &mut output)?;
+    output.flush()?;
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_fb() -> FrameBuffer {
+        let mut fb = FrameBuffer::new(5, 3);
+
+        fb.set(0, 0, Color::new( 1.5, 0.0, 0.0));
+        fb.set(2, 1, Color::new( 0.0, 0.5, 0.0));
+        fb.set(4, 2, Color::new(-0.5, 0.0, 1.0));
+
+        fb
+    }
+
+    #[test]
+    fn write_ppm_ascii() {
+        let fb = make_fb();
+        let mut output = Vec::<u8>::new();
+
+        PpmWriter { binary: false }.write(&fb, &ToneMap::new(), &mut output).expect("Failed to write");
+
+        let expected = "P3
+5 3
+255
+255 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 127 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 0
+0 0 255
+";
+
+        assert_eq!(expected.as_bytes(), output);
+    }
+
+    #[test]
+    fn write_ppm_binary() {
+        let mut fb = make_fb();
+        fb.set_metadata("scene", "test.yaml");
+
+        let mut output = Vec::<u8>::new();
+
+        PpmWriter { binary: true }.write(&fb, &ToneMap::new(), &mut output).expect("Failed to write");
+
+        let header = "P6\n# scene: test.yaml\n5 3\n255\n";
+
+        assert_eq!(output.len(), header.len() + 5*3*3);
+        assert_eq!(&output[..header.len()], header.as_bytes());
+        assert_eq!(&output[header.len()..header.len()+3], &[255, 0, 0]);
+        assert_eq!(&output[output.len()-3..], &[0, 0, 255]);
+    }
+
+    #[test]
+    fn write_pfm() {
+        let fb = make_fb();
+        let mut output = Vec::<u8>::new();
+
+        PfmWriter().write(&fb, &ToneMap::new(), &mut output).expect("Failed to write");
+
+        let header = "PF\n5 3\n-1.0\n";
+
+        assert_eq!(output.len(), header.len() + 5*3*3*4);
+
+        // the first stored row is the bottom one, ending with pixel (4,2)
+        let first = &output[header.len()..];
+        assert_eq!(&first[4*3*4..4*3*4+4], &(-0.5_f32).to_le_bytes());
+        assert_eq!(&first[4*3*4+8..4*3*4+12], &(1.0_f32).to_le_bytes());
+    }
+
+    #[test]
+    fn write_exr_layers() {
+        let fb = make_fb();
+        let mut depth = FrameBuffer::new(5, 3);
+        depth.set(1, 2, Color::new(2.0, 2.0, 2.0));
+
+        let mut output = Vec::<u8>::new();
+
+        ExrWriter().write_layers(&[ ("", &fb), ("depth", &depth) ], &mut output).expect("Failed to write");
+
+        assert_eq!(&output[0..8], &[ 0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0 ]);
+
+        // header ends with a null byte right before the offset table
+        let line_size = 6 * 5 * 4;
+        let data_size = 3 * (8 + line_size);
+        let table_start = output.len() - data_size - 3*8;
+
+        assert_eq!(output[table_start-1], 0);
+
+        let first = u64::from_le_bytes([
+            output[table_start],   output[table_start+1], output[table_start+2], output[table_start+3],
+            output[table_start+4], output[table_start+5], output[table_start+6], output[table_start+7]
+        ]);
+
+        assert_eq!(first as usize, table_start + 3*8);
+
+        // channels are sorted: B, G, R, depth.B, depth.G, depth.R
+        let line2 = &output[output.len() - line_size ..];
+        assert_eq!(&line2[(2*5+4)*4..(2*5+4)*4+4], &(-0.5_f32).to_le_bytes());
+        assert_eq!(&line2[(3*5+1)*4..(3*5+1)*4+4], &(2.0_f32).to_le_bytes());
+    }
+
+    #[test]
+    fn writer_lookup() {
+        assert!(writer_for_file("render.png").is_some());
+        assert!(writer_for_file("render.PPM").is_some());
+        assert!(writer_for_file("render.pfm").is_some());
+        assert!(writer_for_file("render.xyz").is_none());
+        assert!(writer_for_file("render").is_none());
+        assert!(writer_for_format("ppm-ascii").is_some());
+    }
+
+    #[test]
+    fn pass_names() {
+        assert_eq!(pass_file_name("render.png", "depth"), "render.depth.png");
+        assert_eq!(pass_file_name("out/a.b.pfm", "normal"), "out/a.b.normal.pfm");
+        assert_eq!(pass_file_name("render", "albedo"), "render.albedo");
+
+        assert_eq!(frame_file_name("render.png", 1), "render_0001.png");
+        assert_eq!(frame_file_name("out/a.b.pfm", 12345), "out/a.b_12345.pfm");
+        assert_eq!(frame_file_name("render", 7), "render_0007");
+    }
+
+    #[test]
+    fn write_png() {
+        let fb = make_fb();
+        let mut output = Vec::<u8>::new();
+
+        EncodedWriter { format: ImageOutputFormat::Png }.write(&fb, &ToneMap::new(), &mut output).expect("Failed to write");
+
+        let img = image::load_from_memory(&output).unwrap().to_rgb8();
+
+        assert_eq!(img.dimensions(), (5, 3));
+        assert_eq!(img.get_pixel(2, 1), &image::Rgb([0, 127, 0]));
+    }
+}
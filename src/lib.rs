@@ -0,0 +1,58 @@
+//! A ray tracer following "The Ray Tracer Challenge".
+//!
+//! Scenes are read from YAML files (`sceneparser`) or put together in code
+//! with `SceneBuilder`. A `Camera` renders a `World` into a `FrameBuffer`,
+//! in one go, progressively (`Progressive`) or spread over several
+//! machines (`render_distributed`, with workers in `serve_worker`).
+//!
+//! The math core computes in `f32`; the `f64` feature switches `Float`,
+//! and with it vectors, rays, shapes and colors, to double precision.
+
+pub mod aov;
+pub mod builder;
+pub mod camera;
+pub mod color;
+pub mod cone;
+pub mod cube;
+pub mod cylinder;
+pub mod denoise;
+pub mod framebuffer;
+pub mod imageio;
+pub mod lighting;
+pub mod linalg;
+pub mod material;
+pub mod mesh;
+pub mod pattern;
+pub mod plane;
+pub mod progressive;
+pub mod ray;
+pub mod sceneparser;
+pub mod scenewriter;
+pub mod shape;
+pub mod sphere;
+pub mod tonemap;
+pub mod transform;
+pub mod world;
+
+// internals of the renderer and the command line tool
+pub(crate) mod animation;
+pub(crate) mod checkpoint;
+pub(crate) mod distributed;
+pub(crate) mod raytree;
+pub(crate) mod rng;
+pub(crate) mod scenecheck;
+
+mod bookscene;
+mod expr;
+
+pub use builder::{CameraBuilder,MaterialBuilder,PatternBuilder,SceneBuilder,ShapeBuilder};
+pub use camera::Camera;
+pub use checkpoint::{scene_hash,Checkpoint};
+pub use color::Color;
+pub use distributed::{render as render_distributed,serve_on as serve_worker};
+pub use framebuffer::FrameBuffer;
+pub use linalg::{Float,M4,Quat,V4};
+pub use progressive::Progressive;
+pub use raytree::{HitNode,RayKind,RayNode,ShadowRecord};
+pub use scenecheck::{check_scene,Diagnostic,Severity};
+pub use world::World;
@@ -18,7 +18,22 @@ pub fn lighting
         in_shadow: bool
     ) -> V4
 {
-    let mc = material.color_at(*point);
+    lighting_with_color(material.color_at(*point), material, light, point, eyev, normalv, in_shadow)
+}
+
+/// Like `lighting()`, but with the surface color `mc` given rather than
+/// taken from the material at `point`.
+pub fn lighting_with_color
+    (
+        mc:        Color,
+        material:  &Material,
+        light:     &LightSource,
+        point:     &V4,
+        eyev:      &V4,
+        normalv:   &V4,
+        in_shadow: bool
+    ) -> V4
+{
     let lc = light.intensity;
     let colorv = V4::new_vector(mc.r*lc.r, mc.g*lc.g, mc.b*lc.b);
 
@@ -50,6 +65,7 @@ pub fn lighting
 
 #[cfg(test)]
 mod tests {
+    use crate::linalg::consts;
     use crate::material::Texture;
     use super::*;
     use float_cmp::*;
@@ -83,7 +99,7 @@ mod tests {
 
     #[test]
     fn angled_lighting() {
-        let sq2half = 0.5 * std::f32::consts::SQRT_2;
+        let sq2half = 0.5 * consts::SQRT_2;
         let eyev = V4::new_vector(0.0, sq2half, sq2half);
         let normalv = V4::new_vector(0.0, 0.0, -1.0);
         let light = LightSource {
@@ -116,7 +132,7 @@ mod tests {
 
     #[test]
     fn reflect_light() {
-        let sq2half = 0.5 * std::f32::consts::SQRT_2;
+        let sq2half = 0.5 * consts::SQRT_2;
         let eyev = V4::new_vector(0.0, -sq2half, -sq2half);
         let normalv = V4::new_vector(0.0, 0.0, -1.0);
         let light = LightSource {
@@ -1,36 +1,69 @@
 use std::convert::{AsMut,AsRef,From};
 use std::ops::{Add,AddAssign,Sub,SubAssign,Mul,Neg};
 
+/// The scalar type of the math core: f32, or f64 with the `f64` feature.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+#[cfg(not(feature = "f64"))]
+pub type FloatMargin = float_cmp::F32Margin;
+#[cfg(feature = "f64")]
+pub type FloatMargin = float_cmp::F64Margin;
+
+/// Tolerance for geometric tests, and how far secondary rays start off a
+/// surface. About the square root of the machine epsilon, which is what
+/// survives a few operations' worth of rounding.
+#[cfg(not(feature = "f64"))]
+pub const EPSILON: Float = 0.0001;
+#[cfg(feature = "f64")]
+pub const EPSILON: Float = 1e-8;
+
+pub mod scalar;
+mod simd;
+
+use simd as ops;
+pub use simd::SIMD;
+
+// Aligned so that the SIMD code can load it in one go.
 #[derive(Clone,Copy,Debug,PartialEq,PartialOrd)]
-pub struct V4 (f32, f32, f32, f32);
+#[repr(C, align(16))]
+pub struct V4 (Float, Float, Float, Float);
 
 impl V4 {
-    pub fn new_point(x: f32, y: f32, z: f32) -> V4 {
+    pub fn new_point(x: Float, y: Float, z: Float) -> V4 {
         V4(x, y, z, 1.0)
     }
 
-    pub fn new_vector(x: f32, y: f32, z: f32) -> V4 {
+    pub fn new_vector(x: Float, y: Float, z: Float) -> V4 {
         V4(x, y, z, 0.0)
     }
 
-    pub fn x(&self) -> f32 {
+    pub fn x(&self) -> Float {
         self.0
     }
 
-    pub fn y(&self) -> f32 {
+    pub fn y(&self) -> Float {
         self.1
     }
 
-    pub fn z(&self) -> f32 {
+    pub fn z(&self) -> Float {
         self.2
     }
 
-    pub fn w(&self) -> f32 {
+    pub fn w(&self) -> Float {
         self.3
     }
 
-    pub fn magnitude(&self) -> f32 {
-        (self.0*self.0 + self.1*self.1 + self.2*self.2 + self.3*self.3).sqrt()
+    #[inline]
+    pub fn magnitude(&self) -> Float {
+        ops::dot(self, self).sqrt()
     }
 
     pub fn normalize(&self) -> V4 {
@@ -38,12 +71,14 @@ impl V4 {
         V4(self.0/m, self.1/m, self.2/m, self.3/m)
     }
 
-    pub fn dot(a: &V4, b: &V4) -> f32 {
-        a.0*b.0 + a.1*b.1 + a.2*b.2 + a.3*b.3
+    #[inline]
+    pub fn dot(a: &V4, b: &V4) -> Float {
+        ops::dot(a, b)
     }
 
+    #[inline]
     pub fn cross(a: &V4, b: &V4) -> V4 {
-        V4::new_vector(a.1*b.2-a.2*b.1, a.2*b.0-a.0*b.2, a.0*b.1-a.1*b.0)
+        ops::cross(a, b)
     }
 
     pub fn reflect(a: V4, b: V4) -> V4 {
@@ -54,65 +89,56 @@ impl V4 {
 impl Add for V4 {
     type Output = Self;
 
+    #[inline]
     fn add(self, other: Self) -> Self {
-        V4(
-            self.0 + other.0,
-            self.1 + other.1,
-            self.2 + other.2,
-            self.3 + other.3
-        )
+        ops::add(&self, &other)
     }
 }
 
 impl AddAssign for V4 {
+    #[inline]
     fn add_assign(&mut self, rhs: Self) {
-        self.0 += rhs.0;
-        self.1 += rhs.1;
-        self.2 += rhs.2;
-        self.3 += rhs.3;
+        *self = ops::add(self, &rhs)
     }
 }
 
 impl Sub for V4 {
     type Output = Self;
 
+    #[inline]
     fn sub(self, other: Self) -> Self {
-        V4(
-            self.0 - other.0,
-            self.1 - other.1,
-            self.2 - other.2,
-            self.3 - other.3
-        )
+        ops::sub(&self, &other)
     }
 }
 
 impl SubAssign for V4 {
+    #[inline]
     fn sub_assign(&mut self, rhs: Self) {
-        self.0 -= rhs.0;
-        self.1 -= rhs.1;
-        self.2 -= rhs.2;
-        self.3 -= rhs.3;
+        *self = ops::sub(self, &rhs)
     }
 }
 
-impl Mul<f32> for V4 {
+impl Mul<Float> for V4 {
     type Output = Self;
 
-    fn mul(self, f: f32) -> Self {
-        V4(f * self.0, f * self.1, f * self.2, f * self.3)
+    #[inline]
+    fn mul(self, f: Float) -> Self {
+        ops::scale(&self, f)
     }
 }
 
 impl Neg for V4 {
     type Output = Self;
 
+    #[inline]
     fn neg(self) -> Self {
-        V4(-self.0, -self.1, -self.2, -self.3)
+        ops::neg(&self)
     }
 }
 
 #[derive(Clone,Copy,Debug,PartialEq,PartialOrd)]
-pub struct M4([f32; 16]);
+#[repr(C, align(16))]
+pub struct M4([Float; 16]);
 
 impl M4 {
     pub fn identity() -> M4 {
@@ -124,11 +150,11 @@ impl M4 {
         ])
     }
 
-    pub fn set(&mut self, row: usize, col: usize, val: f32) {
+    pub fn set(&mut self, row: usize, col: usize, val: Float) {
         self.0[4*row+col] = val
     }
 
-    pub fn at(&self, row: usize, col: usize) -> f32 {
+    pub fn at(&self, row: usize, col: usize) -> Float {
         self.0[4*row+col]
     }
 
@@ -141,7 +167,7 @@ impl M4 {
         ])
     }
 
-    fn minor(&self, row: usize, col: usize) -> f32 {
+    fn minor(&self, row: usize, col: usize) -> Float {
         fn skip(i: usize) -> [usize;3] {
             match i {
                 0  => [1,2,3],
@@ -170,12 +196,12 @@ impl M4 {
         (a*e*i) + (b*f*g) + (c*d*h) - (c*e*g) - (b*d*i) - (a*f*h)
     }
 
-    fn cofactor(&self, row: usize, col: usize) -> f32 {
-        let f = if (row+col) % 2 == 0 { 1.0_f32 } else { -1.0_f32 };
+    fn cofactor(&self, row: usize, col: usize) -> Float {
+        let f: Float = if (row+col) % 2 == 0 { 1.0 } else { -1.0 };
         f * self.minor(row,col)
     }
 
-    pub fn determinant(&self) -> f32 {
+    pub fn determinant(&self) -> Float {
           self.0[0] * self.minor(0,0)
         - self.0[1] * self.minor(0,1)
         + self.0[2] * self.minor(0,2)
@@ -195,61 +221,91 @@ impl M4 {
         m
     }
 
+    #[inline]
     pub fn mvmul(m: &M4, v: V4) -> V4 {
-        V4(
-            m.0[0*4+0] * v.0 +
-            m.0[0*4+1] * v.1 +
-            m.0[0*4+2] * v.2 +
-            m.0[0*4+3] * v.3,
-
-            m.0[1*4+0] * v.0 +
-            m.0[1*4+1] * v.1 +
-            m.0[1*4+2] * v.2 +
-            m.0[1*4+3] * v.3,
-
-            m.0[2*4+0] * v.0 +
-            m.0[2*4+1] * v.1 +
-            m.0[2*4+2] * v.2 +
-            m.0[2*4+3] * v.3,
-
-            m.0[3*4+0] * v.0 +
-            m.0[3*4+1] * v.1 +
-            m.0[3*4+2] * v.2 +
-            m.0[3*4+3] * v.3
-        )
+        ops::mvmul(m, &v)
+    }
+
+    /// `self.transpose() * v`. With the transpose of a matrix kept at
+    /// hand, this applies the matrix column by column, which suits SIMD.
+    #[inline]
+    pub fn transpose_mul(&self, v: V4) -> V4 {
+        ops::tmvmul(self, &v)
+    }
+
+    /// Element-wise linear interpolation: `a` at s = 0, `b` at s = 1.
+    pub fn lerp(a: &M4, b: &M4, s: Float) -> M4 {
+        let mut c = *a;
+
+        for (x, y) in c.0.iter_mut().zip(b.0.iter()) {
+            *x += (y - *x) * s
+        }
+
+        c
     }
 
+    #[inline]
     pub fn mmul(a: &M4, b: &M4) -> M4 {
-        let mut c = M4([ 0.0; 16 ]);
-
-        for y in 0..4 {
-            for x in 0..4 {
-                c.0[4*y+x] =
-                    a.0[4*y+0] * b.0[0*4+x] +
-                    a.0[4*y+1] * b.0[1*4+x] +
-                    a.0[4*y+2] * b.0[2*4+x] +
-                    a.0[4*y+3] * b.0[3*4+x]
+        ops::mmul(a, b)
+    }
+
+    /// Split an affine matrix into translation, rotation and scale, so
+    /// that it is translation * rotation * scale. A negative determinant
+    /// goes into the x scale. None if the matrix is singular, projective
+    /// or shears.
+    pub fn decompose(&self) -> Option<(V4, Quat, V4)> {
+        if self.at(3, 0) != 0.0 || self.at(3, 1) != 0.0 || self.at(3, 2) != 0.0 || self.at(3, 3) != 1.0 {
+            return None
+        }
+
+        let mut cols = [ 0, 1, 2 ].map(|c| V4::new_vector(self.at(0, c), self.at(1, c), self.at(2, c)));
+        let mut scale = cols.map(|c| c.magnitude());
+
+        if scale.contains(&0.0) {
+            return None
+        }
+        for (c, s) in cols.iter_mut().zip(scale.iter()) {
+            *c = *c * (1.0 / s)
+        }
+        for (a, b) in [ (0, 1), (0, 2), (1, 2) ].iter() {
+            if V4::dot(&cols[*a], &cols[*b]).abs() > EPSILON {
+                return None
             }
         }
+        if V4::dot(&V4::cross(&cols[0], &cols[1]), &cols[2]) < 0.0 {
+            scale[0] = -scale[0];
+            cols[0] = -cols[0];
+        }
 
-        c
+        let rotation = [
+            cols[0].x(), cols[1].x(), cols[2].x(), 0.0,
+            cols[0].y(), cols[1].y(), cols[2].y(), 0.0,
+            cols[0].z(), cols[1].z(), cols[2].z(), 0.0,
+            0.0,         0.0,         0.0,         1.0
+        ].into();
+
+        Some((
+            V4::new_vector(self.at(0, 3), self.at(1, 3), self.at(2, 3)),
+            Quat::from_m4(&rotation),
+            V4::new_vector(scale[0], scale[1], scale[2])
+        ))
     }
 }
 
-impl AsRef<[f32;16]> for M4 {
-    fn as_ref(&self) -> &[f32;16] {
+impl AsRef<[Float;16]> for M4 {
+    fn as_ref(&self) -> &[Float;16] {
         &self.0
     }
 }
 
-impl AsMut<[f32;16]> for M4 {
-    fn as_mut(&mut self) -> &mut [f32;16] {
+impl AsMut<[Float;16]> for M4 {
+    fn as_mut(&mut self) -> &mut [Float;16] {
         &mut self.0
     }
 }
 
-impl From<[f32; 16]> for M4 {
-    fn from(from: [f32; 16]) -> M4 {
+impl From<[Float; 16]> for M4 {
+    fn from(from: [Float; 16]) -> M4 {
         M4(from)
     }
 }
@@ -257,6 +313,7 @@ impl From<[f32; 16]> for M4 {
 impl Mul<V4> for M4 {
     type Output = V4;
 
+    #[inline]
     fn mul(self, v: V4) -> V4 {
         M4::mvmul(&self, v)
     }
@@ -265,17 +322,160 @@ impl Mul<V4> for M4 {
 impl Mul<V4> for &M4 {
     type Output = V4;
 
+    #[inline]
     fn mul(self, v: V4) -> V4 {
         M4::mvmul(self, v)
     }
 }
 
+/// A rotation as a unit quaternion w + xi + yj + zk.
+#[derive(Clone,Copy,Debug,PartialEq,PartialOrd)]
+pub struct Quat (Float, Float, Float, Float);
+
+impl Quat {
+    pub fn new(w: Float, x: Float, y: Float, z: Float) -> Quat {
+        Quat(w, x, y, z)
+    }
+
+    pub fn identity() -> Quat {
+        Quat(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Rotation by `rad` around `axis`, which need not be normalized.
+    pub fn from_axis_angle(axis: &V4, rad: Float) -> Quat {
+        let a = axis.normalize();
+        let (s, c) = (rad / 2.0).sin_cos();
+        Quat(c, a.x() * s, a.y() * s, a.z() * s)
+    }
+
+    /// The rotation in the upper 3x3 of `m`, which must be orthonormal.
+    pub fn from_m4(m: &M4) -> Quat {
+        let trace = m.at(0, 0) + m.at(1, 1) + m.at(2, 2);
+
+        // Start from the largest component, for precision.
+        let q = if trace > 0.0 {
+            let s = 2.0 * (1.0 + trace).sqrt();
+            Quat(s / 4.0, (m.at(2, 1) - m.at(1, 2)) / s, (m.at(0, 2) - m.at(2, 0)) / s, (m.at(1, 0) - m.at(0, 1)) / s)
+        } else if m.at(0, 0) > m.at(1, 1) && m.at(0, 0) > m.at(2, 2) {
+            let s = 2.0 * (1.0 + m.at(0, 0) - m.at(1, 1) - m.at(2, 2)).sqrt();
+            Quat((m.at(2, 1) - m.at(1, 2)) / s, s / 4.0, (m.at(0, 1) + m.at(1, 0)) / s, (m.at(0, 2) + m.at(2, 0)) / s)
+        } else if m.at(1, 1) > m.at(2, 2) {
+            let s = 2.0 * (1.0 + m.at(1, 1) - m.at(0, 0) - m.at(2, 2)).sqrt();
+            Quat((m.at(0, 2) - m.at(2, 0)) / s, (m.at(0, 1) + m.at(1, 0)) / s, s / 4.0, (m.at(1, 2) + m.at(2, 1)) / s)
+        } else {
+            let s = 2.0 * (1.0 + m.at(2, 2) - m.at(0, 0) - m.at(1, 1)).sqrt();
+            Quat((m.at(1, 0) - m.at(0, 1)) / s, (m.at(0, 2) + m.at(2, 0)) / s, (m.at(1, 2) + m.at(2, 1)) / s, s / 4.0)
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_m4(&self) -> M4 {
+        let Quat(w, x, y, z) = *self;
+
+        M4([
+            1.0 - 2.0*(y*y + z*z), 2.0*(x*y - w*z),       2.0*(x*z + w*y),       0.0,
+            2.0*(x*y + w*z),       1.0 - 2.0*(x*x + z*z), 2.0*(y*z - w*x),       0.0,
+            2.0*(x*z - w*y),       2.0*(y*z + w*x),       1.0 - 2.0*(x*x + y*y), 0.0,
+            0.0,                   0.0,                   0.0,                   1.0
+        ])
+    }
+
+    /// Axis and angle in radians, with the angle between 0 and pi. The
+    /// axis is +x for no rotation.
+    pub fn to_axis_angle(&self) -> (V4, Float) {
+        let q = if self.0 < 0.0 { -*self } else { *self };
+        let s = (q.1*q.1 + q.2*q.2 + q.3*q.3).sqrt();
+
+        if s == 0.0 {
+            return (V4::new_vector(1.0, 0.0, 0.0), 0.0)
+        }
+
+        (V4::new_vector(q.1 / s, q.2 / s, q.3 / s), 2.0 * s.atan2(q.0))
+    }
+
+    pub fn w(&self) -> Float {
+        self.0
+    }
+
+    pub fn x(&self) -> Float {
+        self.1
+    }
+
+    pub fn y(&self) -> Float {
+        self.2
+    }
+
+    pub fn z(&self) -> Float {
+        self.3
+    }
+
+    pub fn dot(a: &Quat, b: &Quat) -> Float {
+        a.0*b.0 + a.1*b.1 + a.2*b.2 + a.3*b.3
+    }
+
+    pub fn normalize(&self) -> Quat {
+        let m = Quat::dot(self, self).sqrt();
+        Quat(self.0/m, self.1/m, self.2/m, self.3/m)
+    }
+
+    /// The inverse rotation.
+    pub fn conjugate(&self) -> Quat {
+        Quat(self.0, -self.1, -self.2, -self.3)
+    }
+
+    pub fn rotate(&self, v: V4) -> V4 {
+        self.to_m4() * v
+    }
+
+    /// Spherical linear interpolation along the shorter arc: `a` at s = 0,
+    /// `b` at s = 1, turning at a constant rate in between.
+    pub fn slerp(a: &Quat, b: &Quat, s: Float) -> Quat {
+        let mut d = Quat::dot(a, b);
+        let b = if d < 0.0 { d = -d; -*b } else { *b };
+
+        let (fa, fb) = if d > 0.9995 {
+            // Close enough that lerping doesn't show, and sin(theta) would
+            // be tiny.
+            (1.0 - s, s)
+        } else {
+            let theta = d.acos();
+            (((1.0 - s) * theta).sin() / theta.sin(), (s * theta).sin() / theta.sin())
+        };
+
+        Quat(fa*a.0 + fb*b.0, fa*a.1 + fb*b.1, fa*a.2 + fb*b.2, fa*a.3 + fb*b.3).normalize()
+    }
+}
+
+/// Composition: `a * b` rotates by `b` first, like matrices.
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, b: Self) -> Self {
+        let a = self;
+        Quat(
+            a.0*b.0 - a.1*b.1 - a.2*b.2 - a.3*b.3,
+            a.0*b.1 + a.1*b.0 + a.2*b.3 - a.3*b.2,
+            a.0*b.2 - a.1*b.3 + a.2*b.0 + a.3*b.1,
+            a.0*b.3 + a.1*b.2 - a.2*b.1 + a.3*b.0
+        )
+    }
+}
+
+impl Neg for Quat {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Quat(-self.0, -self.1, -self.2, -self.3)
+    }
+}
+
 use float_cmp::ApproxEq;
 
 impl ApproxEq for V4 {
-    type Margin = float_cmp::F32Margin;
+    type Margin = FloatMargin;
 
-    fn approx_eq<T: Into<float_cmp::F32Margin>>(self, other: Self, margin: T) -> bool {
+    fn approx_eq<T: Into<FloatMargin>>(self, other: Self, margin: T) -> bool {
         let margin = margin.into();
 
         self.0.approx_eq(other.0, margin) &&
@@ -285,10 +485,25 @@ impl ApproxEq for V4 {
     }
 }
 
+/// Equal as rotations: q and -q are the same one.
+impl ApproxEq for Quat {
+    type Margin = FloatMargin;
+
+    fn approx_eq<T: Into<FloatMargin>>(self, other: Self, margin: T) -> bool {
+        let margin = margin.into();
+        let other = if Quat::dot(&self, &other) < 0.0 { -other } else { other };
+
+        self.0.approx_eq(other.0, margin) &&
+        self.1.approx_eq(other.1, margin) &&
+        self.2.approx_eq(other.2, margin) &&
+        self.3.approx_eq(other.3, margin)
+    }
+}
+
 impl<'a> ApproxEq for &'a M4 {
-    type Margin = float_cmp::F32Margin;
+    type Margin = FloatMargin;
 
-    fn approx_eq<T: Into<float_cmp::F32Margin>>(self, other: Self, margin: T) -> bool {
+    fn approx_eq<T: Into<FloatMargin>>(self, other: Self, margin: T) -> bool {
         let margin = margin.into();
 
         for row in 0..4 {
@@ -332,7 +547,7 @@ mod tests {
     #[test]
     fn magnitude() {
         assert_eq!(V4::new_vector(1.0, 0.0, 0.0).magnitude(), 1.0);
-        assert_eq!(V4::new_vector(-1.0, -2.0, -3.0).magnitude(), (14.0_f32).sqrt());
+        assert_eq!(V4::new_vector(-1.0, -2.0, -3.0).magnitude(), Float::sqrt(14.0));
     }
 
     #[test]
@@ -370,7 +585,7 @@ mod tests {
         let n = V4::new_vector(0.0, 1.0, 0.0);
         assert_eq!(V4::reflect(v, n), V4::new_vector(1.0, 1.0, 0.0));
 
-        let sq2half = std::f32::consts::SQRT_2 / 2.0;
+        let sq2half = consts::SQRT_2 / 2.0;
 
         let v = V4::new_vector(0.0, -1.0, 0.0);
         let n = V4::new_vector(sq2half, sq2half, 0.0);
@@ -486,10 +701,117 @@ mod tests {
 
         // for r in 0..4 {
         //     for c in 0..4 {
-        //         assert!(approx_eq!(f32, m.at(r,c), result.at(r,c), epsilon = 0.0001), "m[{},{}] = {} (expected {})", r,c,m.at(r,c),result.at(r,c))
+        //         assert!(approx_eq!(Float, m.at(r,c), result.at(r,c), epsilon = 0.0001), "m[{},{}] = {} (expected {})", r,c,m.at(r,c),result.at(r,c))
         //     }
         // }
 
         assert!(approx_eq!(&M4, &m, &result, epsilon = 0.0001));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lerp() {
+        let a = M4::identity();
+        let mut b = M4([ 0.0; 16 ]);
+        b.set(0, 3, 4.0);
+
+        let m = M4::lerp(&a, &b, 0.25);
+
+        assert_eq!(m.at(0, 0), 0.75);
+        assert_eq!(m.at(0, 3), 1.0);
+        assert_eq!(m.at(1, 2), 0.0);
+        assert_eq!(M4::lerp(&a, &b, 1.0), b);
+    }
+
+    #[test]
+    fn quat_matrix() {
+        let q = Quat::from_axis_angle(&V4::new_vector(0.0, 0.0, 1.0), consts::FRAC_PI_2);
+        let v = V4::new_vector(1.0, 0.0, 0.0);
+
+        assert!(approx_eq!(V4, q.rotate(v), V4::new_vector(0.0, 1.0, 0.0), epsilon = 0.0001));
+
+        // back and forth through a matrix, for each branch of from_m4
+        for angle in [ 0.0, 0.5, 3.0, -3.0 ].iter() {
+            for axis in [ (1.0, 0.2, 0.1), (0.1, 1.0, -0.2), (0.2, 0.1, 1.0) ].iter() {
+                let q = Quat::from_axis_angle(&V4::new_vector(axis.0, axis.1, axis.2), *angle);
+                assert!(approx_eq!(Quat, Quat::from_m4(&q.to_m4()), q, epsilon = 0.0001));
+            }
+        }
+
+        let (axis, angle) = q.to_axis_angle();
+        assert!(approx_eq!(V4, axis, V4::new_vector(0.0, 0.0, 1.0), epsilon = 0.0001));
+        assert!(approx_eq!(Float, angle, consts::FRAC_PI_2, epsilon = 0.0001));
+    }
+
+    #[test]
+    fn quat_compose() {
+        let a = Quat::from_axis_angle(&V4::new_vector(1.0, 0.0, 0.0), 0.3);
+        let b = Quat::from_axis_angle(&V4::new_vector(0.0, 1.0, 0.0), 0.4);
+
+        assert!(approx_eq!(&M4, &(a * b).to_m4(), &M4::mmul(&a.to_m4(), &b.to_m4()), epsilon = 0.0001));
+        assert!(approx_eq!(Quat, a * a.conjugate(), Quat::identity(), epsilon = 0.0001));
+    }
+
+    #[test]
+    fn slerp() {
+        let axis = V4::new_vector(0.0, 1.0, 0.0);
+        let a = Quat::from_axis_angle(&axis, 0.0);
+        let b = Quat::from_axis_angle(&axis, 2.0);
+
+        assert!(approx_eq!(Quat, Quat::slerp(&a, &b, 0.25), Quat::from_axis_angle(&axis, 0.5), epsilon = 0.0001));
+        assert!(approx_eq!(Quat, Quat::slerp(&a, &b, 1.0), b, epsilon = 0.0001));
+
+        // the short way round, even when b is given as -b
+        assert!(approx_eq!(Quat, Quat::slerp(&a, &-b, 0.5), Quat::from_axis_angle(&axis, 1.0), epsilon = 0.0001));
+    }
+
+    #[test]
+    fn decompose() {
+        let q = Quat::from_axis_angle(&V4::new_vector(1.0, 2.0, 3.0), 0.8);
+        let rs = M4::mmul(&q.to_m4(), &[
+            -2.0, 0.0, 0.0, 0.0,
+             0.0, 3.0, 0.0, 0.0,
+             0.0, 0.0, 0.5, 0.0,
+             0.0, 0.0, 0.0, 1.0
+        ].into());
+        let mut m = rs;
+        m.set(0, 3, 4.0);
+        m.set(1, 3, -1.0);
+
+        let (t, r, s) = m.decompose().unwrap();
+
+        assert!(approx_eq!(V4, t, V4::new_vector(4.0, -1.0, 0.0), epsilon = 0.0001));
+        assert!(approx_eq!(Quat, r, q, epsilon = 0.0001));
+        assert!(approx_eq!(V4, s, V4::new_vector(-2.0, 3.0, 0.5), epsilon = 0.0001));
+
+        let mut shear = M4::identity();
+        shear.set(0, 1, 1.0);
+        assert_eq!(shear.decompose(), None);
+
+        let mut projective = M4::identity();
+        projective.set(3, 2, 1.0);
+        assert_eq!(projective.decompose(), None);
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let m: M4 = [
+            0.3, -1.7,  2.1,  4.0,
+            1.1,  0.9, -0.2, -3.5,
+           -2.6,  0.4,  1.3,  0.7,
+            0.0,  0.1,  0.0,  1.0
+        ].into();
+        let a = V4::new_point(0.1, -2.3, 7.9);
+        let b = V4::new_vector(-0.7, 1.3, 0.0001);
+
+        assert_eq!(a + b, scalar::add(&a, &b));
+        assert_eq!(a - b, scalar::sub(&a, &b));
+        assert_eq!(a * 0.3, scalar::scale(&a, 0.3));
+        assert_eq!(-a, scalar::neg(&a));
+        assert_eq!(V4::dot(&a, &b), scalar::dot(&a, &b));
+        assert_eq!(V4::cross(&a, &b), scalar::cross(&a, &b));
+        assert_eq!(m * a, scalar::mvmul(&m, &a));
+        assert_eq!(m.transpose_mul(a), scalar::tmvmul(&m, &a));
+        assert_eq!(m.transpose().transpose_mul(a), m * a);
+        assert_eq!(M4::mmul(&m, &m.invert()), scalar::mmul(&m, &m.invert()));
+    }
+}
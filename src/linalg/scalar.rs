@@ -0,0 +1,103 @@
+//! Plain versions of the `V4` and `M4` arithmetic. They are what runs
+//! where there is no SIMD version (other targets, f64 builds, or the
+//! `scalar` feature), and what the SIMD versions are measured against.
+
+use super::{Float,M4,V4};
+
+#[inline]
+pub fn add(a: &V4, b: &V4) -> V4 {
+    V4(a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3)
+}
+
+#[inline]
+pub fn sub(a: &V4, b: &V4) -> V4 {
+    V4(a.0 - b.0, a.1 - b.1, a.2 - b.2, a.3 - b.3)
+}
+
+#[inline]
+pub fn scale(a: &V4, f: Float) -> V4 {
+    V4(f * a.0, f * a.1, f * a.2, f * a.3)
+}
+
+#[inline]
+pub fn neg(a: &V4) -> V4 {
+    V4(-a.0, -a.1, -a.2, -a.3)
+}
+
+#[inline]
+pub fn dot(a: &V4, b: &V4) -> Float {
+    a.0*b.0 + a.1*b.1 + a.2*b.2 + a.3*b.3
+}
+
+#[inline]
+pub fn cross(a: &V4, b: &V4) -> V4 {
+    V4::new_vector(a.1*b.2-a.2*b.1, a.2*b.0-a.0*b.2, a.0*b.1-a.1*b.0)
+}
+
+#[inline]
+pub fn mvmul(m: &M4, v: &V4) -> V4 {
+    V4(
+        m.0[0*4+0] * v.0 +
+        m.0[0*4+1] * v.1 +
+        m.0[0*4+2] * v.2 +
+        m.0[0*4+3] * v.3,
+
+        m.0[1*4+0] * v.0 +
+        m.0[1*4+1] * v.1 +
+        m.0[1*4+2] * v.2 +
+        m.0[1*4+3] * v.3,
+
+        m.0[2*4+0] * v.0 +
+        m.0[2*4+1] * v.1 +
+        m.0[2*4+2] * v.2 +
+        m.0[2*4+3] * v.3,
+
+        m.0[3*4+0] * v.0 +
+        m.0[3*4+1] * v.1 +
+        m.0[3*4+2] * v.2 +
+        m.0[3*4+3] * v.3
+    )
+}
+
+/// `mt.transpose() * v`, summed in the same order as `mvmul`.
+#[inline]
+pub fn tmvmul(mt: &M4, v: &V4) -> V4 {
+    V4(
+        mt.0[0*4+0] * v.0 +
+        mt.0[1*4+0] * v.1 +
+        mt.0[2*4+0] * v.2 +
+        mt.0[3*4+0] * v.3,
+
+        mt.0[0*4+1] * v.0 +
+        mt.0[1*4+1] * v.1 +
+        mt.0[2*4+1] * v.2 +
+        mt.0[3*4+1] * v.3,
+
+        mt.0[0*4+2] * v.0 +
+        mt.0[1*4+2] * v.1 +
+        mt.0[2*4+2] * v.2 +
+        mt.0[3*4+2] * v.3,
+
+        mt.0[0*4+3] * v.0 +
+        mt.0[1*4+3] * v.1 +
+        mt.0[2*4+3] * v.2 +
+        mt.0[3*4+3] * v.3
+    )
+}
+
+#[inline]
+pub fn mmul(a: &M4, b: &M4) -> M4 {
+    let mut c = M4([ 0.0; 16 ]);
+
+    for y in 0..4 {
+        for x in 0..4 {
+            c.0[4*y+x] =
+                a.0[4*y+0] * b.0[0*4+x] +
+                a.0[4*y+1] * b.0[1*4+x] +
+                a.0[4*y+2] * b.0[2*4+x] +
+                a.0[4*y+3] * b.0[3*4+x]
+        }
+    }
+
+    c
+}
@@ -0,0 +1,208 @@
+//! SSE2 (x86_64) and NEON (aarch64) versions of the hot `V4` and `M4`
+//! operations, for f32 builds. Both instruction sets are part of the base
+//! architecture, so no runtime detection is needed. Sums are formed in
+//! the same order as in `scalar`, so results match it bit for bit.
+//! Everywhere else, and with the `scalar` feature, this is `scalar`.
+
+#[cfg(all(not(feature = "f64"), not(feature = "scalar"), target_arch = "x86_64"))]
+pub use self::sse::*;
+#[cfg(all(not(feature = "f64"), not(feature = "scalar"), target_arch = "aarch64"))]
+pub use self::neon::*;
+#[cfg(not(all(not(feature = "f64"), not(feature = "scalar"), any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub use super::scalar::*;
+
+/// Whether the `V4` and `M4` arithmetic uses SIMD instructions.
+pub const SIMD: bool = cfg!(all(not(feature = "f64"), not(feature = "scalar"),
+                                any(target_arch = "x86_64", target_arch = "aarch64")));
+
+#[cfg(all(not(feature = "f64"), not(feature = "scalar"), target_arch = "x86_64"))]
+mod sse {
+    use super::super::{M4,V4};
+    use std::arch::x86_64::*;
+
+    // A horizontal sum for the dot product, or transposing the matrix
+    // for its columns, costs as much as the multiplications it saves.
+    // Matrices applied often should keep their transpose for `tmvmul`.
+    pub use super::super::scalar::{dot,mvmul};
+
+    // V4 and M4 are 16-byte aligned, so whole vectors and matrix rows
+    // can use aligned loads and stores.
+
+    #[inline(always)]
+    fn load(v: &V4) -> __m128 {
+        unsafe { std::mem::transmute::<V4, __m128>(*v) }
+    }
+
+    #[inline(always)]
+    fn load_row(m: &M4, row: usize) -> __m128 {
+        unsafe { _mm_load_ps((m as *const M4 as *const f32).add(4 * row)) }
+    }
+
+    #[inline(always)]
+    fn store(r: __m128) -> V4 {
+        unsafe { std::mem::transmute::<__m128, V4>(r) }
+    }
+
+    #[inline]
+    pub fn add(a: &V4, b: &V4) -> V4 {
+        unsafe { store(_mm_add_ps(load(a), load(b))) }
+    }
+
+    #[inline]
+    pub fn sub(a: &V4, b: &V4) -> V4 {
+        unsafe { store(_mm_sub_ps(load(a), load(b))) }
+    }
+
+    #[inline]
+    pub fn scale(a: &V4, f: f32) -> V4 {
+        unsafe { store(_mm_mul_ps(_mm_set1_ps(f), load(a))) }
+    }
+
+    #[inline]
+    pub fn neg(a: &V4) -> V4 {
+        unsafe { store(_mm_xor_ps(load(a), _mm_set1_ps(-0.0))) }
+    }
+
+    #[inline]
+    pub fn cross(a: &V4, b: &V4) -> V4 {
+        // (y, z, x, w) and (z, x, y, w) of each operand
+        const YZX: i32 = 0b11_00_10_01;
+        const ZXY: i32 = 0b11_01_00_10;
+
+        let mut c = unsafe {
+            let (a, b) = (load(a), load(b));
+            let l = _mm_mul_ps(_mm_shuffle_ps(a, a, YZX), _mm_shuffle_ps(b, b, ZXY));
+            let r = _mm_mul_ps(_mm_shuffle_ps(a, a, ZXY), _mm_shuffle_ps(b, b, YZX));
+            store(_mm_sub_ps(l, r))
+        };
+        c.3 = 0.0;
+        c
+    }
+
+    #[inline]
+    pub fn tmvmul(mt: &M4, v: &V4) -> V4 {
+        unsafe {
+            // the rows of `mt` are the columns of the matrix applied
+            let v = load(v);
+
+            let r = _mm_mul_ps(load_row(mt, 0), _mm_shuffle_ps(v, v, 0x00));
+            let r = _mm_add_ps(r, _mm_mul_ps(load_row(mt, 1), _mm_shuffle_ps(v, v, 0x55)));
+            let r = _mm_add_ps(r, _mm_mul_ps(load_row(mt, 2), _mm_shuffle_ps(v, v, 0xaa)));
+            store(_mm_add_ps(r, _mm_mul_ps(load_row(mt, 3), _mm_shuffle_ps(v, v, 0xff))))
+        }
+    }
+
+    #[inline]
+    pub fn mmul(a: &M4, b: &M4) -> M4 {
+        let b = [ load_row(b, 0), load_row(b, 1), load_row(b, 2), load_row(b, 3) ];
+        let mut c = M4([ 0.0; 16 ]);
+
+        unsafe {
+            for y in 0..4 {
+                let mut row = _mm_mul_ps(_mm_set1_ps(a.0[4*y]), b[0]);
+                for (k, bk) in b.iter().enumerate().skip(1) {
+                    row = _mm_add_ps(row, _mm_mul_ps(_mm_set1_ps(a.0[4*y+k]), *bk));
+                }
+                _mm_store_ps((&mut c as *mut M4 as *mut f32).add(4 * y), row);
+            }
+        }
+
+        c
+    }
+}
+
+#[cfg(all(not(feature = "f64"), not(feature = "scalar"), target_arch = "aarch64"))]
+mod neon {
+    use super::super::{M4,V4};
+    use std::arch::aarch64::*;
+
+    // Shuffling lanes around costs more than it saves for a single cross
+    // product here.
+    pub use super::super::scalar::cross;
+
+    #[inline(always)]
+    fn load(v: &V4) -> float32x4_t {
+        unsafe { vld1q_f32(v as *const V4 as *const f32) }
+    }
+
+    #[inline(always)]
+    fn store(r: float32x4_t) -> V4 {
+        let mut v = V4(0.0, 0.0, 0.0, 0.0);
+        unsafe { vst1q_f32(&mut v as *mut V4 as *mut f32, r) };
+        v
+    }
+
+    #[inline]
+    pub fn add(a: &V4, b: &V4) -> V4 {
+        unsafe { store(vaddq_f32(load(a), load(b))) }
+    }
+
+    #[inline]
+    pub fn sub(a: &V4, b: &V4) -> V4 {
+        unsafe { store(vsubq_f32(load(a), load(b))) }
+    }
+
+    #[inline]
+    pub fn scale(a: &V4, f: f32) -> V4 {
+        unsafe { store(vmulq_n_f32(load(a), f)) }
+    }
+
+    #[inline]
+    pub fn neg(a: &V4) -> V4 {
+        unsafe { store(vnegq_f32(load(a))) }
+    }
+
+    #[inline]
+    pub fn dot(a: &V4, b: &V4) -> f32 {
+        unsafe {
+            let p = vmulq_f32(load(a), load(b));
+            vgetq_lane_f32(p, 0) + vgetq_lane_f32(p, 1) + vgetq_lane_f32(p, 2) + vgetq_lane_f32(p, 3)
+        }
+    }
+
+    #[inline]
+    pub fn mvmul(m: &M4, v: &V4) -> V4 {
+        unsafe {
+            // de-interleaving load: the matrix's columns
+            let c = vld4q_f32(m as *const M4 as *const f32);
+
+            let r = vmulq_n_f32(c.0, v.0);
+            let r = vaddq_f32(r, vmulq_n_f32(c.1, v.1));
+            let r = vaddq_f32(r, vmulq_n_f32(c.2, v.2));
+            store(vaddq_f32(r, vmulq_n_f32(c.3, v.3)))
+        }
+    }
+
+    #[inline]
+    pub fn tmvmul(mt: &M4, v: &V4) -> V4 {
+        unsafe {
+            // the rows of `mt` are the columns of the matrix applied
+            let p = mt as *const M4 as *const f32;
+
+            let r = vmulq_n_f32(vld1q_f32(p), v.0);
+            let r = vaddq_f32(r, vmulq_n_f32(vld1q_f32(p.add(4)), v.1));
+            let r = vaddq_f32(r, vmulq_n_f32(vld1q_f32(p.add(8)), v.2));
+            store(vaddq_f32(r, vmulq_n_f32(vld1q_f32(p.add(12)), v.3)))
+        }
+    }
+
+    #[inline]
+    pub fn mmul(a: &M4, b: &M4) -> M4 {
+        let mut c = M4([ 0.0; 16 ]);
+
+        unsafe {
+            let p = b as *const M4 as *const f32;
+            let b = [ vld1q_f32(p), vld1q_f32(p.add(4)), vld1q_f32(p.add(8)), vld1q_f32(p.add(12)) ];
+
+            for y in 0..4 {
+                let mut row = vmulq_n_f32(b[0], a.0[4*y]);
+                for (k, bk) in b.iter().enumerate().skip(1) {
+                    row = vaddq_f32(row, vmulq_n_f32(*bk, a.0[4*y+k]));
+                }
+                vst1q_f32((&mut c as *mut M4 as *mut f32).add(4 * y), row);
+            }
+        }
+
+        c
+    }
+}
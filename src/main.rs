@@ -1,60 +1,265 @@
-#![allow(dead_code)]
-
-mod camera;
-mod canvas;
-mod color;
 mod config;
-mod cube;
-mod cylinder;
-mod lighting;
-mod linalg;
-mod material;
-mod mesh;
-mod pattern;
-mod plane;
-mod ray;
-mod sceneparser;
-mod shape;
-mod sphere;
-mod transform;
-mod world;
 
 use std::env;
 use std::error;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufWriter;
+use std::net::TcpListener;
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
 use std::time::{SystemTime};
 
-use camera::Camera;
+use raytracer_challenge::{imageio,sceneparser};
+use raytracer_challenge::{check_scene,render_distributed,serve_worker,Checkpoint,Severity};
+use raytracer_challenge::aov::Aov;
+use raytracer_challenge::camera::Camera;
+use raytracer_challenge::denoise::Denoiser;
+use raytracer_challenge::framebuffer::FrameBuffer;
+use raytracer_challenge::progressive::{Event,Limits,Progressive,SnapshotPolicy};
+use raytracer_challenge::tonemap::ToneMap;
+use raytracer_challenge::world::World;
+
 use config::{Config,ConfigError};
-use world::World;
 
-fn process(config: &Config, camera: &Camera, world: &World) {
+fn write_output(config: &Config, camera: &Camera, fb: &FrameBuffer, aov_fbs: &[FrameBuffer]) -> Result<(), Box<dyn error::Error>> {
+    if config.aov_layers {
+        let mut layers = vec![ ("", fb) ];
+        layers.extend(config.aovs.iter().map(|a| a.name()).zip(aov_fbs.iter()));
+
+        let mut output = BufWriter::new(File::create(&config.output_file_name)?);
+        imageio::ExrWriter().write_layers(&layers, &mut output)?;
+        output.flush()?;
+
+        return Ok(())
+    }
+
+    // Config has checked the format
+    let writer = imageio::writer_for_format(&config.output_format).unwrap();
+
+    imageio::save(fb, camera.tonemap(), &config.output_file_name, writer.as_ref())?;
+
+    for (aov, aov_fb) in config.aovs.iter().zip(aov_fbs.iter()) {
+        let name = imageio::pass_file_name(&config.output_file_name, aov.name());
+        imageio::save(aov_fb, &ToneMap::new(), &name, writer.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Post-process and write one render result, final or intermediate.
+fn finish(config: &Config, camera: &Camera, aovs: &[Aov], fb: &FrameBuffer, aov_fbs: &[FrameBuffer], passes: u32) -> Result<(), Box<dyn error::Error>> {
+    let region = camera.region();
+
+    // only the crop window was rendered; filter and write just that part
+    let (fb, mut aov_fbs) = if camera.crop().is_some() {
+        (fb.crop(&region), aov_fbs.iter().map(|f| f.crop(&region)).collect())
+    } else {
+        (fb.clone(), aov_fbs.to_vec())
+    };
+
+    let mut fb = if config.denoise {
+        let albedo = &aov_fbs[aovs.iter().position(|a| *a == Aov::Albedo).unwrap()];
+        let normal = &aov_fbs[aovs.iter().position(|a| *a == Aov::Normal).unwrap()];
+
+        Denoiser::new().apply(&fb, albedo, normal)
+    } else {
+        fb
+    };
+
+    if camera.crop().is_some() && config.region_fullsize {
+        for f in std::iter::once(&mut fb).chain(aov_fbs.iter_mut()) {
+            let mut full = FrameBuffer::new(camera.width(), camera.height());
+            full.blit(region.x0, region.y0, f);
+            *f = full;
+        }
+    }
+
+    fb.set_metadata("scene", &config.input_file_name);
+    fb.set_metadata("samples", &passes.to_string());
+
+    if config.denoise {
+        fb.set_metadata("denoised", "yes");
+    }
+    if camera.crop().is_some() {
+        fb.set_metadata("region", &format!("{},{},{},{}", region.x0, region.y0, region.x1, region.y1));
+    }
+
+    write_output(config, camera, &fb, &aov_fbs)
+}
+
+fn process(config: &Config, camera: &Camera, world: &World, scene_hash: u64, cancel: &Arc<AtomicBool>) {
+    let t1 = SystemTime::now();
+
+    // the denoiser needs albedo and normal guides even if they aren't output
+    let mut aovs = config.aovs.clone();
+    if config.denoise {
+        for aov in [ Aov::Albedo, Aov::Normal ].iter() {
+            if !aovs.contains(aov) {
+                aovs.push(*aov)
+            }
+        }
+    }
+
+    let limits = Limits {
+        passes: config.samples,
+        time: config.time_limit
+    };
+    let policy = SnapshotPolicy {
+        interval: config.snapshot_interval,
+        passes: config.snapshot_passes,
+        checkpoint_interval: config.checkpoint_file_name.as_ref().map(|_| config.checkpoint_interval)
+    };
+
+    let mut renderer = if config.resume {
+        let name = config.checkpoint_file_name.as_ref().unwrap();
+
+        let renderer = Checkpoint::load(scene_hash, (camera.width(), camera.height()), name)
+            .and_then(|cp| Progressive::from_checkpoint(camera, world, cp))
+            .unwrap_or_else(|e| {
+                eprintln!("Resume error: {}: {}", name, e);
+                process::exit(1)
+            });
+
+        if renderer.aovs() != aovs.as_slice() {
+            eprintln!("Resume error: {}: checkpoint has different AOVs", name);
+            process::exit(1)
+        }
+
+        println!("Resuming after {} passes.", renderer.passes());
+        renderer
+    } else {
+        Progressive::new(camera, world, &aovs, 0)
+    };
+
+    renderer.set_cancel_flag(Arc::clone(cancel));
+
+    let save_checkpoint = |r: &Progressive| -> Result<(), Box<dyn error::Error>> {
+        match &config.checkpoint_file_name {
+            Some(name) => r.checkpoint().save(scene_hash, name),
+            None => Ok(())
+        }
+    };
+
+    let res = renderer.run(&limits, &policy, |r, event| {
+            match event {
+                Event::Snapshot => finish(config, camera, &aovs, r.framebuffer(), r.aov_framebuffers(), r.passes()),
+                Event::Checkpoint => save_checkpoint(r)
+            }
+        });
+
+    let t2 = SystemTime::now();
+
+    res.and_then(|_| save_checkpoint(&renderer))
+        .and_then(|_| {
+            finish(config, camera, &aovs, renderer.framebuffer(), renderer.aov_framebuffers(), renderer.passes())
+        }).unwrap_or_else(|e| {
+            eprintln!("Output error: {}", e);
+            process::exit(1)
+        });
+
+    if renderer.is_cancelled() {
+        eprintln!("Interrupted after {} passes.", renderer.passes());
+        process::exit(130)
+    }
+
+    let t3 = SystemTime::now();
+
+    let render_t = t2.duration_since(t1).unwrap().as_millis();
+    let write_t  = t3.duration_since(t2).unwrap().as_millis();
+
+    println!("Done (render: {}ms, write: {}ms, {} passes).", render_t, write_t, renderer.passes());
+}
+
+/// Coordinator mode: let the workers render the image tile by tile.
+fn process_distributed(config: &Config, camera: &Camera, scene: &str) {
     let t1 = SystemTime::now();
 
-    let img = camera.render(&world);
+    let on_lost = |addr: &str, e: &str| eprintln!("Lost worker {}: {}", addr, e);
+
+    let fb = render_distributed(scene, camera, &config.workers, config.tile_size, config.samples, on_lost).unwrap_or_else(|e| {
+            eprintln!("Render error: {}", e);
+            process::exit(1)
+        });
 
     let t2 = SystemTime::now();
 
-    img.save(&config.output_file_name).expect("Could not write file");
+    finish(config, camera, &[], &fb, &[], config.samples).unwrap_or_else(|e| {
+            eprintln!("Output error: {}", e);
+            process::exit(1)
+        });
 
     let t3 = SystemTime::now();
 
     let render_t = t2.duration_since(t1).unwrap().as_millis();
     let write_t  = t3.duration_since(t2).unwrap().as_millis();
 
-    println!("Done (render: {}ms, write: {}ms).", render_t, write_t);
+    println!("Done (render: {}ms, write: {}ms, {} passes).", render_t, write_t, config.samples);
+}
+
+/// Worker mode: listen on `addr` (e.g. "0.0.0.0:7878").
+fn serve(addr: &str) -> Result<(), Box<dyn error::Error>> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening on {}", listener.local_addr()?);
+    serve_worker(listener, |peer, e| eprintln!("Connection {}: {}", peer, e))
 }
 
-fn setup(config: &Config) -> Result<(Camera, World), Box<dyn error::Error>> {
-    let mut file = File::open(&config.input_file_name)?;
+/// Lint the scene file without rendering. Exits with 1 if there are errors.
+fn check(config: &Config) {
     let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
 
-    let (camera, world) = sceneparser::read_yaml_scene_config(&contents)?;
+    File::open(&config.input_file_name)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}: {}", config.input_file_name, e);
+            process::exit(1)
+        });
+
+    let dir = Path::new(&config.input_file_name).parent().unwrap_or_else(|| Path::new("."));
+    let diags = check_scene(&contents, dir);
+
+    for d in diags.iter() {
+        println!("{}:{}", config.input_file_name, d);
+    }
+
+    let errors = diags.iter().filter(|d| d.severity == Severity::Error).count();
+
+    println!("{} error(s), {} warning(s).", errors, diags.len() - errors);
+
+    if errors > 0 {
+        process::exit(1)
+    }
+}
+
+fn setup(config: &Config, frame: u32) -> Result<(Camera, World, String), Box<dyn error::Error>> {
+    let (mut camera, world, contents) = sceneparser::read_yaml_scene_frame(Path::new(&config.input_file_name), frame as f64)?;
+
+    let mut tonemap = *camera.tonemap();
+
+    if let Some(ev) = config.exposure {
+        tonemap.exposure = ev
+    }
+    if let Some(op) = config.tone_operator {
+        tonemap.operator = op
+    }
+    if let Some(enc) = config.encoding {
+        tonemap.encoding = enc
+    }
+
+    camera.set_tonemap(tonemap);
+
+    if let Some(region) = config.region {
+        if !region.fits(camera.width(), camera.height()) {
+            return Err(format!("region {},{},{},{} is not inside the {}x{} image",
+                region.x0, region.y0, region.x1, region.y1, camera.width(), camera.height()).into())
+        }
+
+        camera.set_crop(Some(region));
+    }
 
-    Ok((camera,world))
+    Ok((camera, world, contents))
 }
 
 fn main() {
@@ -71,10 +276,68 @@ fn main() {
         }
     };
 
-    let (camera, world) = setup(&config).unwrap_or_else(|x| {
+    if let Some(addr) = &config.serve {
+        serve(addr).unwrap_or_else(|e| {
+                eprintln!("Worker error: {}", e);
+                process::exit(1)
+            });
+        return
+    }
+
+    if config.check {
+        check(&config);
+        return
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    if config.workers.is_empty() && config.debug_pixel.is_none() {
+        let flag = Arc::clone(&cancel);
+
+        if let Err(e) = ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst)) {
+            eprintln!("Warning: cannot install interrupt handler: {}", e);
+        }
+    }
+
+    let (first, last) = config.frames.unwrap_or((0, 0));
+
+    for frame in first..=last {
+        let config = match config.frames {
+            Some(_) => {
+                println!("Frame {}:", frame);
+                Config { output_file_name: imageio::frame_file_name(&config.output_file_name, frame), ..config.clone() }
+            },
+            None => config.clone()
+        };
+
+        render_frame(&config, frame, &cancel);
+    }
+}
+
+fn render_frame(config: &Config, frame: u32, cancel: &Arc<AtomicBool>) {
+    let (camera, world, scene) = setup(config, frame).unwrap_or_else(|x| {
             eprintln!("Setup error: {}", x);
             process::exit(1)
         });
 
-    process(&config, &camera, &world);
+    if let Some((x, y)) = config.debug_pixel {
+        if x >= camera.width() || y >= camera.height() {
+            eprintln!("Error: pixel {},{} is outside the {}x{} image", x, y, camera.width(), camera.height());
+            process::exit(1)
+        }
+
+        match camera.debug_pixel(&world, x, y) {
+            Some(tree) => print!("{}", tree),
+            None => println!("No ray goes through pixel {},{}.", x, y)
+        }
+        return
+    }
+
+    if config.workers.is_empty() {
+        // a checkpoint is only valid for the same crop window, too
+        let scene_hash = raytracer_challenge::scene_hash(&format!("{}\n# region: {:?}", scene, camera.region()));
+        process(config, &camera, &world, scene_hash, cancel);
+    } else {
+        process_distributed(config, &camera, &scene);
+    }
 }
@@ -1,5 +1,5 @@
 use crate::color::Color;
-use crate::linalg::{M4,V4};
+use crate::linalg::{Float,M4,V4};
 use crate::pattern::{Pattern,TransformedPattern};
 
 use std::rc::Rc;
@@ -13,13 +13,13 @@ pub enum Texture {
 #[derive(Clone,Debug)]
 pub struct Material {
     pub texture: Texture,
-    pub ambient: f32,
-    pub diffuse: f32,
-    pub specular: f32,
-    pub shininess: f32,
-    pub reflective: f32,
-    pub transparency: f32,
-    pub refractive_index: f32
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    pub reflective: Float,
+    pub transparency: Float,
+    pub refractive_index: Float
 }
 
 impl Material {
@@ -1,12 +1,16 @@
-use crate::linalg::V4;
+use crate::linalg::{EPSILON,Float,V4};
 use crate::ray::Ray;
-use crate::shape::BaseShape;
+use crate::shape::{BaseShape,Hits,Primitive};
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::prelude::*;
 
 #[derive(Clone,Copy,Debug,PartialEq)]
-struct Triangle {
-    pub p: [V4; 3],
-    pub e: [V4; 2],
-    pub normal: V4
+pub struct Triangle {
+    p: [V4; 3],
+    e: [V4; 2],
+    normal: V4
 }
 
 impl Triangle {
@@ -21,67 +25,100 @@ impl Triangle {
         }
     }
 
-    pub fn intersect(&self, ray: &Ray) -> Option<f32> {
+    pub fn points(&self) -> [V4; 3] {
+        self.p
+    }
+}
+
+impl BaseShape for Triangle {
+    fn intersect(&self, ray: &Ray) -> Hits {
+        let mut ret = Hits::new();
+
         let dir_x_e2 = V4::cross(&ray.direction, &self.e[1]);
         let det = V4::dot(&self.e[0], &dir_x_e2);
 
-        if det.abs() < 0.0001 {
-            return None
+        if det.abs() < EPSILON {
+            return ret
         }
 
         let f = 1.0 / det;
         let p1_to_origin = ray.origin - self.p[0];
         let u = f * V4::dot(&p1_to_origin, &dir_x_e2);
 
-        if u < 0.0 || u > 1.0 {
-            return None
+        if !(0.0..=1.0).contains(&u) {
+            return ret
         }
 
         let origin_x_e1 = V4::cross(&p1_to_origin, &self.e[0]);
         let v = f * V4::dot(&ray.direction, &origin_x_e1);
 
         if v < 0.0 || (u + v) > 1.0 {
-            return None
+            return ret
         }
 
-        let t = f * V4::dot(&self.e[1], &origin_x_e1);
+        ret.push(f * V4::dot(&self.e[1], &origin_x_e1));
+        ret
+    }
 
-        Some(t)
+    fn normal_at(&self, _: V4) -> V4 {
+        self.normal
+    }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Triangle { p1: self.p[0], p2: self.p[1], p3: self.p[2] }
     }
 }
 
-use std::io;
-use std::io::prelude::*;
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
 
-fn parse_obj(reader: &mut dyn io::BufRead) -> io::Result< Vec<Triangle> > {
+/// The triangles of the faces in a Wavefront OBJ file. Faces with more
+/// than three vertices are split into a fan; texture coordinates, normals
+/// and groups are ignored.
+pub fn parse_obj(reader: &mut dyn io::BufRead) -> io::Result< Vec<Triangle> > {
     let mut result = Vec::new();
     let mut vertices = Vec::new();
 
-    for line in reader.lines() {
-        let line = line.unwrap();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let n = n + 1;
         let mut split = line.split_whitespace();
+
         match split.next() {
             Some("v") => {
-                let v: Vec<f32> = split.map(|s| s.parse::<f32>().unwrap()).collect();
-                if v.len() >= 3 {
-                    vertices.push( (v[0], v[1], v[2]) );
+                let v = split.map(|s| s.parse::<Float>()).collect::<Result<Vec<Float>,_>>()
+                    .map_err(|_| invalid(n, "expected numbers"))?;
+
+                if v.len() < 3 {
+                    return Err(invalid(n, "expected 3 coordinates"))
                 }
-                ()
-            }
+
+                vertices.push(V4::new_point(v[0], v[1], v[2]));
+            },
             Some("f") => {
-                let indices: Vec<i32> = split.map(|s| s.parse::<i32>().unwrap()).collect();
-                if indices.len() >= 3 {
-                    for index in 2..indices.len() {
-                        let v  = vertices[(indices[0      ] - 1) as usize];
-                        let p0 = V4::new_vector(v.0, v.1, v.2);
-                        let v  = vertices[(indices[index-1] - 1) as usize];
-                        let p1 = V4::new_vector(v.0, v.1, v.2);
-                        let v  = vertices[(indices[index  ] - 1) as usize];
-                        let p2 = V4::new_vector(v.0, v.1, v.2);
-                        result.push(Triangle::new(p0, p1, p2))
+                // "v", "v/vt", "v//vn" or "v/vt/vn"; negative indices
+                // count back from the latest vertex
+                let vertex = |s: &str| {
+                    let i = s.split('/').next().unwrap_or("").parse::<i64>().map_err(|_| invalid(n, "expected vertex index"))?;
+                    let i = if i < 0 { vertices.len() as i64 + i } else { i - 1 };
+
+                    match usize::try_from(i).ok().and_then(|i| vertices.get(i)) {
+                        Some(v) => Ok(*v),
+                        None => Err(invalid(n, "vertex index out of range"))
                     }
+                };
+
+                let points = split.map(vertex).collect::<io::Result<Vec<V4>>>()?;
+
+                if points.len() < 3 {
+                    return Err(invalid(n, "expected 3 vertices"))
+                }
+
+                for i in 2..points.len() {
+                    result.push(Triangle::new(points[0], points[i-1], points[i]))
                 }
-            }
+            },
             _ => ()
         }
     }
@@ -89,19 +126,9 @@ fn parse_obj(reader: &mut dyn io::BufRead) -> io::Result< Vec<Triangle> > {
     Ok(result)
 }
 
-pub struct Mesh {
-
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::color::Color;
-    use crate::material::{Material,Texture};
-    use crate::linalg::{M4,V4};
-    use crate::transform::Transform;
-    use crate::shape::Shape;
-    use float_cmp::*;
 
     #[test]
     fn new_triangle() {
@@ -114,7 +141,8 @@ mod tests {
 
         assert_eq!(t.e[0],   V4::new_vector(-1.0, -1.0,  0.0));
         assert_eq!(t.e[1],   V4::new_vector( 1.0, -1.0,  0.0));
-        assert_eq!(t.normal, V4::new_vector( 0.0,  0.0, -1.0))
+        assert_eq!(t.normal, V4::new_vector( 0.0,  0.0, -1.0));
+        assert_eq!(t.normal_at(V4::new_point(0.0, 0.5, 0.0)), t.normal)
     }
 
     #[test]
@@ -126,11 +154,11 @@ mod tests {
         ];
         let t = Triangle::new(points[0], points[1], points[2]);
 
-        assert_eq!(t.intersect(&Ray::new(V4::new_point( 0.0, -1.0, -2.0), V4::new_vector(0.0, 1.0, 0.0))), None);
-        assert_eq!(t.intersect(&Ray::new(V4::new_point( 1.0,  1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))), None);
-        assert_eq!(t.intersect(&Ray::new(V4::new_point(-1.0,  1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))), None);
-        assert_eq!(t.intersect(&Ray::new(V4::new_point( 0.0, -1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))), None);
-        assert_eq!(t.intersect(&Ray::new(V4::new_point( 0.0,  0.5, -2.0), V4::new_vector(0.0, 0.0, 1.0))), Some(2.0));
+        assert!(t.intersect(&Ray::new(V4::new_point( 0.0, -1.0, -2.0), V4::new_vector(0.0, 1.0, 0.0))).is_empty());
+        assert!(t.intersect(&Ray::new(V4::new_point( 1.0,  1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))).is_empty());
+        assert!(t.intersect(&Ray::new(V4::new_point(-1.0,  1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))).is_empty());
+        assert!(t.intersect(&Ray::new(V4::new_point( 0.0, -1.0, -2.0), V4::new_vector(0.0, 0.0, 1.0))).is_empty());
+        assert_eq!(t.intersect(&Ray::new(V4::new_point( 0.0,  0.5, -2.0), V4::new_vector(0.0, 0.0, 1.0))), [ 2.0 ]);
     }
 
     #[test]
@@ -138,21 +166,40 @@ mod tests {
         let mut input = "
             v -1 1 0
             v -1 0 0
-            v 1 0 0 
+            v 1 0 0
             v 1 1 0
             f 1 2 3
-            f 1 3 4
+            f 1/1 3//2 -1/2/3
         ".as_bytes();
 
         let triangles = parse_obj(&mut input).unwrap();
 
         assert_eq!(triangles.len(), 2);
-        assert_eq!(triangles[0].p[0], V4::new_vector(-1.0, 1.0, 0.0));
-        assert_eq!(triangles[0].p[1], V4::new_vector(-1.0, 0.0, 0.0));
-        assert_eq!(triangles[0].p[2], V4::new_vector( 1.0, 0.0, 0.0));
+        assert_eq!(triangles[0].p[0], V4::new_point(-1.0, 1.0, 0.0));
+        assert_eq!(triangles[0].p[1], V4::new_point(-1.0, 0.0, 0.0));
+        assert_eq!(triangles[0].p[2], V4::new_point( 1.0, 0.0, 0.0));
+
+        assert_eq!(triangles[1].p[0], V4::new_point(-1.0, 1.0, 0.0));
+        assert_eq!(triangles[1].p[1], V4::new_point( 1.0, 0.0, 0.0));
+        assert_eq!(triangles[1].p[2], V4::new_point( 1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn parse_polygon() {
+        let mut input = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 2 0\nf 1 2 3 4 5\n".as_bytes();
+
+        let triangles = parse_obj(&mut input).unwrap();
+
+        assert_eq!(triangles.len(), 3);
+        assert_eq!(triangles[2].p, [ V4::new_point(0.0, 0.0, 0.0), V4::new_point(0.0, 1.0, 0.0), V4::new_point(0.0, 2.0, 0.0) ]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let e = parse_obj(&mut "v 1 0 0\nf 1 2 3\n".as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "line 2: vertex index out of range");
 
-        assert_eq!(triangles[1].p[0], V4::new_vector(-1.0, 1.0, 0.0));
-        assert_eq!(triangles[1].p[1], V4::new_vector( 1.0, 0.0, 0.0));
-        assert_eq!(triangles[1].p[2], V4::new_vector( 1.0, 1.0, 0.0));
+        let e = parse_obj(&mut "v 1 x 0\n".as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "line 1: expected numbers");
     }
 }
@@ -1,10 +1,28 @@
 use crate::color::Color;
-use crate::linalg::{M4,V4};
+use crate::linalg::{Float,M4,V4};
 
 use std::rc::Rc;
 
+/// What a pattern is made of, so that it can be written back out.
+#[derive(Clone,Debug)]
+pub enum PatternKind {
+    Solid(Color),
+    Stripes(Color,Color),
+    Checkerboard(Color,Color),
+    Ring(Color,Color),
+    Gradient(Color,Color),
+    Blended(Rc<dyn Pattern>,Rc<dyn Pattern>),
+    Transformed(Rc<dyn Pattern>,M4),
+    /// A pattern the scene format has no description for
+    Other
+}
+
 pub trait Pattern: std::fmt::Debug {
     fn color_at(&self, p: V4) -> Color;
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Other
+    }
 }
 
 
@@ -23,34 +41,46 @@ impl Pattern for Solid {
     fn color_at(&self, _: V4) -> Color {
         self.color
     }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Solid(self.color)
+    }
 }
 
 
 #[derive(Clone,Debug)]
 pub struct TransformedPattern {
     pattern: Rc<dyn Pattern>,
-    transform_i: M4
+    transform: M4,
+    /// The transposed inverse, see `M4::transpose_mul`
+    transform_i_t: M4
 }
 
 impl TransformedPattern {
     pub fn new<T: Pattern + 'static>(p: T, m: &M4) -> TransformedPattern {
         TransformedPattern {
             pattern: Rc::new(p),
-            transform_i: m.invert()
+            transform: *m,
+            transform_i_t: m.invert().transpose()
         }
     }
 
     pub fn new_from_rc(p: Rc<dyn Pattern>, m: &M4) -> TransformedPattern {
         TransformedPattern {
             pattern: p,
-            transform_i: m.invert()
+            transform: *m,
+            transform_i_t: m.invert().transpose()
         }
     }
 }
 
 impl Pattern for TransformedPattern {
     fn color_at(&self, p: V4) -> Color {
-        self.pattern.color_at(self.transform_i * p)
+        self.pattern.color_at(self.transform_i_t.transpose_mul(p))
+    }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Transformed(Rc::clone(&self.pattern), self.transform)
     }
 }
 
@@ -75,6 +105,10 @@ impl Pattern for Stripes {
             self.b
         }
     }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Stripes(self.a, self.b)
+    }
 }
 
 
@@ -98,6 +132,10 @@ impl Pattern for Checkerboard {
             self.b
         }
     }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Checkerboard(self.a, self.b)
+    }
 }
 
 
@@ -121,6 +159,62 @@ impl Pattern for Ring {
             self.b
         }
     }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Ring(self.a, self.b)
+    }
+}
+
+
+/// Fades from `a` at x = 0 to `b` at x = 1, repeating every unit.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct Gradient {
+    a: Color,
+    b: Color
+}
+
+impl Gradient {
+    pub fn new(a: Color, b: Color) -> Gradient {
+        Gradient { a, b }
+    }
+}
+
+impl Pattern for Gradient {
+    fn color_at(&self, p: V4) -> Color {
+        let f = p.x() - p.x().floor();
+        let mix = |a: Float, b: Float| a + (b - a) * f;
+
+        Color::new(mix(self.a.r, self.b.r), mix(self.a.g, self.b.g), mix(self.a.b, self.b.b))
+    }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Gradient(self.a, self.b)
+    }
+}
+
+
+/// The average of two patterns.
+#[derive(Clone,Debug)]
+pub struct Blended {
+    a: Rc<dyn Pattern>,
+    b: Rc<dyn Pattern>
+}
+
+impl Blended {
+    pub fn new(a: Rc<dyn Pattern>, b: Rc<dyn Pattern>) -> Blended {
+        Blended { a, b }
+    }
+}
+
+impl Pattern for Blended {
+    fn color_at(&self, p: V4) -> Color {
+        let (a, b) = (self.a.color_at(p), self.b.color_at(p));
+        Color::new((a.r + b.r) * 0.5, (a.g + b.g) * 0.5, (a.b + b.b) * 0.5)
+    }
+
+    fn kind(&self) -> PatternKind {
+        PatternKind::Blended(Rc::clone(&self.a), Rc::clone(&self.b))
+    }
 }
 
 
@@ -175,6 +269,24 @@ mod tests {
         assert_eq!(c.color_at(V4::new_point(0.708, 0.0, 0.708)), Color::BLACK);
     }
 
+    #[test]
+    fn gradient() {
+        let g = Gradient::new(Color::WHITE, Color::BLACK);
+
+        assert_eq!(g.color_at(V4::new_point(0.0,  0.0, 0.0)), Color::WHITE);
+        assert_eq!(g.color_at(V4::new_point(0.25, 0.0, 0.0)), Color::new(0.75, 0.75, 0.75));
+        assert_eq!(g.color_at(V4::new_point(0.5,  0.0, 0.0)), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(g.color_at(V4::new_point(1.25, 0.0, 0.0)), Color::new(0.75, 0.75, 0.75));
+    }
+
+    #[test]
+    fn blended() {
+        let b = Blended::new(Rc::new(Stripes::new(Color::WHITE, Color::BLACK)), Rc::new(Solid::new(Color::RED)));
+
+        assert_eq!(b.color_at(V4::new_point(0.0, 0.0, 0.0)), Color::new(1.0, 0.5, 0.5));
+        assert_eq!(b.color_at(V4::new_point(1.0, 0.0, 0.0)), Color::new(0.5, 0.0, 0.0));
+    }
+
     #[derive(Copy,Clone,Debug)]
     struct TestPattern ();
 
@@ -191,5 +303,13 @@ mod tests {
         let r = V4::from(s.color_at(V4::new_point(2.0, 3.0, 4.0)));
 
         assert!(approx_eq!(V4, r, V4::new_vector(1.0, 1.5, 2.0), epsilon = 0.0001));
+
+        match s.kind() {
+            PatternKind::Transformed(p, m) => {
+                assert!(matches!(p.kind(), PatternKind::Other));
+                assert!(approx_eq!(&M4, &m, &t.matrix));
+            },
+            _ => panic!("not a transformed pattern")
+        }
     }
 }
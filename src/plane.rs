@@ -1,21 +1,27 @@
-use crate::linalg::V4;
+use crate::linalg::{EPSILON,V4};
 use crate::ray::Ray;
-use crate::shape::BaseShape;
+use crate::shape::{BaseShape,Hits,Primitive};
 
 pub struct Plane ();
 
 impl BaseShape for Plane {
-    fn intersect(&self, r: &Ray) -> Vec<f32> {
-        if r.direction.y().abs() < 0.0001 {
-            vec![]
-        } else {
-            vec![ -r.origin.y() / r.direction.y() ]
+    fn intersect(&self, r: &Ray) -> Hits {
+        let mut v = Hits::new();
+
+        if r.direction.y().abs() >= EPSILON {
+            v.push(-r.origin.y() / r.direction.y())
         }
+
+        v
     }
 
     fn normal_at(&self, _: V4) -> V4 {
         V4::new_vector(0.0, 1.0, 0.0)
     }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Plane
+    }
 }
 
 #[cfg(test)]
@@ -36,7 +42,8 @@ mod tests {
     fn intersect_parallel() {
         let mut ray = Ray {
             origin: V4::new_point(0.0, 10.0, 0.0),
-            direction: V4::new_vector(0.0, 0.0, 1.0)
+            direction: V4::new_vector(0.0, 0.0, 1.0),
+            time: 0.0
         };
 
         assert!(Plane().intersect(&ray).is_empty());
@@ -49,7 +56,8 @@ mod tests {
     fn intersect_above() {
         let ray = Ray {
             origin: V4::new_point(0.0, 1.0, 0.0),
-            direction: V4::new_point(0.0, -1.0, 0.0)
+            direction: V4::new_point(0.0, -1.0, 0.0),
+            time: 0.0
         };
 
         assert_eq!(Plane().intersect(&ray), [ 1.0 ]);
@@ -59,7 +67,8 @@ mod tests {
     fn intersect_below() {
         let ray = Ray {
             origin: V4::new_point(0.0, -1.0, 0.0),
-            direction: V4::new_point(0.0, 1.0, 0.0)
+            direction: V4::new_point(0.0, 1.0, 0.0),
+            time: 0.0
         };
 
         assert_eq!(Plane().intersect(&ray), [ 1.0 ]);
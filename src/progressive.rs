@@ -0,0 +1,387 @@
+use crate::aov::Aov;
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::framebuffer::{FrameBuffer,Rect};
+use crate::rng::Rng;
+use crate::world::World;
+
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::time::{Duration,Instant};
+
+/// When to stop refining. A zero `passes` limit means no pass limit.
+#[derive(Clone,Copy,Debug)]
+pub struct Limits {
+    pub passes: u32,
+    pub time: Option<Duration>
+}
+
+/// When to hand out intermediate results while refining.
+#[derive(Clone,Copy,Debug)]
+pub struct SnapshotPolicy {
+    pub interval: Option<Duration>,
+    pub passes: Option<u32>,
+    pub checkpoint_interval: Option<Duration>
+}
+
+/// Why `Progressive::run` hands out an intermediate state.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Event {
+    /// Time to write the current image estimate.
+    Snapshot,
+    /// Time to save the render state so it can be resumed.
+    Checkpoint
+}
+
+/// Refines an image (or the camera's crop window) in passes of one sample
+/// per pixel. The first pass
+/// samples pixel centers and records the AOVs, so a single-pass render is
+/// identical to `Camera::render_with_aovs`; later passes jitter the sample
+/// positions within each pixel.
+///
+/// Rows are rendered in a fixed order from one RNG stream, so the state
+/// after any row (see `checkpoint()`) is enough to continue the render
+/// with bit-identical results.
+pub struct Progressive<'a> {
+    camera: &'a Camera,
+    world: &'a World,
+    aovs: Vec<Aov>,
+    fb: FrameBuffer,
+    aov_fbs: Vec<FrameBuffer>,
+    passes: u32,
+    row: usize,
+    rng: Rng,
+    cancel: Option<Arc<AtomicBool>>
+}
+
+impl<'a> Progressive<'a> {
+    pub fn new(camera: &'a Camera, world: &'a World, aovs: &[Aov], seed: u64) -> Progressive<'a> {
+        let (w, h) = (camera.width(), camera.height());
+
+        Progressive {
+            camera,
+            world,
+            aovs: aovs.to_vec(),
+            fb: FrameBuffer::new(w, h),
+            aov_fbs: vec![FrameBuffer::new(w, h); aovs.len()],
+            passes: 0,
+            row: camera.region().y0,
+            rng: Rng::new(seed),
+            cancel: None
+        }
+    }
+
+    /// Continue a render from a saved state. The checkpoint must have been
+    /// taken with the same camera and AOV list.
+    pub fn from_checkpoint(camera: &'a Camera, world: &'a World, cp: Checkpoint) -> Result<Progressive<'a>, Box<dyn Error>> {
+        if cp.fb.width != camera.width() || cp.fb.height != camera.height() {
+            return Err("checkpoint image size does not match the camera".into())
+        }
+        let region = camera.region();
+
+        if cp.aov_fbs.len() != cp.aovs.len() || cp.row < region.y0 || cp.row >= region.y1 {
+            return Err("inconsistent checkpoint".into())
+        }
+
+        Ok(Progressive {
+            camera,
+            world,
+            aovs: cp.aovs,
+            fb: cp.fb,
+            aov_fbs: cp.aov_fbs,
+            passes: cp.passes,
+            row: cp.row,
+            rng: Rng::from_state(cp.rng_state),
+            cancel: None
+        })
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            passes: self.passes,
+            row: self.row,
+            rng_state: self.rng.state(),
+            aovs: self.aovs.clone(),
+            fb: self.fb.clone(),
+            aov_fbs: self.aov_fbs.clone()
+        }
+    }
+
+    /// Stop rendering as soon as `flag` is set, even within the first pass.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.load(Ordering::SeqCst))
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.fb
+    }
+
+    pub fn aov_framebuffers(&self) -> &[FrameBuffer] {
+        &self.aov_fbs
+    }
+
+    /// Render the next row of the current pass. Returns true if that
+    /// completed the pass.
+    fn render_next_row(&mut self) -> bool {
+        let region = self.camera.region();
+        let rect = Rect { y0: self.row, y1: self.row + 1, ..region };
+
+        if self.passes == 0 {
+            self.camera.render_rect(self.world, &rect, None, &mut self.fb, &self.aovs, &mut self.aov_fbs);
+        } else {
+            self.camera.render_rect(self.world, &rect, Some(&mut self.rng), &mut self.fb, &[], &mut []);
+        }
+
+        self.row += 1;
+
+        if self.row == region.y1 {
+            self.row = region.y0;
+            self.passes += 1;
+            return true
+        }
+
+        false
+    }
+
+    /// Add one sample to every pixel not yet sampled in the current pass.
+    /// Passes after the first one give up at `deadline`; any pass stops
+    /// when cancelled. Returns whether the pass was completed.
+    pub fn render_pass(&mut self, deadline: Option<Instant>) -> bool {
+        loop {
+            if self.is_cancelled() {
+                return false
+            }
+            if self.passes > 0 && deadline.is_some_and(|d| Instant::now() >= d) {
+                return false
+            }
+            if self.render_next_row() {
+                return true
+            }
+        }
+    }
+
+    /// Render passes until one of the limits is hit or the render is
+    /// cancelled, calling `callback` with intermediate states according
+    /// to `policy`. The final state is not passed to `callback`.
+    pub fn run<F>(&mut self, limits: &Limits, policy: &SnapshotPolicy, mut callback: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(&Progressive, Event) -> Result<(), Box<dyn Error>>
+    {
+        let start = Instant::now();
+        let deadline = limits.time.map(|t| start + t);
+        let mut last_snapshot = start;
+        let mut last_checkpoint = start;
+
+        loop {
+            if limits.passes > 0 && self.passes >= limits.passes {
+                break
+            }
+            if !self.render_pass(deadline) {
+                break
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                break
+            }
+            if limits.passes > 0 && self.passes >= limits.passes {
+                break
+            }
+
+            let by_passes = policy.passes.is_some_and(|n| n > 0 && self.passes.is_multiple_of(n));
+            let by_time = policy.interval.is_some_and(|t| last_snapshot.elapsed() >= t);
+
+            if by_passes || by_time {
+                callback(self, Event::Snapshot)?;
+                last_snapshot = Instant::now();
+            }
+
+            if policy.checkpoint_interval.is_some_and(|t| last_checkpoint.elapsed() >= t) {
+                callback(self, Event::Checkpoint)?;
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::lighting::LightSource;
+    use crate::linalg::{consts,M4,V4};
+    use crate::material::{Material,Texture};
+    use crate::shape::Shape;
+    use crate::sphere::Sphere;
+    use crate::transform::Transform;
+
+    use std::rc::Rc;
+
+    fn make_scene() -> (Camera, World) {
+        let m = Material {
+            texture: Texture::Color(Color::new(0.8, 1.0, 0.6)),
+            ambient: 0.1,
+            diffuse: 0.7,
+            specular: 0.2,
+            shininess: 200.0,
+            reflective: 0.0,
+            transparency: 0.0,
+            refractive_index: 1.0
+        };
+
+        let light = LightSource {
+            intensity: Color::WHITE,
+            pos: V4::new_point(-10.0, 10.0, -10.0)
+        };
+
+        let world = World::new_with(vec![light], vec![ Rc::new(Shape::new(Box::new(Sphere()), &m, &M4::identity())) ]);
+
+        let from = V4::new_point(0.0, 0.0, -5.0);
+        let to = V4::new_point(0.0, 0.0, 0.0);
+        let up = V4::new_vector(0.0, 1.0, 0.0);
+
+        let t = Transform::view_transform(&from, &to, &up);
+
+        (Camera::new(11, 11, consts::FRAC_PI_2, &t.matrix), world)
+    }
+
+    #[test]
+    fn first_pass_matches_render() {
+        let (camera, world) = make_scene();
+
+        let mut p = Progressive::new(&camera, &world, &[ Aov::Depth ], 1);
+        assert!(p.render_pass(None));
+
+        let (fb, aov_fbs) = camera.render_with_aovs(&world, &[ Aov::Depth ]);
+
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(p.framebuffer().at(x, y), fb.at(x, y));
+                assert_eq!(p.aov_framebuffers()[0].at(x, y), aov_fbs[0].at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn pass_limit_and_snapshots() {
+        let (camera, world) = make_scene();
+
+        let mut p = Progressive::new(&camera, &world, &[], 1);
+        let limits = Limits { passes: 5, time: None };
+        let policy = SnapshotPolicy { interval: None, passes: Some(2), checkpoint_interval: None };
+
+        let mut snapshots = Vec::new();
+
+        p.run(&limits, &policy, |p, _| { snapshots.push(p.passes()); Ok(()) }).unwrap();
+
+        assert_eq!(p.passes(), 5);
+        assert_eq!(p.framebuffer().samples(5, 5), 5);
+        assert_eq!(snapshots, vec![ 2, 4 ]);
+    }
+
+    #[test]
+    fn time_limit() {
+        let (camera, world) = make_scene();
+
+        let mut p = Progressive::new(&camera, &world, &[], 1);
+        let limits = Limits { passes: 0, time: Some(Duration::from_millis(20)) };
+        let policy = SnapshotPolicy { interval: None, passes: None, checkpoint_interval: None };
+
+        p.run(&limits, &policy, |_, _| Ok(())).unwrap();
+
+        // the first pass always completes
+        assert!(p.passes() >= 1);
+        assert!(p.framebuffer().samples(10, 10) >= 1);
+    }
+
+    #[test]
+    fn resume_is_bit_identical() {
+        let (camera, world) = make_scene();
+
+        let mut full = Progressive::new(&camera, &world, &[ Aov::Normal ], 7);
+        for _ in 0..4 {
+            full.render_pass(None);
+        }
+
+        // interrupt in the middle of the first pass
+        let mut a = Progressive::new(&camera, &world, &[ Aov::Normal ], 7);
+        for _ in 0..5 {
+            a.render_next_row();
+        }
+
+        let mut b = Progressive::from_checkpoint(&camera, &world, a.checkpoint()).unwrap();
+
+        // ... and again in the middle of the third one
+        for _ in 0..(11 + 11 + 3) {
+            b.render_next_row();
+        }
+
+        let mut c = Progressive::from_checkpoint(&camera, &world, b.checkpoint()).unwrap();
+
+        while c.passes() < 4 {
+            c.render_pass(None);
+        }
+
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(c.framebuffer().at(x, y), full.framebuffer().at(x, y));
+                assert_eq!(c.framebuffer().samples(x, y), 4);
+                assert_eq!(c.aov_framebuffers()[0].at(x, y), full.aov_framebuffers()[0].at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn crop() {
+        let (mut camera, world) = make_scene();
+        let full = camera.render(&world);
+
+        camera.set_crop(Some(Rect { x0: 2, y0: 3, x1: 7, y1: 5 }));
+
+        let mut p = Progressive::new(&camera, &world, &[], 1);
+        assert!(p.render_pass(None));
+        assert!(p.render_pass(None));
+
+        assert_eq!(p.passes(), 2);
+        assert_eq!(p.framebuffer().samples(2, 3), 2);
+        assert_eq!(p.framebuffer().samples(6, 4), 2);
+        assert_eq!(p.framebuffer().samples(7, 4), 0);
+        assert_eq!(p.framebuffer().samples(2, 5), 0);
+
+        let fb = camera.render(&world);
+
+        assert_eq!(fb.at(5, 4), full.at(5, 4));
+        assert_eq!(fb.samples(1, 4), 0);
+    }
+
+    #[test]
+    fn cancel() {
+        let (camera, world) = make_scene();
+
+        let flag = Arc::new(AtomicBool::new(true));
+
+        let mut p = Progressive::new(&camera, &world, &[], 1);
+        p.set_cancel_flag(Arc::clone(&flag));
+
+        assert!(!p.render_pass(None));
+        assert_eq!(p.framebuffer().samples(0, 0), 0);
+
+        flag.store(false, Ordering::SeqCst);
+
+        assert!(p.render_pass(None));
+        assert_eq!(p.passes(), 1);
+    }
+}
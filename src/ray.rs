@@ -1,27 +1,45 @@
-use crate::linalg::{V4,M4};
+use crate::linalg::{Float,M4,V4};
 
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub struct Ray {
     pub origin: V4,
-    pub direction: V4
+    pub direction: V4,
+    /// When the ray is cast, in frames relative to the rendered frame.
+    pub time: Float
 }
 
 impl Ray {
     pub fn new(orig: V4, dir: V4) -> Ray {
         Ray {
             origin: orig,
-            direction: dir
+            direction: dir,
+            time: 0.0
         }
     }
 
+    pub fn with_time(mut self, time: Float) -> Ray {
+        self.time = time;
+        self
+    }
+
     pub fn apply(&self, m: &M4) -> Ray {
         Ray {
             origin: m * self.origin,
-            direction: m * self.direction
+            direction: m * self.direction,
+            time: self.time
+        }
+    }
+
+    /// `apply(&mt.transpose())`
+    pub fn apply_transposed(&self, mt: &M4) -> Ray {
+        Ray {
+            origin: mt.transpose_mul(self.origin),
+            direction: mt.transpose_mul(self.direction),
+            time: self.time
         }
     }
 
-    pub fn position(&self, t: f32) -> V4 {
+    pub fn position(&self, t: Float) -> V4 {
         self.origin + self.direction * t
     }
 }
@@ -41,13 +59,19 @@ mod tests {
 
         assert!(approx_eq!(V4, rt.origin, V4::new_point(4.0, 6.0, 8.0)));
         assert!(approx_eq!(V4, rt.direction, V4::new_vector(0.0, 1.0, 0.0)));
+
+        let rt = ray.with_time(0.5).apply(&trans.matrix);
+        assert_eq!(rt.time, 0.5);
+
+        assert_eq!(ray.apply_transposed(&trans.matrix.transpose()), ray.apply(&trans.matrix));
     }
 
     #[test]
     fn position() {
         let ray = Ray {
             origin: V4::new_point(2.0, 3.0, 4.0),
-            direction: V4::new_vector(1.0, 0.0, 0.0)
+            direction: V4::new_vector(1.0, 0.0, 0.0),
+            time: 0.0
         };
 
         assert_eq!(ray.position(2.5), V4::new_point(4.5, 3.0, 4.0));
@@ -0,0 +1,138 @@
+use crate::color::Color;
+use crate::linalg::{Float,V4};
+use crate::ray::Ray;
+
+use std::fmt;
+
+/// Why a ray was cast.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum RayKind {
+    Primary,
+    Reflected,
+    Refracted
+}
+
+/// Shadow ray from a hit point towards one light.
+#[derive(Clone,Debug,PartialEq)]
+pub struct ShadowRecord {
+    pub light: usize,
+    /// Object ID and distance of the closest occluder, if any.
+    pub blocker: Option<(u32,Float)>,
+    pub contribution: Color
+}
+
+/// Everything that went into shading one hit.
+#[derive(Clone,Debug,PartialEq)]
+pub struct HitNode {
+    pub distance: Float,
+    pub object_id: u32,
+    pub point: V4,
+    pub normal: V4,
+    /// The ray hit the back side, so the normal was flipped.
+    pub inside: bool,
+    pub shadows: Vec<ShadowRecord>,
+    pub n1: Option<Float>,
+    pub n2: Option<Float>,
+    pub reflectance: Option<Float>,
+    pub total_internal_reflection: bool,
+    pub reflected: Option<Box<RayNode>>,
+    pub refracted: Option<Box<RayNode>>
+}
+
+/// One ray of a traced pixel, with its intersections and what they led to.
+/// Produced by `World::debug_trace()`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct RayNode {
+    pub kind: RayKind,
+    pub ray: Ray,
+    /// Recursion levels left for this ray.
+    pub depth: u32,
+    /// All (distance, object ID) pairs, sorted by distance.
+    pub intersections: Vec<(Float,u32)>,
+    pub hit: Option<HitNode>,
+    pub color: Color
+}
+
+impl RayNode {
+    pub(crate) fn new(kind: RayKind, ray: &Ray, depth: u32) -> RayNode {
+        RayNode {
+            kind,
+            ray: *ray,
+            depth,
+            intersections: Vec::new(),
+            hit: None,
+            color: Color::BLACK
+        }
+    }
+
+    fn fmt_indent(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = "  ".repeat(indent);
+
+        let kind = match self.kind {
+            RayKind::Primary => "primary",
+            RayKind::Reflected => "reflected",
+            RayKind::Refracted => "refracted"
+        };
+
+        writeln!(f, "{}{} ray from {} dir {} (depth {})", pad, kind, Point(self.ray.origin), Point(self.ray.direction), self.depth)?;
+
+        if self.intersections.is_empty() {
+            writeln!(f, "{}  intersections: none", pad)?;
+        } else {
+            let xs: Vec<String> = self.intersections.iter().map(|(t, id)| format!("{:.5} #{}", t, id)).collect();
+            writeln!(f, "{}  intersections: {}", pad, xs.join(", "))?;
+        }
+
+        match &self.hit {
+            Some(hit) => {
+                writeln!(f, "{}  hit: t={:.5} object #{} at {}", pad, hit.distance, hit.object_id, Point(hit.point))?;
+                writeln!(f, "{}  normal: {}{}", pad, Point(hit.normal), if hit.inside { " (inside, flipped)" } else { "" })?;
+
+                for s in hit.shadows.iter() {
+                    let status = match s.blocker {
+                        Some((id, t)) => format!("shadowed by #{} at t={:.5}", id, t),
+                        None => String::from("lit")
+                    };
+                    writeln!(f, "{}  light {}: {}, contributes {}", pad, s.light, status, Rgb(s.contribution))?;
+                }
+
+                if let (Some(n1), Some(n2)) = (hit.n1, hit.n2) {
+                    writeln!(f, "{}  n1/n2: {}/{}{}", pad, n1, n2, if hit.total_internal_reflection { " (total internal reflection)" } else { "" })?;
+                }
+                if let Some(r) = hit.reflectance {
+                    writeln!(f, "{}  schlick reflectance: {:.5}", pad, r)?;
+                }
+                if let Some(node) = &hit.reflected {
+                    node.fmt_indent(f, indent + 1)?;
+                }
+                if let Some(node) = &hit.refracted {
+                    node.fmt_indent(f, indent + 1)?;
+                }
+            },
+            None => writeln!(f, "{}  miss", pad)?
+        }
+
+        writeln!(f, "{}  color: {}", pad, Rgb(self.color))
+    }
+}
+
+struct Point(V4);
+struct Rgb(Color);
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({:.4}, {:.4}, {:.4})", self.0.x(), self.0.y(), self.0.z())
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:.4} {:.4} {:.4}]", self.0.r, self.0.g, self.0.b)
+    }
+}
+
+impl fmt::Display for RayNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(f, 0)
+    }
+}
@@ -0,0 +1,74 @@
+/// Small, fast xorshift64* generator. The whole state is one u64, so it
+/// can be stored and restored to reproduce a sample sequence exactly.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift must not start from zero; scramble the seed a bit
+        let state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ 0xD1B5_4A32_D192_ED03;
+
+        Rng { state: if state == 0 { 1 } else { state } }
+    }
+
+    pub fn from_state(state: u64) -> Rng {
+        Rng { state }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniformly distributed in [0,1).
+    pub fn next_f32(&mut self) -> f32 {
+        // top 24 bits fill the f32 mantissa exactly
+        ((self.next_u64() >> 40) as f32) / ((1_u64 << 24) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range() {
+        let mut rng = Rng::new(0);
+
+        for _ in 0..10000 {
+            let v = rng.next_f32();
+            assert!((0.0..1.0).contains(&v));
+        }
+    }
+
+    #[test]
+    fn restore() {
+        let mut a = Rng::new(42);
+
+        a.next_u64();
+        a.next_u64();
+
+        let mut b = Rng::from_state(a.state());
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn seeds_differ() {
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+}
@@ -0,0 +1,1019 @@
+use crate::bookscene;
+use crate::camera::Projection;
+use crate::expr::Vars;
+use crate::linalg::{Float,M4,V4};
+use crate::ray::Ray;
+use crate::sceneparser;
+use crate::sceneparser::ParseError;
+use crate::shape::Shape;
+
+use yaml_rust::parser::{MarkedEventReceiver,Parser};
+use yaml_rust::scanner::Marker;
+use yaml_rust::{Event,Yaml,YamlLoader};
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+/// A problem found in a scene file. Lines and columns are 1-based.
+#[derive(Clone,Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub col: usize,
+    pub message: String
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sev = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        };
+
+        write!(f, "{}:{}: {}: {}", self.line, self.col, sev, self.message)
+    }
+}
+
+/// Source positions of a YAML document, mirroring its structure.
+enum Pos {
+    Scalar(Marker),
+    Seq(Marker, Vec<Pos>),
+    Map(Marker, Vec<(String, Marker, Pos)>)
+}
+
+impl Pos {
+    /// Where the node starts; for mappings, that's the first key.
+    fn mark(&self) -> Marker {
+        match self {
+            Pos::Map(_, v) if !v.is_empty() => v[0].1,
+            Pos::Scalar(m) | Pos::Seq(m, _) | Pos::Map(m, _) => *m
+        }
+    }
+
+    /// Where the node's own event is, which for mappings comes before
+    /// the first key.
+    fn start(&self) -> Marker {
+        match self {
+            Pos::Scalar(m) | Pos::Seq(m, _) | Pos::Map(m, _) => *m
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Pos> {
+        match self {
+            Pos::Map(_, v) => v.iter().find(|(k, _, _)| k == key).map(|(_, _, p)| p),
+            _ => None
+        }
+    }
+
+    fn key_mark(&self, key: &str) -> Option<Marker> {
+        match self {
+            Pos::Map(_, v) => v.iter().find(|(k, _, _)| k == key).map(|(_, m, _)| *m),
+            _ => None
+        }
+    }
+
+    fn index(&self, i: usize) -> Option<&Pos> {
+        match self {
+            Pos::Seq(_, v) => v.get(i),
+            _ => None
+        }
+    }
+
+    /// Entry `i` of a list, or the list itself where its entries have no
+    /// positions of their own, as for an alias.
+    fn item(&self, i: usize) -> &Pos {
+        self.index(i).unwrap_or(self)
+    }
+
+    /// The closest node below this one that is the value of `key`.
+    fn find(&self, key: &str) -> Option<&Pos> {
+        let mut level = vec![ self ];
+
+        while !level.is_empty() {
+            let mut next = Vec::new();
+
+            for p in level {
+                match p {
+                    Pos::Map(_, v) => {
+                        if let Some((_, _, c)) = v.iter().find(|(k, _, _)| k == key) {
+                            return Some(c)
+                        }
+                        next.extend(v.iter().map(|(_, _, c)| c));
+                    },
+                    Pos::Seq(_, v) => next.extend(v.iter()),
+                    Pos::Scalar(_) => ()
+                }
+            }
+
+            level = next;
+        }
+
+        None
+    }
+}
+
+#[derive(Default)]
+struct PosBuilder {
+    stack: Vec<(Pos, Option<(String, Marker)>)>,
+    docs: Vec<Pos>,
+    anchors: Vec<(usize, Marker)>,
+    aliases: Vec<usize>
+}
+
+impl PosBuilder {
+    /// The nodes that are used again through an alias.
+    fn aliased(&self) -> Vec<Marker> {
+        self.anchors.iter().filter(|(id, _)| self.aliases.contains(id)).map(|(_, m)| *m).collect()
+    }
+
+    fn anchor(&mut self, id: usize, mark: Marker) {
+        if id != 0 {
+            self.anchors.push((id, mark))
+        }
+    }
+
+    fn insert(&mut self, node: Pos, scalar: Option<String>) {
+        let mark = node.mark();
+
+        match self.stack.last_mut() {
+            None => self.docs.push(node),
+            Some((Pos::Seq(_, v), _)) => v.push(node),
+            Some((Pos::Map(_, v), pending)) => {
+                match pending.take() {
+                    Some((key, key_mark)) => v.push((key, key_mark, node)),
+                    None => *pending = Some((scalar.unwrap_or_default(), mark))
+                }
+            },
+            Some((Pos::Scalar(_), _)) => ()
+        }
+    }
+}
+
+impl MarkedEventReceiver for PosBuilder {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(s, _, id, _) => {
+                self.anchor(id, mark);
+                self.insert(Pos::Scalar(mark), Some(s))
+            },
+            // an alias's entries have no positions of their own, see `Pos::item`
+            Event::Alias(id) => {
+                self.aliases.push(id);
+                self.insert(Pos::Scalar(mark), None)
+            },
+            Event::SequenceStart(id) => {
+                self.anchor(id, mark);
+                self.stack.push((Pos::Seq(mark, Vec::new()), None))
+            },
+            Event::MappingStart(id) => {
+                self.anchor(id, mark);
+                self.stack.push((Pos::Map(mark, Vec::new()), None))
+            },
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((node, _)) = self.stack.pop() {
+                    self.insert(node, None)
+                }
+            },
+            _ => ()
+        }
+    }
+}
+
+const SINGULAR_EPSILON: Float = 1e-6;
+
+/// Whether `m` can't be inverted sensibly. The determinant is taken
+/// relative to the lengths of the axes, so that small but uniform
+/// scales pass and only flattened or degenerate transformations don't.
+fn is_singular(m: &M4) -> bool {
+    let axes: Float = (0..3).map(|c| V4::new_vector(m.at(0, c), m.at(1, c), m.at(2, c)).magnitude()).product();
+
+    axes == 0.0 || (m.determinant() / axes).abs() < SINGULAR_EPSILON
+}
+
+struct Checker<'a> {
+    /// The document being checked
+    root: &'a Yaml,
+    /// The same with its includes merged in, to resolve references
+    scope: &'a Yaml,
+    diags: Vec<Diagnostic>,
+    /// Names of definitions referred to anywhere in the merged scene
+    references: Vec<String>,
+    /// Nodes used again through an alias, like `lights: *L`
+    aliased: Vec<Marker>,
+    variables: Vec<(String,f64)>,
+    /// Malformed animated values, already reported
+    bad_keyframes: Vec<Marker>,
+    singular_shapes: Vec<usize>
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, severity: Severity, mark: Marker, message: String) {
+        // yaml-rust lines are 1-based, columns 0-based
+        self.diags.push(Diagnostic { severity, line: mark.line(), col: mark.col() + 1, message })
+    }
+
+    fn error(&mut self, pos: &Pos, context: &str, e: Box<dyn Error>) {
+        let mut p = pos;
+
+        for key in sceneparser::error_path(e.as_ref()) {
+            match p.find(key) {
+                Some(c) => p = c,
+                None => break
+            }
+        }
+
+        if !self.bad_keyframes.contains(&p.mark()) {
+            self.report(Severity::Error, p.mark(), format!("{}: {}", context, e))
+        }
+    }
+
+    fn warn(&mut self, pos: &Pos, message: String) {
+        self.report(Severity::Warning, pos.mark(), message)
+    }
+
+    fn unknown_keys(&mut self, node: &Yaml, pos: &Pos, known: &[&str], context: &str) {
+        if let Yaml::Hash(kv) = node {
+            for key in kv.keys() {
+                if let Some(key) = key.as_str() {
+                    if !known.contains(&key) {
+                        let mark = pos.key_mark(key).unwrap_or_else(|| pos.mark());
+                        self.report(Severity::Warning, mark, format!("{}: unknown key \"{}\"", context, key))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false if the transformation is singular.
+    fn check_transformations(&mut self, node: &Yaml, pos: &Pos, context: &str) -> bool {
+        if let Ok(m) = sceneparser::read_transformations(self.scope, node) {
+            if is_singular(&m) {
+                self.warn(pos, format!("{}: transformation is singular (zero scale?) and can't be inverted", context));
+                return false
+            }
+        }
+
+        true
+    }
+
+    fn check_material(&mut self, node: &Yaml, pos: &Pos, context: &str) {
+        self.unknown_keys(node, pos, sceneparser::MATERIAL_KEYS, context);
+
+        if let (Yaml::Hash(kv), Some(tpos)) = (&node["texture"], pos.get("texture")) {
+            if let Some((key, val)) = kv.iter().next() {
+                let key = key.as_str().unwrap_or("");
+
+                if let (Yaml::Hash(_), Some(ppos)) = (val, tpos.get(key)) {
+                    self.unknown_keys(val, ppos, sceneparser::PATTERN_KEYS, context);
+
+                    if let Some(trpos) = ppos.get("transformations") {
+                        self.check_transformations(&val["transformations"], trpos, context);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Report malformed keyframes anywhere below `node`.
+    fn check_keyframes(&mut self, node: &Yaml, pos: &Pos) {
+        if sceneparser::is_animated(node) {
+            self.unknown_keys(node, pos, sceneparser::ANIMATED_KEYS, "animated value");
+
+            if let Err(e) = sceneparser::read_keyframes(node) {
+                self.error(pos, "animated value", e);
+                self.bad_keyframes.push(pos.mark())
+            }
+            return
+        }
+
+        match (node, pos) {
+            (Yaml::Array(v), Pos::Seq(_, p)) => {
+                for (n, p) in v.iter().zip(p.iter()) {
+                    self.check_keyframes(n, p)
+                }
+            },
+            (Yaml::Hash(kv), Pos::Map(_, p)) => {
+                for (n, (_, _, p)) in kv.values().zip(p.iter()) {
+                    self.check_keyframes(n, p)
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn check_camera(&mut self, pos: &Pos) {
+        let node = &self.root["camera"];
+
+        let cpos = match pos.get("camera") {
+            Some(p) => p,
+            None if !self.scope["camera"].is_badvalue() => return,
+            None => return self.report(Severity::Error, pos.mark(), String::from("camera: element missing"))
+        };
+
+        let camera = match sceneparser::read_camera(node) {
+            Ok(c) => c,
+            Err(e) => return self.error(cpos, "camera", e)
+        };
+
+        let (w, h) = camera.eye_size();
+
+        if camera.projection() == Projection::Equirectangular && w != 2.0 * h {
+            let message = match camera.stereo() {
+                Some(_) => "camera: each eye's equirectangular view should be twice as wide as high",
+                None => "camera: equirectangular images should be twice as wide as high"
+            };
+            self.warn(cpos, String::from(message))
+        }
+
+        self.unknown_keys(node, cpos, sceneparser::CAMERA_KEYS, "camera");
+
+        if let Some(spos) = cpos.get("stereo") {
+            self.unknown_keys(&node["stereo"], spos, sceneparser::STEREO_KEYS, "camera.stereo");
+        }
+
+        let from = sceneparser::read_v3(&node["from"]).unwrap();
+        let to = sceneparser::read_v3(&node["to"]).unwrap();
+        let up = sceneparser::read_v3_or(&node["up"], &[ 0.0, 1.0, 0.0 ]).unwrap();
+
+        let dir = V4::new_vector(to[0]-from[0], to[1]-from[1], to[2]-from[2]);
+        let up = V4::new_vector(up[0], up[1], up[2]);
+
+        if dir.magnitude() == 0.0 {
+            self.warn(cpos, String::from("camera: \"from\" and \"to\" are the same point"))
+        } else if V4::cross(&up, &dir).magnitude() <= SINGULAR_EPSILON * up.magnitude() * dir.magnitude() {
+            match cpos.get("up") {
+                Some(upos) => self.warn(upos, String::from("camera: \"up\" is parallel to the view direction")),
+                None => self.warn(cpos.get("to").unwrap_or(cpos), String::from("camera: default \"up\" [ 0, 1, 0 ] is parallel to the view direction"))
+            }
+        }
+    }
+
+    fn check_lights(&mut self, pos: &Pos) {
+        let lpos = match pos.get("lights") {
+            Some(p) => p,
+            None if !self.scope["lights"].is_badvalue() => return,
+            None => return self.report(Severity::Error, pos.mark(), String::from("lights: element missing"))
+        };
+
+        let lights = match &self.root["lights"] {
+            Yaml::Array(v) => v,
+            _ => return self.error(lpos, "lights", sceneparser::read_lights(&self.root["lights"]).unwrap_err())
+        };
+
+        for (i, node) in lights.iter().enumerate() {
+            let ipos = lpos.item(i);
+            let context = format!("lights[{}]", i);
+
+            if let Err(e) = sceneparser::read_lights(&Yaml::Array(vec![ node.clone() ])) {
+                self.error(ipos, &context, e);
+                continue
+            }
+
+            if let Some(ppos) = ipos.get("point") {
+                self.unknown_keys(&node["point"], ppos, sceneparser::LIGHT_KEYS, &context)
+            }
+        }
+    }
+
+    fn check_shapes(&mut self, pos: &Pos) {
+        let spos = match pos.get("shapes") {
+            Some(p) => p,
+            None if !self.scope["shapes"].is_badvalue() => return,
+            None => return self.report(Severity::Error, pos.mark(), String::from("shapes: element missing"))
+        };
+
+        let shapes = match &self.root["shapes"] {
+            Yaml::Array(v) => v,
+            _ => return self.report(Severity::Error, spos.mark(), String::from("shapes: expected array"))
+        };
+
+        let mut materials = Vec::new();
+        let vars = self.variables.clone();
+
+        for (i, node) in shapes.iter().enumerate() {
+            let ipos = spos.item(i);
+
+            if !self.check_shape(node, ipos, &format!("shapes[{}]", i), &vars, &mut materials) {
+                self.singular_shapes.push(i)
+            }
+        }
+    }
+
+    /// Check a `repeat:` entry, and its shapes for the first value of the
+    /// loop variable. Returns false if one of them is singular.
+    fn check_repeat(&mut self, node: &Yaml, pos: &Pos, context: &str, vars: &Vars, materials: &mut Vec<Option<String>>) -> bool {
+        self.unknown_keys(node, pos, sceneparser::REPEAT_KEYS, context);
+
+        if let Err(e) = sceneparser::expand_repeat(node, vars) {
+            self.error(pos, context, e);
+            return true
+        }
+
+        let values = sceneparser::read_range(&node["range"], vars).unwrap();
+        if values.is_empty() {
+            self.warn(pos.get("range").unwrap_or(pos), format!("{}: range is empty", context));
+            return true
+        }
+
+        let mut scope = vars.to_vec();
+        scope.push((String::from(node["for"].as_str().unwrap()), values[0]));
+
+        let spos = pos.get("shapes").unwrap_or(pos);
+        let mut ok = true;
+
+        for (i, shape) in node["shapes"].as_vec().unwrap().iter().enumerate() {
+            let shape = if sceneparser::is_repeat(shape) { shape.clone() } else { substitute(shape, &scope) };
+            let context = format!("{}.shapes[{}]", context, i);
+
+            ok &= self.check_shape(&shape, spos.item(i), &context, &scope, materials)
+        }
+
+        ok
+    }
+
+    /// Returns false if the shape's transformation is singular.
+    fn check_shape(&mut self, node: &Yaml, ipos: &Pos, context: &str, vars: &Vars, materials: &mut Vec<Option<String>>) -> bool {
+        let (kind, body) = match node {
+            Yaml::Hash(kv) if !kv.is_empty() => {
+                let (k, v) = kv.iter().next().unwrap();
+                (k.as_str().unwrap_or(""), v)
+            },
+            _ => {
+                self.report(Severity::Error, ipos.mark(), format!("{}: expected dict", context));
+                return true
+            }
+        };
+
+        if let Yaml::Hash(kv) = node {
+            for key in kv.keys().skip(1).filter_map(|k| k.as_str()) {
+                let mark = ipos.key_mark(key).unwrap_or_else(|| ipos.mark());
+                self.report(Severity::Warning, mark, format!("{}: \"{}\" ignored, only one shape per list entry", context, key))
+            }
+        }
+
+        let bpos = ipos.get(kind).unwrap_or(ipos);
+
+        if kind == "repeat" {
+            return self.check_repeat(body, bpos, context, vars, materials)
+        }
+
+        if let (Yaml::String(name), Some(mpos)) = (&body["material"], bpos.get("material")) {
+            if let Yaml::BadValue = self.scope[name.as_str()] {
+                self.report(Severity::Error, mpos.mark(), format!("{}: undefined material {}", context, name));
+                return true
+            }
+
+            // errors in the definition itself are reported there
+            if sceneparser::read_material(self.scope, &self.scope[name.as_str()]).is_err() {
+                return true
+            }
+        }
+
+        if let Err(e) = sceneparser::read_shape(self.scope, node, materials) {
+            self.error(ipos, context, e);
+            return true
+        }
+
+        let known = match kind {
+            "cylinder" | "pipe" | "cone" | "open_cone" => sceneparser::CYLINDER_KEYS,
+            "triangle" => sceneparser::TRIANGLE_KEYS,
+            _ => sceneparser::SHAPE_KEYS
+        };
+
+        self.unknown_keys(body, bpos, known, context);
+
+        if let (Yaml::Hash(_), Some(mpos)) = (&body["material"], bpos.get("material")) {
+            self.check_material(&body["material"], mpos, context)
+        }
+        if let Some(tpos) = bpos.get("transformations") {
+            return self.check_transformations(&body["transformations"], tpos, context)
+        }
+
+        true
+    }
+
+    fn check_definitions(&mut self, pos: &Pos) {
+        let kv = match self.root {
+            Yaml::Hash(kv) => kv,
+            _ => return
+        };
+
+        for (key, node) in kv.iter() {
+            let key = match key.as_str() {
+                Some(k) => k,
+                None => continue
+            };
+
+            let dpos = match pos.get(key) {
+                Some(p) => p,
+                None => continue
+            };
+
+            let referenced = self.references.iter().any(|m| m == key);
+            let aliased = self.aliased.contains(&dpos.start());
+
+            if key.starts_with(".mat.") {
+                match sceneparser::read_material(self.scope, node) {
+                    Ok(_) => self.check_material(node, dpos, key),
+                    Err(e) => self.error(dpos, key, e)
+                }
+
+                if !referenced && !aliased {
+                    let mark = pos.key_mark(key).unwrap();
+                    self.report(Severity::Warning, mark, format!("material {} is never used", key))
+                }
+            } else if key.starts_with('.') && node.as_vec().is_some() {
+                // plain YAML reused through an alias is checked where it's used
+                if aliased && !referenced {
+                    continue
+                }
+
+                if let Err(e) = sceneparser::read_transformations(self.scope, node) {
+                    self.error(dpos, key, e)
+                }
+
+                if !referenced {
+                    let mark = pos.key_mark(key).unwrap();
+                    self.report(Severity::Warning, mark, format!("transformation list {} is never used", key))
+                }
+            } else if !key.starts_with('.') && !sceneparser::SCENE_KEYS.contains(&key) {
+                let mark = pos.key_mark(key).unwrap();
+                self.report(Severity::Warning, mark, format!("unknown key \"{}\"", key))
+            }
+        }
+    }
+
+    /// Warn about lights that sit inside an opaque closed object, which
+    /// leaves that light without any effect outside of it.
+    fn check_light_placement(&mut self, pos: &Pos) {
+        let (_, world) = match sceneparser::read_scene(self.scope) {
+            Ok(s) => s,
+            Err(_) => return
+        };
+
+        // the list entry each of our own shapes comes from
+        let mut entries = Vec::new();
+        if let Yaml::Array(v) = &self.root["shapes"] {
+            for (i, node) in v.iter().enumerate() {
+                let n = sceneparser::expand_shapes(std::slice::from_ref(node), &self.variables).map_or(0, |s| s.len());
+                entries.extend(std::iter::repeat_n(i, n))
+            }
+        }
+
+        // included lights and shapes come before our own
+        let light_offset = world.lights().len() - self.root["lights"].as_vec().map_or(0, |v| v.len());
+        let shape_offset = world.shapes().len() - entries.len();
+
+        for (i, light) in world.lights().iter().enumerate().skip(light_offset) {
+            for (j, shape) in world.shapes().iter().enumerate() {
+                // singular shapes have been reported and don't intersect sensibly
+                if j >= shape_offset && self.singular_shapes.contains(&entries[j - shape_offset]) {
+                    continue
+                }
+
+                if shape.material().transparency == 0.0 && is_inside(shape, &light.pos) {
+                    let i = i - light_offset;
+                    let lpos = pos.get("lights").map_or(pos, |p| p.item(i));
+
+                    let shape = if j >= shape_offset {
+                        format!("shapes[{}]", entries[j - shape_offset])
+                    } else {
+                        format!("included shape #{}", j + 1)
+                    };
+
+                    self.warn(lpos, format!("lights[{}] is inside opaque {}", i, shape))
+                }
+            }
+        }
+    }
+}
+
+/// A point is inside a closed shape if rays from it in any direction cross
+/// the surface an odd number of times.
+fn is_inside(shape: &Shape, point: &V4) -> bool {
+    let dirs = [
+        V4::new_vector( 0.48,  0.60,  0.64),
+        V4::new_vector(-0.64,  0.36, -0.68),
+        V4::new_vector( 0.36, -0.80,  0.48)
+    ];
+
+    dirs.iter().all(|d| {
+        let n = shape.intersect(&Ray::new(*point, *d)).iter().filter(|&&t| t > 0.0).count();
+        n % 2 == 1
+    })
+}
+
+/// Every string value that names a definition (starts with a dot).
+fn collect_references(node: &Yaml, out: &mut Vec<String>) {
+    match node {
+        Yaml::String(s) if s.starts_with('.') => out.push(s.clone()),
+        Yaml::Array(v) => v.iter().for_each(|n| collect_references(n, out)),
+        Yaml::Hash(kv) => kv.values().for_each(|n| collect_references(n, out)),
+        _ => ()
+    }
+}
+
+/// Malformed animated values are reported separately and left alone here.
+fn substitute(node: &Yaml, vars: &Vars) -> Yaml {
+    sceneparser::substitute(node, vars).unwrap_or_else(|_| node.clone())
+}
+
+/// The document with its expressions evaluated at frame 0, except in
+/// `repeat:` entries, which are checked one iteration at a time.
+fn substitute_root(doc: &Yaml, vars: &Vars) -> Yaml {
+    let kv = match doc {
+        Yaml::Hash(kv) => kv,
+        _ => return doc.clone()
+    };
+
+    Yaml::Hash(kv.iter().map(|(key, val)| {
+        let val = match (key.as_str(), val) {
+            (Some("variables"), _) => val.clone(),
+            (Some("shapes"), Yaml::Array(v)) => Yaml::Array(v.iter().map(|n| {
+                if sceneparser::is_repeat(n) { n.clone() } else { substitute(n, vars) }
+            }).collect()),
+            _ => substitute(val, vars)
+        };
+
+        (key.clone(), val)
+    }).collect())
+}
+
+/// Scenes in the book's format are only loaded, reporting the first error
+/// at the command it occurred in.
+fn check_book_scene(source: &str, dir: &Path, pos: &Pos) -> Vec<Diagnostic> {
+    let err = match sceneparser::load_yaml_scene(source, dir).and_then(|doc| sceneparser::read_scene(&doc)) {
+        Ok(_) => return Vec::new(),
+        Err(e) => e
+    };
+
+    let mark = match err.downcast_ref::<ParseError>() {
+        Some(ParseError::Item(i, _)) => pos.index(*i).map_or(pos.mark(), |p| p.mark()),
+        _ => pos.mark()
+    };
+
+    vec![ Diagnostic { severity: Severity::Error, line: mark.line(), col: mark.col() + 1, message: err.to_string() } ]
+}
+
+/// Parse a scene and report every problem found, without rendering it.
+/// Includes are resolved relative to `dir`; only problems in `source`
+/// itself are reported.
+pub fn check_scene(source: &str, dir: &Path) -> Vec<Diagnostic> {
+    let docs = match YamlLoader::load_from_str(source) {
+        Ok(docs) => docs,
+        Err(e) => {
+            let m = e.marker();
+            return vec![ Diagnostic { severity: Severity::Error, line: m.line(), col: m.col() + 1, message: e.to_string() } ]
+        }
+    };
+
+    let mut builder = PosBuilder::default();
+    if Parser::new(source.chars()).load(&mut builder, false).is_err() || docs.is_empty() || builder.docs.is_empty() {
+        return vec![ Diagnostic { severity: Severity::Error, line: 1, col: 1, message: String::from("empty scene") } ]
+    }
+
+    let pos = &builder.docs[0];
+
+    if bookscene::is_book_scene(&docs[0]) {
+        return check_book_scene(source, dir, pos)
+    }
+
+    if !matches!(pos, Pos::Map(_, _)) {
+        return vec![ Diagnostic { severity: Severity::Error, line: 1, col: 1, message: String::from("expected dict") } ]
+    }
+
+    let mut diags = Vec::new();
+
+    let merged = match sceneparser::load_merged_scene(source, dir) {
+        Ok(doc) => doc,
+        Err(e) => {
+            let mark = pos.key_mark("include").unwrap_or_else(|| pos.mark());
+            diags.push(Diagnostic { severity: Severity::Error, line: mark.line(), col: mark.col() + 1, message: e.to_string() });
+            docs[0].clone()
+        }
+    };
+
+    let variables = match sceneparser::read_variables(&merged["variables"], 0.0) {
+        Ok(v) => v,
+        Err(e) => {
+            let mark = pos.get("variables").map_or(pos.mark(), |p| p.mark());
+            diags.push(Diagnostic { severity: Severity::Error, line: mark.line(), col: mark.col() + 1, message: format!("variables: {}", e) });
+            vec![ (String::from("frame"), 0.0) ]
+        }
+    };
+
+    let root = substitute_root(&docs[0], &variables);
+    let scope = sceneparser::expand_scene(&merged, 0.0).unwrap_or(merged);
+
+    // definitions may also be used by included shapes and definitions
+    let mut references = Vec::new();
+    collect_references(&scope, &mut references);
+
+    let mut checker = Checker {
+        root: &root,
+        scope: &scope,
+        diags,
+        references,
+        aliased: builder.aliased(),
+        variables,
+        bad_keyframes: Vec::new(),
+        singular_shapes: Vec::new()
+    };
+
+    checker.check_keyframes(&docs[0], pos);
+    checker.check_camera(pos);
+    checker.check_lights(pos);
+    checker.check_shapes(pos);
+    checker.check_definitions(pos);
+
+    if !checker.diags.iter().any(|d| d.severity == Severity::Error) {
+        checker.check_light_placement(pos);
+    }
+
+    let mut diags = checker.diags;
+    diags.sort_by_key(|d| (d.line, d.col));
+    diags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = "
+.mat.red:
+  texture:
+    color: [ 1.0, 0.0, 0.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.0
+  shininess: 10.0
+
+.mat.unused:
+  texture:
+    color: [ 0.0, 1.0, 0.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.0
+  shininess: 10.0
+
+camera:
+  width: 100
+  height: 50
+  field_of_view: 60.0
+  from: [ 0.0, 5.0, 0.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+  colour: red
+
+lights:
+  - point:
+      position: [ 0.0, 0.0, 0.0 ]
+
+shapes:
+  - sphere:
+      material: .mat.red
+  - cube:
+      material: .mat.red
+      transformations:
+        - translate: [ 5.0, 0.0, 0.0 ]
+        - scale: [ 1.0, 0.0, 1.0 ]
+";
+
+    fn messages(diags: &[Diagnostic]) -> Vec<String> {
+        diags.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn warnings() {
+        let diags = check_scene(SCENE, Path::new("."));
+        let msgs = messages(&diags);
+
+        assert!(diags.iter().all(|d| d.severity == Severity::Warning), "{:?}", msgs);
+        assert_eq!(diags.len(), 5, "{:?}", msgs);
+
+        assert_eq!(msgs[0], "10:1: warning: material .mat.unused is never used");
+        assert_eq!(msgs[1], "23:7: warning: camera: default \"up\" [ 0, 1, 0 ] is parallel to the view direction");
+        assert_eq!(msgs[2], "24:3: warning: camera: unknown key \"colour\"");
+        assert_eq!(msgs[3], "27:5: warning: lights[0] is inside opaque shapes[0]");
+        assert!(msgs[4].starts_with("36:9: warning: shapes[1]: transformation is singular"));
+    }
+
+    #[test]
+    fn small_scale() {
+        let scene = SCENE.replace("scale: [ 1.0, 0.0, 1.0 ]", "scale: [ 0.009, 0.009, 0.009 ]");
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+        assert!(!msgs.iter().any(|m| m.contains("singular")), "{:?}", msgs);
+
+        // two axes made parallel
+        let scene = SCENE.replace("scale: [ 1.0, 0.0, 1.0 ]", "shear: [ 1.0, 0.0, 1.0, 0.0, 0.0, 0.0 ]");
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+        assert!(msgs.iter().any(|m| m.contains("singular")), "{:?}", msgs);
+    }
+
+    #[test]
+    fn projection() {
+        let scene = SCENE.replace("colour: red", "projection: equirectangular");
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+        assert!(!msgs.iter().any(|m| m.contains("equirectangular")), "{:?}", msgs);
+
+        let msgs = messages(&check_scene(&scene.replace("height: 50", "height: 60"), Path::new(".")));
+        assert!(msgs.contains(&String::from("19:3: warning: camera: equirectangular images should be twice as wide as high")), "{:?}", msgs);
+
+        let stereo = scene.replace("height: 50", "height: 100\n  stereo: { layout: over_under, eye_distance: 0.1, focus: 2.0 }");
+        let msgs = messages(&check_scene(&stereo, Path::new(".")));
+        assert!(!msgs.iter().any(|m| m.contains("equirectangular")), "{:?}", msgs);
+        assert!(msgs.contains(&String::from("21:52: warning: camera.stereo: unknown key \"focus\"")), "{:?}", msgs);
+
+        let msgs = messages(&check_scene(&stereo.replace("over_under", "side_by_side"), Path::new(".")));
+        assert!(msgs.contains(&String::from("19:3: warning: camera: each eye's equirectangular view should be twice as wide as high")), "{:?}", msgs);
+
+        let msgs = messages(&check_scene(&scene.replace("equirectangular", "fisheye\n  fisheye_mapping: polar"), Path::new(".")));
+        assert!(msgs.contains(&String::from("25:20: error: camera: In fisheye_mapping: unknown value polar")), "{:?}", msgs);
+    }
+
+    #[test]
+    fn all_errors() {
+        let scene = SCENE
+            .replace("width: 100", "width: wide")
+            .replace("position: [ 0.0, 0.0, 0.0 ]", "position: [ 0.0, 0.0 ]")
+            .replace("  - sphere:\n      material: .mat.red", "  - sphere:\n      material: .mat.blue")
+            .replace("shininess: 10.0\n\n.mat.unused", "shininess: many\n\n.mat.unused");
+
+        let diags = check_scene(&scene, Path::new("."));
+        let errors: Vec<String> = diags.iter().filter(|d| d.severity == Severity::Error).map(|d| d.to_string()).collect();
+
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert_eq!(errors[0], "8:14: error: .mat.red: In shininess: many is not defined");
+        assert_eq!(errors[1], "19:10: error: camera: width: expected integer");
+        assert!(errors[2].starts_with("28:17: error: lights[0]"));
+        assert_eq!(errors[3], "32:17: error: shapes[0]: undefined material .mat.blue");
+    }
+
+    #[test]
+    fn references() {
+        let scene = SCENE.replace("camera:", "
+.mat.derived:
+  extend: .mat.unused
+  reflective: 0.5
+
+.mat.broken:
+  extend: .mat.none
+
+.xf.spare:
+  - scale: [ 2.0, 2.0, 2.0 ]
+
+camera:");
+
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+
+        assert_eq!(msgs.len(), 7, "{:?}", msgs);
+        assert_eq!(msgs[0], "19:1: warning: material .mat.derived is never used");
+        assert_eq!(msgs[1], "23:1: warning: material .mat.broken is never used");
+        assert_eq!(msgs[2], "24:11: error: .mat.broken: In extend: .mat.none is not defined");
+        assert_eq!(msgs[3], "26:1: warning: transformation list .xf.spare is never used");
+    }
+
+    #[test]
+    fn repeat() {
+        let scene = "
+variables:
+  n: 4
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from: [ 0.0, 0.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+lights:
+  - point:
+      position: [ 0.0, 10.0, -5.0 ]
+shapes:
+  - repeat:
+      for: i
+      range: n
+      count: 3
+      shapes:
+        - sphere:
+            material: .mat.none
+        - cube:
+            material: .mat.red
+            transformations:
+              - scale: [ i, 1.0, 1.0 ]
+  - repeat:
+      for: j
+      range: [ n, 0 ]
+      shapes: []
+  - repeat:
+      for: k
+      range: [ 0, m ]
+.mat.red:
+  texture:
+    color: [ 1.0, 0.0, 0.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.0
+  shininess: 10.0
+";
+
+        let msgs = messages(&check_scene(scene, Path::new(".")));
+
+        assert_eq!(msgs, vec![
+                "17:7: warning: shapes[0]: unknown key \"count\"",
+                "20:23: error: shapes[0].shapes[0]: undefined material .mat.none",
+                "24:15: warning: shapes[0].shapes[1]: transformation is singular (zero scale?) and can't be inverted",
+                "27:14: warning: shapes[1]: range is empty",
+                "31:14: error: shapes[2]: In range: m is not defined"
+            ]);
+    }
+
+    #[test]
+    fn keyframes() {
+        let scene = SCENE.replace("  from: [ 0.0, 5.0, 0.0 ]", "
+  from:
+    keyframes:
+      0: [ 0.0, 5.0, 0.0 ]
+      24: [ 0.0, 5.0, frame ]
+    interpolation: smooth
+    ease: in").replace("      material: .mat.red\n  - cube:", "      material: .mat.red
+      transformations:
+        - rotate_y: { keyframes: { 0: 0.0, 24: 360.0 } }
+  - cube:");
+
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+
+        assert_eq!(msgs.len(), 4, "{:?}", msgs);
+        assert_eq!(msgs[1], "27:20: error: animated value: In interpolation: unknown value smooth");
+        assert_eq!(msgs[2], "28:5: warning: animated value: unknown key \"ease\"");
+
+        let msgs = messages(&check_scene(&scene.replace("interpolation: smooth\n    ease: in", "interpolation: spline"), Path::new(".")));
+        assert!(msgs.iter().all(|m| m.contains(": warning: ")), "{:?}", msgs);
+    }
+
+    #[test]
+    fn book_scene() {
+        let scene = "
+- add: light
+  at: [ 0, 0, 0 ]
+
+- add: torus
+  min: 0
+";
+
+        let msgs = messages(&check_scene(scene, Path::new(".")));
+        assert_eq!(msgs, vec![ "5:3: error: In item 2: unknown value shape torus" ]);
+    }
+
+    #[test]
+    fn aliases() {
+        let scene = "
+.mat.red: &RED
+  texture:
+    color: [ 1.0, 0.0, 0.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.0
+  shininess: 10.0
+
+.lights: &L
+  - point:
+      position: [ 0.0, 5.0, -5.0 ]
+
+.shapes: &S
+  - sphere:
+      material: *RED
+
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from: [ 0.0, 1.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+
+lights: *L
+shapes: *S
+";
+
+        let msgs = messages(&check_scene(scene, Path::new(".")));
+        assert!(msgs.is_empty(), "{:?}", msgs);
+
+        // problems inside an alias are reported where it is used
+        let scene = scene.replace("[ 0.0, 5.0, -5.0 ]", "[ 0.0, 0.0, 0.0 ]").replace("      material: *RED", "      material: *RED\n      size: 2");
+        let msgs = messages(&check_scene(&scene, Path::new(".")));
+        assert_eq!(msgs, vec![ "26:9: warning: lights[0] is inside opaque shapes[0]", "27:9: warning: shapes[0]: unknown key \"size\"" ]);
+    }
+
+    #[test]
+    fn syntax_error() {
+        let diags = check_scene("camera:\n  width: [ 1, 2\n", Path::new("."));
+
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert_eq!(diags[0].line, 3);
+    }
+}
@@ -1,33 +1,48 @@
-use crate::camera::Camera;
+use crate::animation;
+use crate::animation::Interpolation;
+use crate::bookscene;
+use crate::camera::{Camera,FisheyeMapping,Projection,Stereo,StereoLayout};
 use crate::color::Color;
+use crate::cone::Cone;
 use crate::cube::Cube;
 use crate::cylinder::Cylinder;
-use crate::linalg::{M4,V4};
+use crate::expr;
+use crate::framebuffer::Rect;
+use crate::linalg::{Float,M4,V4};
 use crate::lighting::LightSource;
 use crate::material::{Material,Texture};
-use crate::pattern::{Checkerboard,Pattern,Ring,Stripes,TransformedPattern};
+use crate::mesh::Triangle;
+use crate::pattern::{Blended,Checkerboard,Gradient,Pattern,Ring,Solid,Stripes,TransformedPattern};
 use crate::plane::Plane;
 use crate::shape::{BaseShape,Shape};
 use crate::sphere::Sphere;
+use crate::tonemap::{Encoding,ToneMap,ToneOperator};
 use crate::transform::Transform;
 use crate::world::World;
 
-use yaml_rust::{Yaml,YamlLoader};
+use yaml_rust::{yaml,Yaml,YamlEmitter,YamlLoader};
 
 use std::error;
 use std::fmt;
+use std::fs;
+use std::path::{Path,PathBuf};
 use std::rc::Rc;
 
 type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 #[derive(Debug)]
-enum ParseError {
+pub(crate) enum ParseError {
     Missing,
     MissingElem(&'static str),
     UnknownValue(String),
+    Undefined(String),
     WrongType(&'static str),
     WrongTypeFor(&'static str,&'static str),
-    In(&'static str, Box<dyn error::Error>)
+    /// More than the given number of something
+    Limit(&'static str, usize),
+    In(&'static str, Box<dyn error::Error>),
+    /// In the n-th (0-based) entry of a list of commands
+    Item(usize, Box<dyn error::Error>)
 }
 
 impl fmt::Display for ParseError {
@@ -39,14 +54,23 @@ impl fmt::Display for ParseError {
                 => f.write_fmt(format_args!("\"{}\" missing", s)),
             ParseError::UnknownValue(s)
                 => f.write_fmt(format_args!("unknown value {}", s)),
+            ParseError::Undefined(s)
+                => f.write_fmt(format_args!("{} is not defined", s)),
             ParseError::WrongType(typestr)
                 => f.write_fmt(format_args!("expected {}", typestr)),
             ParseError::WrongTypeFor(elem,typestr)
                 => f.write_fmt(format_args!("{}: expected {}", elem, typestr)),
+            ParseError::Limit(what,n)
+                => f.write_fmt(format_args!("more than {} {}", n, what)),
             ParseError::In(elem,err)
                 => {
                     f.write_fmt(format_args!("In {}: ", elem))?;
                     err.fmt(f)
+                },
+            ParseError::Item(i,err)
+                => {
+                    f.write_fmt(format_args!("In item {}: ", i + 1))?;
+                    err.fmt(f)
                 }
         }
     }
@@ -54,25 +78,96 @@ impl fmt::Display for ParseError {
 
 impl error::Error for ParseError {}
 
+/// The chain of keys leading to the element an error is about, outermost
+/// first.
+pub(crate) fn error_path(e: &(dyn error::Error + 'static)) -> Vec<&'static str> {
+    let mut path = Vec::new();
+    let mut e = e;
+
+    while let Some(pe) = e.downcast_ref::<ParseError>() {
+        match pe {
+            ParseError::In(elem, inner) => {
+                path.push(*elem);
+                e = inner.as_ref()
+            },
+            ParseError::MissingElem(elem) | ParseError::WrongTypeFor(elem, _) => {
+                path.push(*elem);
+                break
+            },
+            _ => break
+        }
+    }
+
+    path
+}
+
+// Top-level lists that includes add to rather than replace.
+const LIST_KEYS: &[&str] = &[ "lights", "shapes" ];
+
+// Keys understood in each kind of element, for the scene checker.
+pub(crate) const SCENE_KEYS    : &[&str] = &[ "include", "variables", "camera", "lights", "shapes" ];
+pub(crate) const CAMERA_KEYS   : &[&str] = &[ "width", "height", "field_of_view", "from", "to", "up",
+                                              "exposure", "tone_operator", "encoding", "crop", "shutter",
+                                              "projection", "view_width", "fisheye_mapping", "stereo" ];
+pub(crate) const STEREO_KEYS   : &[&str] = &[ "layout", "eye_distance", "convergence" ];
+pub(crate) const LIGHT_KEYS    : &[&str] = &[ "position", "intensity" ];
+pub(crate) const SHAPE_KEYS    : &[&str] = &[ "transformations", "material", "motion" ];
+pub(crate) const CYLINDER_KEYS : &[&str] = &[ "transformations", "material", "motion", "min", "max" ];
+pub(crate) const TRIANGLE_KEYS : &[&str] = &[ "transformations", "material", "motion", "p1", "p2", "p3" ];
+pub(crate) const MATERIAL_KEYS : &[&str] = &[ "texture", "ambient", "diffuse", "specular", "shininess",
+                                              "reflective", "transparency", "refractive_index", "extend" ];
+pub(crate) const PATTERN_KEYS  : &[&str] = &[ "a", "b", "transformations" ];
+pub(crate) const REPEAT_KEYS   : &[&str] = &[ "for", "range", "shapes" ];
+pub(crate) const ANIMATED_KEYS : &[&str] = &[ "keyframes", "interpolation" ];
+
 const TYPE_V3  : &str = "3 floating-point values";
-const TYPE_F32 : &str = "floating-point value";
+const TYPE_FLOAT : &str = "floating-point value";
+const TYPE_FILES: &str = "file name or list of file names";
+const TYPE_RECT: &str = "4 integers (x0, y0, x1, y1)";
+const TYPE_M4  : &str = "16 floating-point values (row by row)";
+const TYPE_SHEAR: &str = "6 floating-point values (xy, xz, yx, yz, zx, zy)";
+const TYPE_TRANSFORMS: &str = "array or name of a transformation list";
+const TYPE_NUMBER: &str = "number or expression";
+const TYPE_RANGE: &str = "end, or 2 or 3 numbers (start, end, step)";
+const TYPE_KEYFRAMES: &str = "dict of frame: value";
+const TYPE_SHUTTER: &str = "2 floating-point values (open, close)";
+const TYPE_MOTION: &str = "dict of time: transformations";
+
+/// Most values a `repeat:` range may have, so that a typo can't make
+/// the parser run out of memory.
+const MAX_RANGE_COUNT: f64 = 100_000.0;
+
+/// Samples per frame of the shutter interval for the motion of animated
+/// shapes.
+const MOTION_STEPS: f64 = 8.0;
+
+/// A floating-point literal or a constant expression.
+fn read_real(node: &Yaml, typestr: &'static str) -> Result<Float> {
+    match node {
+        Yaml::Real(s) => Ok(s.parse::<Float>()?),
+        Yaml::String(s) => match expr::eval(s, &[]) {
+            Ok(v) => Ok(v as Float),
+            Err(expr::ExprError::Syntax(_)) => Err(ParseError::WrongType(typestr).into()),
+            Err(e) => Err(e.into())
+        },
+        Yaml::BadValue => Err(ParseError::Missing.into()),
+        _ => Err(ParseError::WrongType(typestr).into())
+    }
+}
 
-fn read_v3_data(v: &Vec<Yaml>) -> Result<[f32;3]> {
+fn read_v3_data(v: &Vec<Yaml>) -> Result<[Float;3]> {
     if v.len() != 3 {
         return Err(ParseError::WrongType(TYPE_V3).into())
     } else {
         let mut ret = [ 0.0, 0.0, 0.0 ];
         for i in 0..3 {
-            ret[i] = match &v[i] {
-                Yaml::Real(s) => s.parse::<f32>()?,
-                _ => return Err(ParseError::WrongType(TYPE_V3).into())
-            }
+            ret[i] = read_real(&v[i], TYPE_V3)?
         }
         Ok (ret)
     }
 }
 
-fn read_v3(yml: &Yaml) -> Result<[f32;3]> {
+pub(crate) fn read_v3(yml: &Yaml) -> Result<[Float;3]> {
     match yml {
         Yaml::Array(v) => Ok(read_v3_data(&v)?),
         Yaml::BadValue => Err(ParseError::Missing.into()),
@@ -80,7 +175,35 @@ fn read_v3(yml: &Yaml) -> Result<[f32;3]> {
     }
 }
 
-fn read_v3_or(yml: &Yaml, default: &[f32;3]) -> Result<[f32;3]> {
+fn read_m4(yml: &Yaml) -> Result<M4> {
+    let v = match yml {
+        Yaml::Array(v) if v.len() == 16 => v,
+        _ => return Err(ParseError::WrongType(TYPE_M4).into())
+    };
+
+    let mut m = [ 0.0; 16 ];
+    for i in 0..16 {
+        m[i] = read_real(&v[i], TYPE_M4)?
+    }
+
+    Ok(m.into())
+}
+
+fn read_shear(yml: &Yaml) -> Result<[Float;6]> {
+    let v = match yml {
+        Yaml::Array(v) if v.len() == 6 => v,
+        _ => return Err(ParseError::WrongType(TYPE_SHEAR).into())
+    };
+
+    let mut s = [ 0.0; 6 ];
+    for i in 0..6 {
+        s[i] = read_real(&v[i], TYPE_SHEAR)?
+    }
+
+    Ok(s)
+}
+
+pub(crate) fn read_v3_or(yml: &Yaml, default: &[Float;3]) -> Result<[Float;3]> {
     match yml {
         Yaml::Array(v) => Ok(read_v3_data(&v)?),
         Yaml::BadValue => Ok(*default),
@@ -88,27 +211,152 @@ fn read_v3_or(yml: &Yaml, default: &[f32;3]) -> Result<[f32;3]> {
     }
 }
 
-fn read_f32(node: &Yaml) -> Result<f32> {
-    let val = match node {
-        Yaml::Real(s) => s.parse::<f32>()?,
-        Yaml::BadValue => return Err(ParseError::Missing.into()),
-        _ => return Err(ParseError::WrongType(TYPE_F32).into())
+fn read_float(node: &Yaml) -> Result<Float> {
+    read_real(node, TYPE_FLOAT)
+}
+
+fn read_float_or(node: &Yaml, default: Float) -> Result<Float> {
+    match node {
+        Yaml::BadValue => Ok(default),
+        _ => read_real(node, TYPE_FLOAT)
+    }
+}
+
+fn read_tonemap(node: &Yaml) -> Result<ToneMap> {
+    let mut tonemap = ToneMap::new();
+
+    tonemap.exposure = match read_float_or(&node["exposure"], 0.0) {
+        Ok(v) => v,
+        Err(e) => return Err(ParseError::In("exposure", e).into())
+    };
+
+    match &node["tone_operator"] {
+        Yaml::String(s) => {
+            tonemap.operator = match ToneOperator::from_name(s) {
+                Some(op) => op,
+                None => return Err(ParseError::In("tone_operator", ParseError::UnknownValue(s.clone()).into()).into())
+            }
+        },
+        Yaml::BadValue => (),
+        _ => return Err(ParseError::WrongTypeFor("tone_operator", "string").into())
+    }
+
+    match &node["encoding"] {
+        Yaml::String(s) => {
+            tonemap.encoding = match Encoding::from_name(s) {
+                Some(enc) => enc,
+                None => return Err(ParseError::In("encoding", ParseError::UnknownValue(s.clone()).into()).into())
+            }
+        },
+        Yaml::BadValue => (),
+        _ => return Err(ParseError::WrongTypeFor("encoding", "string").into())
+    }
+
+    Ok(tonemap)
+}
+
+fn read_crop(node: &Yaml) -> Result<Rect> {
+    let v = match node {
+        Yaml::Array(v) if v.len() == 4 => v,
+        _ => return Err(ParseError::WrongType(TYPE_RECT).into())
+    };
+
+    let mut ret = [ 0; 4 ];
+    for i in 0..4 {
+        ret[i] = match v[i] {
+            Yaml::Integer(n) if n >= 0 => n as usize,
+            _ => return Err(ParseError::WrongType(TYPE_RECT).into())
+        }
+    }
+
+    Ok(Rect { x0: ret[0], y0: ret[1], x1: ret[2], y1: ret[3] })
+}
+
+fn read_shutter(node: &Yaml) -> Result<(Float,Float)> {
+    let (open, close) = match node {
+        Yaml::Array(v) if v.len() == 2 => (read_real(&v[0], TYPE_SHUTTER)?, read_real(&v[1], TYPE_SHUTTER)?),
+        _ => return Err(ParseError::WrongType(TYPE_SHUTTER).into())
+    };
+
+    if close < open {
+        return Err("shutter closes before it opens".into())
+    }
+
+    Ok((open, close))
+}
+
+fn read_projection(node: &Yaml) -> Result<Projection> {
+    let name = match &node["projection"] {
+        Yaml::String(s) => s.as_str(),
+        Yaml::BadValue => "perspective",
+        _ => return Err(ParseError::WrongTypeFor("projection", "string").into())
+    };
+
+    let projection = match name {
+        "perspective" => Projection::Perspective,
+        "orthographic" => {
+            let width = match &node["view_width"] {
+                Yaml::BadValue => return Err(ParseError::MissingElem("view_width").into()),
+                wnode => match read_float(wnode) {
+                    Ok(w) if w > 0.0 => w,
+                    Ok(_) => return Err(ParseError::In("view_width", "must be positive".into()).into()),
+                    Err(e) => return Err(ParseError::In("view_width", e).into())
+                }
+            };
+
+            Projection::Orthographic { width }
+        },
+        "fisheye" => match &node["fisheye_mapping"] {
+            Yaml::BadValue => Projection::Fisheye(FisheyeMapping::Equidistant),
+            Yaml::String(s) => match FisheyeMapping::from_name(s) {
+                Some(m) => Projection::Fisheye(m),
+                None => return Err(ParseError::In("fisheye_mapping", ParseError::UnknownValue(s.clone()).into()).into())
+            },
+            _ => return Err(ParseError::WrongTypeFor("fisheye_mapping", "equidistant or equisolid").into())
+        },
+        "equirectangular" => Projection::Equirectangular,
+        _ => return Err(ParseError::In("projection", ParseError::UnknownValue(String::from(name)).into()).into())
     };
 
-    Ok(val)
+    Ok(projection)
 }
 
-fn read_f32_or(node: &Yaml, default: f32) -> Result<f32> {
-    let val = match node {
-        Yaml::Real(s) => s.parse::<f32>()?,
-        Yaml::BadValue => default,
-        _ => return Err(ParseError::WrongType(TYPE_F32).into())
+fn read_stereo(node: &Yaml) -> Result<Stereo> {
+    if node.as_hash().is_none() {
+        return Err(ParseError::WrongType("dict").into())
+    }
+
+    let layout = match &node["layout"] {
+        Yaml::BadValue => StereoLayout::SideBySide,
+        Yaml::String(s) => match StereoLayout::from_name(s) {
+            Some(l) => l,
+            None => return Err(ParseError::In("layout", ParseError::UnknownValue(s.clone()).into()).into())
+        },
+        _ => return Err(ParseError::WrongTypeFor("layout", "side_by_side or over_under").into())
+    };
+
+    let eye_distance = match &node["eye_distance"] {
+        Yaml::BadValue => return Err(ParseError::MissingElem("eye_distance").into()),
+        dnode => match read_float(dnode) {
+            Ok(d) if d >= 0.0 => d,
+            Ok(_) => return Err(ParseError::In("eye_distance", "must not be negative".into()).into()),
+            Err(e) => return Err(ParseError::In("eye_distance", e).into())
+        }
+    };
+
+    let convergence = match &node["convergence"] {
+        Yaml::BadValue => None,
+        cnode => match read_float(cnode) {
+            Ok(c) if c > 0.0 => Some(c),
+            Ok(_) => return Err(ParseError::In("convergence", "must be positive".into()).into()),
+            Err(e) => return Err(ParseError::In("convergence", e).into())
+        }
     };
 
-    Ok(val)
+    Ok(Stereo { layout, eye_distance, convergence })
 }
 
-fn read_camera(node: &Yaml) -> Result<Camera> {
+pub(crate) fn read_camera(node: &Yaml) -> Result<Camera> {
     let mut width_height = [ ("width", 0), ("height", 0) ];
     for elem in width_height.iter_mut() {
         elem.1 = match node[elem.0] {
@@ -118,11 +366,23 @@ fn read_camera(node: &Yaml) -> Result<Camera> {
         }
     }
 
-    let fov = match read_f32(&node["field_of_view"]) {
-        Ok(v) => v,
-        Err(e) => return Err(ParseError::In("field_of_view", e).into())
+    let projection = read_projection(node)?;
+
+    // only perspective and fisheye cameras need a field of view
+    let fov = match (projection, &node["field_of_view"]) {
+        (Projection::Orthographic {..}, Yaml::BadValue) | (Projection::Equirectangular, Yaml::BadValue) => 90.0,
+        (_, fnode) => match read_float(fnode) {
+            Ok(v) => v,
+            Err(e) => return Err(ParseError::In("field_of_view", e).into())
+        }
     };
 
+    if let Projection::Fisheye(_) = projection {
+        if fov <= 0.0 || fov > 360.0 {
+            return Err(ParseError::In("field_of_view", "a fisheye sees between 0 and 360 degrees".into()).into())
+        }
+    }
+
     let from = match read_v3(&node["from"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("from", e).into())
@@ -145,7 +405,49 @@ fn read_camera(node: &Yaml) -> Result<Camera> {
 
     let vt = Transform::view_transform(&from, &to, &up);
 
-    Ok(Camera::new(w, h, fov.to_radians(), &vt.matrix))
+    let mut camera = Camera::new(w, h, fov.to_radians(), &vt.matrix);
+    camera.set_tonemap(read_tonemap(node)?);
+    camera.set_projection(projection);
+
+    if !node["stereo"].is_badvalue() {
+        let stereo = match read_stereo(&node["stereo"]) {
+            Ok(s) => s,
+            Err(e) => return Err(ParseError::In("stereo", e).into())
+        };
+
+        if !matches!(projection, Projection::Perspective | Projection::Equirectangular) {
+            return Err(ParseError::In("stereo", format!("not possible with the {} projection", projection.name()).into()).into())
+        }
+
+        camera.set_stereo(Some(stereo))
+    }
+
+    match &node["crop"] {
+        Yaml::BadValue => (),
+        cropnode => {
+            let crop = match read_crop(cropnode) {
+                Ok(r) => r,
+                Err(e) => return Err(ParseError::In("crop", e).into())
+            };
+
+            if !crop.fits(w, h) {
+                return Err(ParseError::In("crop", "window outside the image".into()).into())
+            }
+
+            camera.set_crop(Some(crop))
+        }
+    }
+
+    if !node["shutter"].is_badvalue() {
+        let (open, close) = match read_shutter(&node["shutter"]) {
+            Ok(s) => s,
+            Err(e) => return Err(ParseError::In("shutter", e).into())
+        };
+
+        camera.set_shutter(open, close)
+    }
+
+    Ok(camera)
 }
 
 fn read_pointlight(node: &Yaml) -> Result<LightSource> {
@@ -165,7 +467,7 @@ fn read_pointlight(node: &Yaml) -> Result<LightSource> {
     Ok(LightSource { pos: pos, intensity: col })
 }
 
-fn read_lights(node: &Yaml) -> Result<Vec<LightSource>> {
+pub(crate) fn read_lights(node: &Yaml) -> Result<Vec<LightSource>> {
     let mut lights = Vec::new();
 
     match node {
@@ -196,7 +498,7 @@ fn read_lights(node: &Yaml) -> Result<Vec<LightSource>> {
     Ok(lights)
 }
 
-fn read_2col_pattern<F,P>(node: &Yaml, new: F) -> Result<Texture>
+fn read_2col_pattern<F,P>(root: &Yaml, node: &Yaml, new: F) -> Result<Texture>
 where
     P: Pattern + 'static,
     F: Fn(Color,Color) -> P
@@ -213,19 +515,44 @@ where
     let a = Color::new(a[0], a[1], a[2]);
     let b = Color::new(b[0], b[1], b[2]);
 
-    let p = Rc::new(new(a, b));
+    with_transformations(root, node, Rc::new(new(a, b)))
+}
+
+/// Two textures, each a color or a pattern, averaged.
+fn read_blended(root: &Yaml, node: &Yaml) -> Result<Texture> {
+    let mut patterns = Vec::new();
+
+    for name in [ "a", "b" ].iter() {
+        let p: Rc<dyn Pattern> = match read_texture(root, &node[*name]) {
+            Ok(Texture::Color(c)) => Rc::new(Solid::new(c)),
+            Ok(Texture::Pattern(p)) => p,
+            Err(e) => return Err(ParseError::In(name, e).into())
+        };
+
+        patterns.push(p);
+    }
+
+    let b = patterns.pop().unwrap();
+    let a = patterns.pop().unwrap();
+
+    with_transformations(root, node, Rc::new(Blended::new(a, b)))
+}
 
+/// A pattern with the transformations in `node`, if it has any.
+fn with_transformations(root: &Yaml, node: &Yaml, p: Rc<dyn Pattern>) -> Result<Texture> {
     match &node["transformations"] {
-        Yaml::Array(v) => {
-            let t = read_transformations(&v)?;
+        Yaml::BadValue => Ok(Texture::Pattern(p)),
+        tnode => {
+            let t = match read_transformations(root, tnode) {
+                Ok(t) => t,
+                Err(e) => return Err(ParseError::In("transformations", e).into())
+            };
             Ok(Texture::Pattern(Rc::new(TransformedPattern::new_from_rc(p, &t))))
         }
-        Yaml::BadValue => Ok(Texture::Pattern(p)),
-        _ => Err(ParseError::WrongTypeFor("transformations", "array").into())
     }
 }
 
-fn read_texture(node: &Yaml) -> Result<Texture> {
+fn read_texture(root: &Yaml, node: &Yaml) -> Result<Texture> {
     match node {
         Yaml::Hash(kv) => {
             match kv.iter().next() {
@@ -237,14 +564,16 @@ fn read_texture(node: &Yaml) -> Result<Texture> {
                             Ok(Texture::Color(Color::new(col[0], col[1], col[2])))
                         },
                         "stripes" => {
-                            return read_2col_pattern(val, Stripes::new)
+                            return read_2col_pattern(root, val, Stripes::new)
                         },
                         "checkerboard" => {
-                            return read_2col_pattern(val, Checkerboard::new)
+                            return read_2col_pattern(root, val, Checkerboard::new)
                         },
                         "ring" => {
-                            return read_2col_pattern(val, Ring::new)
+                            return read_2col_pattern(root, val, Ring::new)
                         },
+                        "gradient" => read_2col_pattern(root, val, Gradient::new),
+                        "blended" => read_blended(root, val),
                         _ => return Err(ParseError::UnknownValue(String::from(key)).into())
                     }
                 },
@@ -256,36 +585,86 @@ fn read_texture(node: &Yaml) -> Result<Texture> {
     }
 }
 
-fn read_material(node: &Yaml) -> Result<Material> {
-    let texture = match read_texture(&node["texture"]) {
+/// Look up a named top-level definition, e.g. ".mat.red".
+fn definition<'a>(root: &'a Yaml, name: &str) -> Result<&'a Yaml> {
+    match &root[name] {
+        Yaml::BadValue => Err(ParseError::Undefined(String::from(name)).into()),
+        node => Ok(node)
+    }
+}
+
+/// Flatten a material's `extend:` chain: the keys of the named material it
+/// extends, overridden by its own.
+fn resolve_material(root: &Yaml, node: &Yaml, chain: &mut Vec<String>) -> Result<Yaml> {
+    let own = match node {
+        Yaml::Hash(kv) => kv,
+        Yaml::BadValue => return Err(ParseError::Missing.into()),
+        _ => return Err(ParseError::WrongType("dict").into())
+    };
+
+    let extend = Yaml::String(String::from("extend"));
+
+    let name = match own.get(&extend) {
+        Some(Yaml::String(s)) => s,
+        Some(_) => return Err(ParseError::WrongTypeFor("extend", "material name").into()),
+        None => return Ok(node.clone())
+    };
+
+    if chain.contains(name) {
+        chain.push(name.clone());
+        return Err(ParseError::In("extend", format!("cycle: {}", chain.join(" -> ")).into()).into())
+    }
+
+    chain.push(name.clone());
+    let base = definition(root, name).and_then(|base| resolve_material(root, base, chain));
+    chain.pop();
+
+    let mut merged = match base {
+        Ok(Yaml::Hash(kv)) => kv,
+        Ok(_) => return Err(ParseError::In("extend", ParseError::WrongType("dict").into()).into()),
+        Err(e) => return Err(ParseError::In("extend", e).into())
+    };
+
+    for (key, val) in own.iter().filter(|(k, _)| **k != extend) {
+        merged.insert(key.clone(), val.clone());
+    }
+
+    Ok(Yaml::Hash(merged))
+}
+
+/// Read a material; `root` is the scene document, for resolving `extend:`.
+pub(crate) fn read_material(root: &Yaml, node: &Yaml) -> Result<Material> {
+    let node = &resolve_material(root, node, &mut Vec::new())?;
+
+    let texture = match read_texture(root, &node["texture"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("texture", e).into())
     };
-    let ambient = match read_f32(&node["ambient"]) {
+    let ambient = match read_float(&node["ambient"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("ambient", e).into())
     };
-    let diffuse = match read_f32(&node["diffuse"]) {
+    let diffuse = match read_float(&node["diffuse"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("diffuse", e).into())
     };
-    let specular = match read_f32(&node["specular"]) {
+    let specular = match read_float(&node["specular"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("specular", e).into())
     };
-    let shininess = match read_f32(&node["shininess"]) {
+    let shininess = match read_float(&node["shininess"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("shininess", e).into())
     };
-    let reflective = match read_f32_or(&node["reflective"], 0.0) {
+    let reflective = match read_float_or(&node["reflective"], 0.0) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("reflective", e).into())
     };
-    let transparency = match read_f32_or(&node["transparency"], 0.0) {
+    let transparency = match read_float_or(&node["transparency"], 0.0) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("transparency", e).into())
     };
-    let refractive_index = match read_f32_or(&node["refractive_index"], 1.0) {
+    let refractive_index = match read_float_or(&node["refractive_index"], 1.0) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("refractive_index", e).into())
     };
@@ -302,8 +681,81 @@ fn read_material(node: &Yaml) -> Result<Material> {
     })
 }
 
-fn read_transformations(nodes: &Vec<Yaml>) -> Result<M4> {
-    let mut trans = Transform::new();
+/// A rotation around an arbitrary axis: `{ axis: [x, y, z], angle: degrees }`.
+fn read_rotate(node: &Yaml) -> Result<(V4,Float)> {
+    if node.as_hash().is_none() {
+        return Err(ParseError::WrongType("dict").into())
+    }
+
+    let axis = match read_v3(&node["axis"]) {
+        Ok(a) if a.iter().any(|&c| c != 0.0) => V4::new_vector(a[0], a[1], a[2]),
+        Ok(_) => return Err(ParseError::In("axis", "must not be zero".into()).into()),
+        Err(e) => return Err(ParseError::In("axis", e).into())
+    };
+    let angle = match read_float(&node["angle"]) {
+        Ok(a) => a,
+        Err(e) => return Err(ParseError::In("angle", e).into())
+    };
+
+    Ok((axis, angle.to_radians()))
+}
+
+/// Where an object is placed and what it faces: `{ from, to, up }`, with up
+/// defaulting to +y like the camera's.
+fn read_look_at(node: &Yaml) -> Result<(V4,V4,V4)> {
+    if node.as_hash().is_none() {
+        return Err(ParseError::WrongType("dict").into())
+    }
+
+    let mut points = [ ("from", V4::new_point(0.0, 0.0, 0.0)), ("to", V4::new_point(0.0, 0.0, 0.0)) ];
+    for elem in points.iter_mut() {
+        elem.1 = match read_v3(&node[elem.0]) {
+            Ok(v) => V4::new_point(v[0], v[1], v[2]),
+            Err(e) => return Err(ParseError::In(elem.0, e).into())
+        }
+    }
+    let up = match read_v3_or(&node["up"], &[ 0.0, 1.0, 0.0 ]) {
+        Ok(v) => V4::new_vector(v[0], v[1], v[2]),
+        Err(e) => return Err(ParseError::In("up", e).into())
+    };
+
+    let (from, to) = (points[0].1, points[1].1);
+
+    if from == to {
+        return Err("from and to are the same point".into())
+    }
+    if V4::cross(&(to - from), &up) == V4::new_vector(0.0, 0.0, 0.0) {
+        return Err("up is parallel to the direction looked in".into())
+    }
+
+    Ok((from, to, up))
+}
+
+/// Read a transformation list, or the name of one. Lists may also refer to
+/// other named lists, which are applied in place.
+pub(crate) fn read_transformations(root: &Yaml, node: &Yaml) -> Result<M4> {
+    Ok(add_transformations(root, node, Transform::new(), &mut Vec::new())?.matrix)
+}
+
+fn add_transformations(root: &Yaml, node: &Yaml, trans: Transform, chain: &mut Vec<String>) -> Result<Transform> {
+    let nodes = match node {
+        Yaml::Array(v) => v,
+        Yaml::String(name) => {
+            if chain.contains(name) {
+                chain.push(name.clone());
+                return Err(format!("transformation cycle: {}", chain.join(" -> ")).into())
+            }
+
+            chain.push(name.clone());
+            let trans = add_transformations(root, definition(root, name)?, trans, chain)?;
+            chain.pop();
+
+            return Ok(trans)
+        },
+        _ => return Err(ParseError::WrongType(TYPE_TRANSFORMS).into())
+    };
+
+    let mut trans = trans;
 
     for node in nodes.iter() {
         match node {
@@ -321,29 +773,90 @@ fn read_transformations(nodes: &Vec<Yaml>) -> Result<M4> {
                             trans = trans.scale(v[0], v[1], v[2]);
                         },
                         "rotate_x" => {
-                            let v = read_f32(val)?;
+                            let v = read_float(val)?;
                             trans = trans.rotate_x(v.to_radians());
                         },
                         "rotate_y" => {
-                            let v = read_f32(val)?;
+                            let v = read_float(val)?;
                             trans = trans.rotate_y(v.to_radians());
                         },
                         "rotate_z" => {
-                            let v = read_f32(val)?;
+                            let v = read_float(val)?;
                             trans = trans.rotate_z(v.to_radians());
                         },
+                        "rotate" => {
+                            let (axis, rad) = read_rotate(val).map_err(|e| ParseError::In("rotate", e))?;
+                            trans = trans.rotate(&axis, rad);
+                        },
+                        "shear" => {
+                            let v = read_shear(val)?;
+                            trans = trans.shear(v[0], v[1], v[2], v[3], v[4], v[5]);
+                        },
+                        "look_at" => {
+                            let (from, to, up) = read_look_at(val).map_err(|e| ParseError::In("look_at", e))?;
+                            trans = trans.look_at(&from, &to, &up);
+                        },
+                        "matrix" => {
+                            let m = read_m4(val)?;
+                            trans = trans.matrix(&m);
+                        },
                         _ => return Err(ParseError::UnknownValue(String::from(key)).into())
                     }
                 }
-            }
-            _ => return Err(ParseError::WrongType("dict").into())
+            },
+            Yaml::String(_) => trans = add_transformations(root, node, trans, chain)?,
+            _ => return Err(ParseError::WrongType("dict or name").into())
         }
     }
 
-    Ok(trans.matrix)
+    Ok(trans)
+}
+
+/// The transformations of a moving shape by time, sorted.
+fn read_motion(root: &Yaml, node: &Yaml) -> Result<Vec<(Float,M4)>> {
+    let kv = match node {
+        Yaml::Hash(kv) => kv,
+        _ => return Err(ParseError::WrongType(TYPE_MOTION).into())
+    };
+
+    let mut keys = Vec::new();
+
+    for (key, val) in kv.iter() {
+        let time = match key {
+            Yaml::Integer(i) => *i as Float,
+            Yaml::Real(s) => s.parse::<Float>()?,
+            _ => return Err(ParseError::WrongType(TYPE_MOTION).into())
+        };
+
+        keys.push((time, read_transformations(root, val)?));
+    }
+
+    keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    if let Some(w) = keys.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(format!("time {} given twice", w[0].0).into())
+    }
+
+    Ok(keys)
+}
+
+/// Assigns material IDs: one per named material, one per inline material.
+fn material_id(materials: &mut Vec<Option<String>>, name: Option<&str>) -> u32 {
+    let pos = match name {
+        Some(name) => materials.iter().position(|m| m.as_deref() == Some(name)),
+        None => None
+    };
+
+    match pos {
+        Some(p) => (p + 1) as u32,
+        None => {
+            materials.push(name.map(String::from));
+            materials.len() as u32
+        }
+    }
 }
 
-fn read_shape(root: &Yaml, node: &Yaml) -> Result< Rc<Shape> > {
+pub(crate) fn read_shape(root: &Yaml, node: &Yaml, materials: &mut Vec<Option<String>>) -> Result< Rc<Shape> > {
     let shape = match node {
         Yaml::Hash(kv) => {
             match kv.iter().next() {
@@ -351,37 +864,67 @@ fn read_shape(root: &Yaml, node: &Yaml) -> Result< Rc<Shape> > {
                     let key = key.as_str().unwrap();
 
                     let base: Box<dyn BaseShape> = match key {
+                        "cone" => {
+                            let min = read_float_or(&val["min"], Float::MIN)?;
+                            let max = read_float_or(&val["max"], Float::MAX)?;
+                            Box::new(Cone::new_closed(min, max))
+                        },
                         "cube"   => Box::new(Cube()  ),
                         "cylinder" => {
-                            let min = read_f32_or(&val["min"], std::f32::MIN)?;
-                            let max = read_f32_or(&val["max"], std::f32::MAX)?;
+                            let min = read_float_or(&val["min"], Float::MIN)?;
+                            let max = read_float_or(&val["max"], Float::MAX)?;
                             Box::new(Cylinder::new_closed(min, max))
                         },
+                        "open_cone" => {
+                            let min = read_float_or(&val["min"], Float::MIN)?;
+                            let max = read_float_or(&val["max"], Float::MAX)?;
+                            Box::new(Cone::new_truncated(min, max))
+                        },
                         "plane"  => Box::new(Plane() ),
                         "pipe" => {
-                            let min = read_f32_or(&val["min"], std::f32::MIN)?;
-                            let max = read_f32_or(&val["max"], std::f32::MAX)?;
+                            let min = read_float_or(&val["min"], Float::MIN)?;
+                            let max = read_float_or(&val["max"], Float::MAX)?;
                             Box::new(Cylinder::new_truncated(min, max))
                         },
                         "sphere" => Box::new(Sphere()),
+                        "triangle" => {
+                            let mut p = Vec::new();
+                            for name in [ "p1", "p2", "p3" ].iter() {
+                                match read_v3(&val[*name]) {
+                                    Ok(v) => p.push(V4::new_point(v[0], v[1], v[2])),
+                                    Err(e) => return Err(ParseError::In(name, e).into())
+                                }
+                            }
+                            Box::new(Triangle::new(p[0], p[1], p[2]))
+                        },
                         _ => return Err(ParseError::UnknownValue(String::from(key)).into())
                     };
 
                     let trans = match &val["transformations"] {
-                        Yaml::Array(v) => read_transformations(&v)?,
                         Yaml::BadValue => M4::identity(),
-                        _ => return Err(ParseError::WrongTypeFor("transformations", "array").into())
+                        tnode => match read_transformations(root, tnode) {
+                            Ok(t) => t,
+                            Err(e) => return Err(ParseError::In("transformations", e).into())
+                        }
                     };
 
                     let matnode = &val["material"];
-                    let mat = match matnode {
-                        Yaml::Hash(_) => read_material(&matnode)?,
-                        Yaml::String(s) => read_material(&root[s.as_str()])?,
+                    let (mat, mat_id) = match matnode {
+                        Yaml::Hash(_) => (read_material(root, &matnode)?, material_id(materials, None)),
+                        Yaml::String(s) => (read_material(root, definition(root, s)?)?, material_id(materials, Some(s))),
                         Yaml::BadValue => return Err(ParseError::MissingElem("material").into()),
                         _ => return Err(ParseError::WrongTypeFor("material", "dict or entry").into())
                     };
 
-                    Rc::new(Shape::new(base, &mat, &trans))
+                    let motion = match &val["motion"] {
+                        Yaml::BadValue => Vec::new(),
+                        mnode => match read_motion(root, mnode) {
+                            Ok(m) => m,
+                            Err(e) => return Err(ParseError::In("motion", e).into())
+                        }
+                    };
+
+                    Rc::new(Shape::new(base, &mat, &trans).with_material_id(mat_id).with_motion(&motion))
                 },
                 None => return Err(ParseError::Missing.into())
             }
@@ -394,11 +937,12 @@ fn read_shape(root: &Yaml, node: &Yaml) -> Result< Rc<Shape> > {
 
 fn read_shapes(root: &Yaml, node: &Yaml) -> Result< Vec<Rc<Shape>> > {
     let mut shapes = Vec::new();
+    let mut materials = Vec::new();
 
     match node {
         Yaml::Array(v) => {
             for shapenode in v {
-                shapes.push(read_shape(root, shapenode)?);
+                shapes.push(read_shape(root, shapenode, &mut materials)?);
             }
         },
         Yaml::BadValue => return Err(ParseError::Missing.into()),
@@ -408,20 +952,18 @@ fn read_shapes(root: &Yaml, node: &Yaml) -> Result< Vec<Rc<Shape>> > {
     Ok(shapes)
 }
 
-pub fn read_yaml_scene_config(str: &str) -> Result<(Camera,World)> {
-    let docs = YamlLoader::load_from_str(str)?;
-
-    let camera = match read_camera(&docs[0]["camera"]) {
+pub(crate) fn read_scene(doc: &Yaml) -> Result<(Camera,World)> {
+    let camera = match read_camera(&doc["camera"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("camera", e).into())
     };
 
-    let lights = match read_lights(&docs[0]["lights"]) {
+    let lights = match read_lights(&doc["lights"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("lights", e).into())
     };
 
-    let shapes = match read_shapes(&docs[0], &docs[0]["shapes"]) {
+    let shapes = match read_shapes(doc, &doc["shapes"]) {
         Ok(v) => v,
         Err(e) => return Err(ParseError::In("shapes", e).into())
     };
@@ -429,59 +971,631 @@ pub fn read_yaml_scene_config(str: &str) -> Result<(Camera,World)> {
     Ok( (camera, World::new_with(lights, shapes)) )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::linalg::*;
-    use float_cmp::*;
+fn read_number(node: &Yaml, vars: &expr::Vars) -> Result<f64> {
+    match node {
+        Yaml::Integer(i) => Ok(*i as f64),
+        Yaml::Real(s) => Ok(s.parse::<f64>()?),
+        Yaml::String(s) => Ok(expr::eval(s, vars)?),
+        Yaml::BadValue => Err(ParseError::Missing.into()),
+        _ => Err(ParseError::WrongType(TYPE_NUMBER).into())
+    }
+}
 
-    #[test]
-    fn read_camera_ok() {
-        let s =
-"
-width: 640
-height: 480
-field_of_view:
-  60.0
-from: [ 0.0, 1.5, -5.0 ]
-to:
-  - 0.0
-  - 1.0
-  - 0.0
-whatever: 42
-";
-        let docs = YamlLoader::load_from_str(&s).unwrap();
+/// Evaluate the "variables" section at the given frame. The result starts
+/// with the `frame` variable; each variable may use the ones before it.
+pub(crate) fn read_variables(node: &Yaml, frame: f64) -> Result<Vec<(String,f64)>> {
+    let mut vars = vec![ (String::from("frame"), frame) ];
 
-        let cam = read_camera(&docs[0]);
-        assert!(cam.is_ok())
+    let kv = match node {
+        Yaml::Hash(kv) => kv,
+        Yaml::BadValue => return Ok(vars),
+        _ => return Err(ParseError::WrongType("dict").into())
+    };
+
+    for (key, val) in kv.iter() {
+        let name = match key.as_str() {
+            Some(name) if expr::is_identifier(name) => String::from(name),
+            _ => return Err(format!("invalid variable name {:?}", key).into())
+        };
 
+        match substitute(val, &vars).and_then(|v| read_number(&v, &vars)) {
+            Ok(v) => vars.push((name, v)),
+            Err(e) => return Err(format!("{}: {}", name, e).into())
+        }
     }
 
-    #[test]
-    fn read_lights_ok() {
-        let s =
-"
-- point:
-    intensity: [ 1.0, 0.0, 0.0 ]
-    position: [ 4.0, 5.5, -6.0 ]
-- point:
-    position: [ -5.0, 3.0, -8.0 ]
-";
+    Ok(vars)
+}
 
-        let docs = YamlLoader::load_from_str(&s).unwrap();
+pub(crate) fn is_animated(node: &Yaml) -> bool {
+    match node {
+        Yaml::Hash(kv) => kv.contains_key(&Yaml::String(String::from("keyframes"))),
+        _ => false
+    }
+}
 
-        let lights = read_lights(&docs[0]).unwrap();
+/// The keyframes of an animated value, sorted by frame, and how to
+/// interpolate between them. The values aren't evaluated yet.
+pub(crate) fn read_keyframes(node: &Yaml) -> Result<(Vec<(f64,&Yaml)>, Interpolation)> {
+    let interpolation = match &node["interpolation"] {
+        Yaml::BadValue => Interpolation::Linear,
+        Yaml::String(s) => match Interpolation::from_name(s) {
+            Some(i) => i,
+            None => return Err(ParseError::In("interpolation", ParseError::UnknownValue(s.clone()).into()).into())
+        },
+        _ => return Err(ParseError::WrongTypeFor("interpolation", "linear or spline").into())
+    };
 
-        assert_eq!(lights.len(), 2);
-        assert_eq!(lights[0].pos, V4::new_point(4.0, 5.5, -6.0));
-        assert_eq!(lights[1].intensity, Color::WHITE);
+    let kv = match &node["keyframes"] {
+        Yaml::Hash(kv) if !kv.is_empty() => kv,
+        _ => return Err(ParseError::WrongTypeFor("keyframes", TYPE_KEYFRAMES).into())
+    };
+
+    let mut keys = Vec::new();
+
+    for (key, val) in kv.iter() {
+        let frame = match key {
+            Yaml::Integer(i) => *i as f64,
+            Yaml::Real(s) => s.parse::<f64>()?,
+            _ => return Err(ParseError::WrongTypeFor("keyframes", TYPE_KEYFRAMES).into())
+        };
+
+        keys.push((frame, val));
     }
 
-    #[test]
-    fn read_texture_transformation_ok() {
-        let s =
-"
-texture:
+    keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    if let Some(w) = keys.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(ParseError::In("keyframes", format!("frame {} given twice", w[0].0).into()).into())
+    }
+
+    let len = |v: &Yaml| v.as_vec().map(|a| a.len());
+    if keys.iter().any(|(_, v)| len(v) != len(keys[0].1)) {
+        return Err(ParseError::In("keyframes", "values must all be numbers or lists of the same length".into()).into())
+    }
+
+    Ok((keys, interpolation))
+}
+
+/// The value of an animated node at the frame given by the `frame`
+/// variable.
+fn interpolate(node: &Yaml, vars: &expr::Vars) -> Result<Yaml> {
+    let (keys, interpolation) = read_keyframes(node)?;
+    let frame = vars.iter().rev().find(|(n, _)| n == "frame").map_or(0.0, |v| v.1);
+
+    let mut values = Vec::new();
+
+    for (_, val) in keys.iter() {
+        let v = match val {
+            Yaml::Array(a) => a.iter().map(|x| read_number(x, vars)).collect::<Result<Vec<f64>>>(),
+            _ => read_number(val, vars).map(|x| vec![ x ])
+        };
+
+        match v {
+            Ok(v) => values.push(v),
+            Err(e) => return Err(ParseError::In("keyframes", e).into())
+        }
+    }
+
+    let component = |c: usize| {
+        let curve: Vec<(f64,f64)> = keys.iter().zip(values.iter()).map(|(k, v)| (k.0, v[c])).collect();
+        real(animation::sample(&curve, frame, interpolation))
+    };
+
+    match keys[0].1 {
+        Yaml::Array(a) => Ok(Yaml::Array((0..a.len()).map(component).collect())),
+        _ => Ok(component(0))
+    }
+}
+
+/// The values of a loop variable: `range: end`, `[ start, end ]` or
+/// `[ start, end, step ]`, with `end` exclusive.
+pub(crate) fn read_range(node: &Yaml, vars: &expr::Vars) -> Result<Vec<f64>> {
+    let (start, end, step) = match node {
+        Yaml::Array(v) if v.len() == 2 || v.len() == 3 => {
+            let step = match v.get(2) {
+                Some(n) => read_number(n, vars)?,
+                None => 1.0
+            };
+            (read_number(&v[0], vars)?, read_number(&v[1], vars)?, step)
+        },
+        Yaml::Integer(_) | Yaml::Real(_) | Yaml::String(_) => (0.0, read_number(node, vars)?, 1.0),
+        Yaml::BadValue => return Err(ParseError::Missing.into()),
+        _ => return Err(ParseError::WrongType(TYPE_RANGE).into())
+    };
+
+    if step == 0.0 || !step.is_finite() {
+        return Err("step must not be 0".into())
+    }
+
+    let count = ((end - start) / step).ceil().max(0.0);
+
+    if count > MAX_RANGE_COUNT {
+        return Err(ParseError::Limit("values", MAX_RANGE_COUNT as usize).into())
+    }
+
+    let count = count as usize;
+
+    Ok((0..count).map(|k| start + (k as f64) * step).collect())
+}
+
+fn real(v: f64) -> Yaml {
+    Yaml::Real(format!("{:?}", v as Float))
+}
+
+/// Replace the strings in `node` that are expressions over `vars` by their
+/// values, and animated values by their value at the current frame.
+pub(crate) fn substitute(node: &Yaml, vars: &expr::Vars) -> Result<Yaml> {
+    let res = match node {
+        Yaml::String(s) => match expr::eval(s, vars) {
+            Ok(v) => real(v),
+            Err(_) => node.clone()
+        },
+        Yaml::Hash(_) if is_animated(node) => interpolate(node, vars)?,
+        Yaml::Array(v) => Yaml::Array(v.iter().map(|n| substitute(n, vars)).collect::<Result<_>>()?),
+        Yaml::Hash(kv) => Yaml::Hash(kv.iter().map(|(k, v)| Ok((k.clone(), substitute(v, vars)?))).collect::<Result<_>>()?),
+        _ => node.clone()
+    };
+
+    Ok(res)
+}
+
+pub(crate) fn is_repeat(node: &Yaml) -> bool {
+    match node {
+        Yaml::Hash(kv) => kv.keys().next().and_then(|k| k.as_str()) == Some("repeat"),
+        _ => false
+    }
+}
+
+/// Expand the body of a `repeat:` entry into its shapes, once for each
+/// value of the loop variable.
+pub(crate) fn expand_repeat(node: &Yaml, vars: &expr::Vars) -> Result<Vec<Yaml>> {
+    let name = match &node["for"] {
+        Yaml::String(s) if expr::is_identifier(s) => s.clone(),
+        Yaml::BadValue => return Err(ParseError::MissingElem("for").into()),
+        _ => return Err(ParseError::WrongTypeFor("for", "variable name").into())
+    };
+
+    let values = match read_range(&node["range"], vars) {
+        Ok(v) => v,
+        Err(e) => return Err(ParseError::In("range", e).into())
+    };
+
+    let shapes = match &node["shapes"] {
+        Yaml::Array(v) => v,
+        Yaml::BadValue => return Err(ParseError::MissingElem("shapes").into()),
+        _ => return Err(ParseError::WrongTypeFor("shapes", "array").into())
+    };
+
+    let mut scope = vars.to_vec();
+    let mut out = Vec::new();
+
+    for v in values {
+        scope.push((name.clone(), v));
+
+        match expand_shapes(shapes, &scope) {
+            Ok(s) => out.extend(s),
+            Err(e) => return Err(ParseError::In("shapes", e).into())
+        }
+
+        scope.pop();
+    }
+
+    Ok(out)
+}
+
+/// Evaluate expressions in a list of shapes and expand its `repeat:`
+/// entries.
+pub(crate) fn expand_shapes(shapes: &[Yaml], vars: &expr::Vars) -> Result<Vec<Yaml>> {
+    let mut out = Vec::new();
+
+    for (i, node) in shapes.iter().enumerate() {
+        if is_repeat(node) {
+            match expand_repeat(&node["repeat"], vars) {
+                Ok(s) => out.extend(s),
+                Err(e) => return Err(ParseError::Item(i, ParseError::In("repeat", e).into()).into())
+            }
+        } else {
+            match substitute(node, vars) {
+                Ok(s) => out.push(s),
+                Err(e) => return Err(ParseError::Item(i, e).into())
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Evaluate the expressions and animated values in a scene at the given
+/// frame and expand the `repeat:` entries in its shapes. The result has no
+/// variables left.
+pub(crate) fn expand_scene(doc: &Yaml, frame: f64) -> Result<Yaml> {
+    let kv = match doc {
+        Yaml::Hash(kv) => kv,
+        _ => return Ok(doc.clone())
+    };
+
+    let vars = match read_variables(&doc["variables"], frame) {
+        Ok(v) => v,
+        Err(e) => return Err(ParseError::In("variables", e).into())
+    };
+
+    let mut out = yaml::Hash::new();
+
+    for (key, val) in kv.iter() {
+        let val = match (key.as_str(), val) {
+            (Some("variables"), _) => continue,
+            (Some("shapes"), Yaml::Array(v)) => match expand_shapes(v, &vars) {
+                Ok(s) => Yaml::Array(s),
+                Err(e) => return Err(ParseError::In("shapes", e).into())
+            },
+            (Some(k), _) => match substitute(val, &vars) {
+                Ok(v) => v,
+                Err(e) => match SCENE_KEYS.iter().find(|s| **s == k) {
+                    Some(s) => return Err(ParseError::In(s, e).into()),
+                    None => return Err(format!("In {}: {}", k, e).into())
+                }
+            },
+            _ => val.clone()
+        };
+
+        out.insert(key.clone(), val);
+    }
+
+    let shutter = match out.get(&Yaml::String(String::from("camera"))) {
+        Some(camera) => read_shutter(&camera["shutter"]).ok(),
+        None => None
+    };
+
+    if let (Some((open, close)), Some(Yaml::Array(shapes))) = (shutter, out.get_mut(&Yaml::String(String::from("shapes")))) {
+        if open < close {
+            if let Err(e) = add_motion(doc, shapes, frame, open as f64, close as f64) {
+                return Err(ParseError::In("shapes", e).into())
+            }
+        }
+    }
+
+    Ok(Yaml::Hash(out))
+}
+
+/// Give each shape in `shapes`, the shapes of `doc` expanded at `frame`,
+/// whose transformations change while the shutter is open a `motion:`
+/// entry with its transformations over the shutter interval. Shapes that
+/// have one already are left alone.
+fn add_motion(doc: &Yaml, shapes: &mut [Yaml], frame: f64, open: f64, close: f64) -> Result<()> {
+    let template = match &doc["shapes"] {
+        Yaml::Array(v) => v,
+        _ => return Ok(())
+    };
+
+    let steps = ((close - open) * MOTION_STEPS).ceil();
+    let mut samples = Vec::new();
+
+    for k in 0..=(steps as usize) {
+        let time = open + (close - open) * (k as f64) / steps;
+        let shapes_at = expand_shapes(template, &read_variables(&doc["variables"], frame + time)?)?;
+
+        if shapes_at.len() != shapes.len() {
+            return Err("the number of shapes changes while the shutter is open".into())
+        }
+
+        samples.push((time, shapes_at));
+    }
+
+    let transformations = Yaml::String(String::from("transformations"));
+    let motion = Yaml::String(String::from("motion"));
+
+    for (i, shape) in shapes.iter_mut().enumerate() {
+        let body = match shape {
+            Yaml::Hash(kv) => match kv.iter_mut().next() {
+                Some((_, Yaml::Hash(body))) if !body.contains_key(&motion) => body,
+                _ => continue
+            },
+            _ => continue
+        };
+
+        let still = body.get(&transformations).cloned().unwrap_or_else(|| Yaml::Array(Vec::new()));
+        let mut keys = yaml::Hash::new();
+
+        for (time, shapes_at) in samples.iter() {
+            let t = match &shapes_at[i] {
+                Yaml::Hash(kv) => kv.iter().next().map_or(Yaml::BadValue, |(_, v)| v["transformations"].clone()),
+                _ => Yaml::BadValue
+            };
+
+            keys.insert(real(*time), if t.is_badvalue() { Yaml::Array(Vec::new()) } else { t });
+        }
+
+        if keys.values().any(|t| *t != still) {
+            body.insert(motion.clone(), Yaml::Hash(keys));
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge `other` into `doc`: the "lights" and "shapes" lists are
+/// concatenated and variables are merged, any other key in `other`
+/// replaces the one in `doc`.
+fn merge_into(doc: &mut yaml::Hash, other: yaml::Hash) {
+    for (key, val) in other {
+        match (doc.get_mut(&key), val) {
+            (Some(Yaml::Array(a)), Yaml::Array(b)) if LIST_KEYS.contains(&key.as_str().unwrap_or("")) => a.extend(b),
+            (Some(Yaml::Hash(a)), Yaml::Hash(b)) if key.as_str() == Some("variables") => a.extend(b),
+            (_, val) => { doc.insert(key, val); }
+        }
+    }
+}
+
+fn read_include_file(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Yaml> {
+    let canonical = fs::canonicalize(path)?;
+
+    if stack.contains(&canonical) {
+        let mut chain: Vec<String> = stack.iter().map(|p| p.display().to_string()).collect();
+        chain.push(canonical.display().to_string());
+        return Err(format!("include cycle: {}", chain.join(" -> ")).into())
+    }
+
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    stack.push(canonical);
+    let doc = resolve_includes(&source, dir, stack);
+    stack.pop();
+
+    doc
+}
+
+/// Parse `source` and merge in the files it includes, recursively. Include
+/// paths are relative to `dir`. The including document's own keys win
+/// over included ones; included lights and shapes come first.
+fn resolve_includes(source: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Yaml> {
+    let mut docs = YamlLoader::load_from_str(source)?;
+
+    if docs.is_empty() {
+        return Err(ParseError::Missing.into())
+    }
+
+    let mut own = match docs.swap_remove(0) {
+        Yaml::Hash(h) => h,
+        Yaml::Array(v) => bookscene::convert_scene(&v, dir)?,
+        _ => return Err(ParseError::WrongType("dict").into())
+    };
+
+    let files = match own.remove(&Yaml::String(String::from("include"))) {
+        None => Vec::new(),
+        Some(Yaml::String(s)) => vec![ s ],
+        Some(Yaml::Array(v)) => {
+            let mut files = Vec::new();
+            for f in v {
+                match f {
+                    Yaml::String(s) => files.push(s),
+                    _ => return Err(ParseError::WrongTypeFor("include", TYPE_FILES).into())
+                }
+            }
+            files
+        },
+        Some(_) => return Err(ParseError::WrongTypeFor("include", TYPE_FILES).into())
+    };
+
+    let mut merged = yaml::Hash::new();
+
+    for file in files {
+        let path = dir.join(&file);
+
+        match read_include_file(&path, stack) {
+            Ok(Yaml::Hash(h)) => merge_into(&mut merged, h),
+            Ok(_) => return Err(ParseError::In("include", format!("{}: expected dict", file).into()).into()),
+            Err(e) => return Err(ParseError::In("include", format!("{}: {}", file, e).into()).into())
+        }
+    }
+
+    merge_into(&mut merged, own);
+
+    Ok(Yaml::Hash(merged))
+}
+
+/// Parse a scene document and merge in its includes, relative to `dir`,
+/// but leave variables and `repeat:` entries alone.
+pub(crate) fn load_merged_scene(source: &str, dir: &Path) -> Result<Yaml> {
+    resolve_includes(source, dir, &mut Vec::new())
+}
+
+/// Parse a scene document, merge in its includes, relative to `dir`, and
+/// expand its variables and `repeat:` entries at frame 0.
+pub fn load_yaml_scene(source: &str, dir: &Path) -> Result<Yaml> {
+    expand_scene(&load_merged_scene(source, dir)?, 0.0)
+}
+
+/// Read a scene file at frame 0.
+pub fn read_yaml_scene_file(path: &Path) -> Result<(Camera,World,String)> {
+    read_yaml_scene_frame(path, 0.0)
+}
+
+/// Read a scene file at the given frame of its animation. Besides the
+/// camera and world, returns the scene with all includes merged in and
+/// expanded as self-contained YAML text.
+pub fn read_yaml_scene_frame(path: &Path, frame: f64) -> Result<(Camera,World,String)> {
+    let doc = expand_scene(&read_include_file(path, &mut Vec::new())?, frame)?;
+    let (camera, world) = read_scene(&doc)?;
+
+    let mut text = String::new();
+    YamlEmitter::new(&mut text).dump(&doc)?;
+
+    Ok((camera, world, text))
+}
+
+/// Read a scene from YAML text; includes are relative to the working
+/// directory.
+pub fn read_yaml_scene_config(str: &str) -> Result<(Camera,World)> {
+    read_scene(&load_yaml_scene(str, Path::new("."))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linalg::*;
+    use crate::ray::Ray;
+    use float_cmp::*;
+
+    #[test]
+    fn read_camera_ok() {
+        let s =
+"
+width: 640
+height: 480
+field_of_view:
+  60.0
+from: [ 0.0, 1.5, -5.0 ]
+to:
+  - 0.0
+  - 1.0
+  - 0.0
+whatever: 42
+";
+        let docs = YamlLoader::load_from_str(&s).unwrap();
+
+        let cam = read_camera(&docs[0]);
+        assert!(cam.is_ok())
+
+    }
+
+    #[test]
+    fn read_camera_tonemap() {
+        let s =
+"
+width: 640
+height: 480
+field_of_view: 60.0
+from: [ 0.0, 1.5, -5.0 ]
+to: [ 0.0, 1.0, 0.0 ]
+exposure: 1.5
+tone_operator: aces
+encoding: srgb
+";
+        let docs = YamlLoader::load_from_str(s).unwrap();
+
+        let cam = read_camera(&docs[0]).unwrap();
+        assert_eq!(cam.tonemap().exposure, 1.5);
+        assert_eq!(cam.tonemap().operator, ToneOperator::Aces);
+        assert_eq!(cam.tonemap().encoding, Encoding::Srgb);
+
+        let docs = YamlLoader::load_from_str("tone_operator: sepia").unwrap();
+        assert!(read_tonemap(&docs[0]).is_err())
+    }
+
+    #[test]
+    fn read_camera_crop() {
+        let s =
+"
+width: 640
+height: 480
+field_of_view: 60.0
+from: [ 0.0, 1.5, -5.0 ]
+to: [ 0.0, 1.0, 0.0 ]
+crop: [ 100, 50, 300, 200 ]
+";
+        let docs = YamlLoader::load_from_str(s).unwrap();
+
+        let cam = read_camera(&docs[0]).unwrap();
+        assert_eq!(cam.crop(), Some(Rect { x0: 100, y0: 50, x1: 300, y1: 200 }));
+
+        let docs = YamlLoader::load_from_str(&s.replace("300, 200", "700, 200")).unwrap();
+        assert!(read_camera(&docs[0]).is_err());
+
+        let docs = YamlLoader::load_from_str(&s.replace("100, 50, ", "")).unwrap();
+        assert!(read_camera(&docs[0]).is_err());
+    }
+
+    #[test]
+    fn read_camera_projection() {
+        let s =
+"
+width: 640
+height: 320
+field_of_view: 180.0
+from: [ 0.0, 1.5, -5.0 ]
+to: [ 0.0, 1.0, 0.0 ]
+projection: fisheye
+";
+        let camera = |s: &str| read_camera(&YamlLoader::load_from_str(s).unwrap()[0]);
+
+        assert_eq!(camera(s).unwrap().projection(), Projection::Fisheye(FisheyeMapping::Equidistant));
+        assert_eq!(camera(&(String::from(s) + "fisheye_mapping: equisolid")).unwrap().projection(),
+                   Projection::Fisheye(FisheyeMapping::Equisolid));
+        assert_eq!(camera(&s.replace("projection: fisheye", "")).unwrap().projection(), Projection::Perspective);
+
+        // no field of view needed
+        let s = s.replace("field_of_view: 180.0", "");
+        assert_eq!(camera(&s.replace("fisheye", "equirectangular")).unwrap().projection(), Projection::Equirectangular);
+        assert_eq!(camera(&s.replace("fisheye", "orthographic\nview_width: 8.0")).unwrap().projection(),
+                   Projection::Orthographic { width: 8.0 });
+
+        let error = |s: &str| camera(s).err().unwrap().to_string();
+
+        assert_eq!(error(&s.replace("fisheye", "orthographic")), "\"view_width\" missing");
+        assert_eq!(error(&s.replace("fisheye", "orthographic\nview_width: -1.0")), "In view_width: must be positive");
+        assert_eq!(error(&s.replace("fisheye", "cylindrical")), "In projection: unknown value cylindrical");
+        assert_eq!(error(&(s.clone() + "fisheye_mapping: stereographic")), "In fisheye_mapping: unknown value stereographic");
+        assert_eq!(error(&(s.clone() + "field_of_view: 400.0")), "In field_of_view: a fisheye sees between 0 and 360 degrees");
+        assert_eq!(error(&s), "In field_of_view: element missing");
+    }
+
+    #[test]
+    fn read_camera_stereo() {
+        let s =
+"
+width: 640
+height: 320
+field_of_view: 60.0
+from: [ 0.0, 1.5, -5.0 ]
+to: [ 0.0, 1.0, 0.0 ]
+stereo:
+  eye_distance: 0.065
+";
+        let camera = |s: &str| read_camera(&YamlLoader::load_from_str(s).unwrap()[0]);
+
+        let c = camera(s).unwrap();
+        assert_eq!(c.stereo(), Some(Stereo { layout: StereoLayout::SideBySide, eye_distance: 0.065, convergence: None }));
+        assert_eq!(c.eye_size(), (320.0, 320.0));
+
+        let c = camera(&(String::from(s) + "  layout: over_under\n  convergence: 2.0\nprojection: equirectangular")).unwrap();
+        assert_eq!(c.stereo(), Some(Stereo { layout: StereoLayout::OverUnder, eye_distance: 0.065, convergence: Some(2.0) }));
+
+        let error = |s: &str| camera(s).err().unwrap().to_string();
+
+        assert_eq!(error(&s.replace("eye_distance: 0.065", "convergence: 2.0")), "In stereo: \"eye_distance\" missing");
+        assert_eq!(error(&(String::from(s) + "  convergence: 0.0")), "In stereo: In convergence: must be positive");
+        assert_eq!(error(&(String::from(s) + "  layout: anaglyph")), "In stereo: In layout: unknown value anaglyph");
+        assert_eq!(error(&(String::from(s) + "projection: fisheye")), "In stereo: not possible with the fisheye projection");
+        assert_eq!(error(&s.replace("  eye_distance: 0.065", "  - 0.065")), "In stereo: expected dict");
+    }
+
+    #[test]
+    fn read_lights_ok() {
+        let s =
+"
+- point:
+    intensity: [ 1.0, 0.0, 0.0 ]
+    position: [ 4.0, 5.5, -6.0 ]
+- point:
+    position: [ -5.0, 3.0, -8.0 ]
+";
+
+        let docs = YamlLoader::load_from_str(&s).unwrap();
+
+        let lights = read_lights(&docs[0]).unwrap();
+
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].pos, V4::new_point(4.0, 5.5, -6.0));
+        assert_eq!(lights[1].intensity, Color::WHITE);
+    }
+
+    #[test]
+    fn read_texture_transformation_ok() {
+        let s =
+"
+texture:
   stripes:
     transformations:
       - scale: [ 0.5, 0.5, 0.5 ]
@@ -490,7 +1604,7 @@ texture:
 ";
 
         let docs = YamlLoader::load_from_str(&s).unwrap();
-        let texture = read_texture(&docs[0]["texture"]).unwrap();
+        let texture = read_texture(&docs[0], &docs[0]["texture"]).unwrap();
 
         match texture {
             Texture::Pattern(p) => {
@@ -514,7 +1628,7 @@ shininess: 100.0
 
         let docs = YamlLoader::load_from_str(&s).unwrap();
 
-        let mat = read_material(&docs[0]).unwrap();
+        let mat = read_material(&docs[0], &docs[0]).unwrap();
         let col = match mat.texture {
             Texture::Color(c) => c,
             _ => panic!("material texture is not a color")
@@ -541,7 +1655,7 @@ shininess: 100.0
 
         let docs = YamlLoader::load_from_str(&s).unwrap();
 
-        let mat = read_material(&docs[0]).unwrap();
+        let mat = read_material(&docs[0], &docs[0]).unwrap();
         match mat.texture {
             Texture::Pattern(p) => assert_eq!(p.color_at(V4::new_point(0.5, 0.0, 0.0)), Color::WHITE),
             _ => panic!("material texture is not a pattern")
@@ -562,15 +1676,53 @@ shininess: 100.0
 
         let reference = Transform::new()
                             .translate(0.5, 1.0, 3.5)
-                            .rotate_x(180_f32.to_radians());
+                            .rotate_x(Float::to_radians(180.0));
 
-        let trans = read_transformations(&docs[0].as_vec().unwrap()).unwrap();
+        let trans = read_transformations(&docs[0], &docs[0]).unwrap();
 
         let v = V4::new_vector(1.0, 2.0, 3.0);
 
         assert!(approx_eq!(V4, &trans * v, reference.apply(v), epsilon = 0.0001));
     }
 
+    #[test]
+    fn read_transformations_more() {
+        let s =
+"
+- shear: [ 1.0, 0.0, 0.0, 0.0, 0.0, 0.5 ]
+- rotate: { axis: [ 1.0, 1.0, 0.0 ], angle: 30.0 }
+- look_at: { from: [ 1.0, 2.0, 3.0 ], to: [ 0.0, 0.0, 0.0 ] }
+";
+
+        let docs = YamlLoader::load_from_str(s).unwrap();
+
+        let reference = Transform::new()
+                            .shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.5)
+                            .rotate(&V4::new_vector(1.0, 1.0, 0.0), Float::to_radians(30.0))
+                            .look_at(&V4::new_point(1.0, 2.0, 3.0), &V4::new_point(0.0, 0.0, 0.0),
+                                     &V4::new_vector(0.0, 1.0, 0.0));
+
+        let trans = read_transformations(&docs[0], &docs[0]).unwrap();
+
+        assert!(approx_eq!(&M4, &trans, &reference.matrix, epsilon = 0.0001));
+
+        let error = |s: &str| {
+            let docs = YamlLoader::load_from_str(s).unwrap();
+            read_transformations(&docs[0], &docs[0]).err().unwrap().to_string()
+        };
+
+        assert_eq!(error("- shear: [ 1.0, 0.0 ]"),
+                   "expected 6 floating-point values (xy, xz, yx, yz, zx, zy)");
+        assert_eq!(error("- rotate: { axis: [ 0.0, 0.0, 0.0 ], angle: 30.0 }"),
+                   "In rotate: In axis: must not be zero");
+        assert_eq!(error("- rotate: { axis: [ 0.0, 1.0, 0.0 ] }"),
+                   "In rotate: In angle: element missing");
+        assert_eq!(error("- look_at: { from: [ 1.0, 2.0, 3.0 ], to: [ 1.0, 2.0, 3.0 ] }"),
+                   "In look_at: from and to are the same point");
+        assert_eq!(error("- look_at: { from: [ 0.0, 0.0, 0.0 ], to: [ 0.0, 2.0, 0.0 ] }"),
+                   "In look_at: up is parallel to the direction looked in");
+    }
+
     #[test]
     fn read_shapes_ok() {
         let s =
@@ -633,4 +1785,470 @@ shapes:
         assert_eq!(shapes.len(), 1);
         assert_eq!(shapes[0].material().ambient, 0.3);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn material_ids() {
+        let s =
+"
+.mat.a:
+   texture:
+     color: [ 0.1, 0.3, 0.7 ]
+   ambient: 0.3
+   diffuse: 0.7
+   specular: 0.2
+   shininess: 20.0
+
+shapes:
+  - sphere:
+      material: .mat.a
+  - plane:
+      material:
+        texture:
+          color: [ 0.1, 0.3, 0.7 ]
+        ambient: 0.3
+        diffuse: 0.7
+        specular: 0.2
+        shininess: 20.0
+  - cube:
+      material: .mat.a
+";
+
+        let docs = YamlLoader::load_from_str(s).unwrap();
+
+        let shapes = read_shapes(&docs[0], &docs[0]["shapes"]).unwrap();
+
+        assert_eq!(shapes[0].material_id(), 1);
+        assert_eq!(shapes[1].material_id(), 2);
+        assert_eq!(shapes[2].material_id(), 1);
+    }
+
+    #[test]
+    fn material_extend() {
+        let s =
+"
+.mat.checks:
+   texture:
+     checkerboard:
+       a: [ 1.0, 1.0, 1.0 ]
+       b: [ 0.0, 0.0, 0.0 ]
+   ambient: 0.3
+   diffuse: 0.7
+   specular: 0.2
+   shininess: 20.0
+
+.mat.shiny_checks:
+   extend: .mat.checks
+   reflective: 0.5
+
+shapes:
+  - plane:
+      material: .mat.shiny_checks
+  - sphere:
+      material:
+        extend: .mat.shiny_checks
+        ambient: 0.1
+  - cube:
+      material: .mat.checks
+";
+
+        let docs = YamlLoader::load_from_str(s).unwrap();
+
+        let shapes = read_shapes(&docs[0], &docs[0]["shapes"]).unwrap();
+
+        assert_eq!(shapes[0].material().reflective, 0.5);
+        assert_eq!(shapes[0].material().ambient, 0.3);
+        assert_eq!(shapes[1].material().reflective, 0.5);
+        assert_eq!(shapes[1].material().ambient, 0.1);
+        assert_eq!(shapes[2].material().reflective, 0.0);
+
+        match &shapes[1].material().texture {
+            Texture::Pattern(p) => assert_eq!(p.color_at(V4::new_point(1.5, 0.0, 0.0)), Color::BLACK),
+            _ => panic!("material texture is not a pattern")
+        }
+
+        let cycle = YamlLoader::load_from_str("
+.mat.a: { extend: .mat.b }
+.mat.b: { extend: .mat.a }
+").unwrap();
+
+        let e = read_material(&cycle[0], &cycle[0][".mat.a"]).err().unwrap().to_string();
+        assert!(e.contains("cycle: .mat.b -> .mat.a -> .mat.b"), "{}", e);
+
+        let e = read_material(&cycle[0], &YamlLoader::load_from_str("extend: .mat.c").unwrap()[0]).err().unwrap();
+        assert_eq!(e.to_string(), "In extend: .mat.c is not defined");
+        assert_eq!(error_path(e.as_ref()), vec![ "extend" ]);
+    }
+
+    #[test]
+    fn named_transformations() {
+        let s =
+"
+.xf.lift:
+  - translate: [ 0.0, 1.0, 0.0 ]
+.xf.place:
+  - scale: [ 2.0, 2.0, 2.0 ]
+  - .xf.lift
+  - translate: [ 1.0, 0.0, 0.0 ]
+.xf.loop:
+  - .xf.loop
+";
+
+        let docs = YamlLoader::load_from_str(s).unwrap();
+        let root = &docs[0];
+
+        let reference = Transform::new()
+                            .scale(2.0, 2.0, 2.0)
+                            .translate(0.0, 1.0, 0.0)
+                            .translate(1.0, 0.0, 0.0);
+
+        let p = V4::new_point(1.0, 2.0, 3.0);
+
+        let trans = read_transformations(root, &Yaml::String(String::from(".xf.place"))).unwrap();
+        assert!(approx_eq!(V4, trans * p, reference.apply(p), epsilon = 0.0001));
+
+        let trans = read_transformations(root, &root[".xf.place"]).unwrap();
+        assert!(approx_eq!(V4, trans * p, reference.apply(p), epsilon = 0.0001));
+
+        let e = read_transformations(root, &root[".xf.loop"]).err().unwrap().to_string();
+        assert_eq!(e, "transformation cycle: .xf.loop -> .xf.loop");
+
+        let e = read_transformations(root, &Yaml::String(String::from(".xf.none"))).err().unwrap().to_string();
+        assert_eq!(e, ".xf.none is not defined");
+    }
+
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rt-{}-{}", test, std::process::id()));
+
+        for (name, contents) in files.iter() {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    const INCLUDE_MAIN: &str = "
+include: lib/materials.yaml
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from: [ 0.0, 0.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+shapes:
+  - cube:
+      material: .mat.red
+";
+
+    const INCLUDE_MATERIALS: &str = "
+include: [ rig.yaml ]
+.mat.red:
+  texture:
+    color: [ 1.0, 0.0, 0.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.0
+  shininess: 10.0
+shapes:
+  - sphere:
+      material: .mat.red
+";
+
+    const INCLUDE_RIG: &str = "
+lights:
+  - point:
+      position: [ -10.0, 10.0, -10.0 ]
+";
+
+    #[test]
+    fn includes() {
+        let dir = write_files("includes", &[
+                ("scene.yaml", INCLUDE_MAIN),
+                ("lib/materials.yaml", INCLUDE_MATERIALS),
+                ("lib/rig.yaml", INCLUDE_RIG)
+            ]);
+
+        let (_, world, text) = read_yaml_scene_file(&dir.join("scene.yaml")).unwrap();
+
+        assert_eq!(world.lights().len(), 1);
+        assert_eq!(world.shapes().len(), 2);
+
+        // included shapes come first
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+        assert_eq!(world.shapes()[0].intersect(&r), [ 4.0, 6.0 ]);
+
+        // the merged text stands on its own
+        let (_, world) = read_yaml_scene_config(&text).unwrap();
+        assert_eq!(world.shapes().len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_errors() {
+        let dir = write_files("include-errors", &[
+                ("a.yaml", "include: sub/b.yaml\n"),
+                ("sub/b.yaml", "include: ../a.yaml\n"),
+                ("missing.yaml", "include: nothere.yaml\n")
+            ]);
+
+        let e = read_yaml_scene_file(&dir.join("a.yaml")).err().unwrap().to_string();
+        assert!(e.contains("include cycle"), "{}", e);
+
+        let e = read_yaml_scene_file(&dir.join("missing.yaml")).err().unwrap().to_string();
+        assert!(e.contains("nothere.yaml"), "{}", e);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expressions() {
+        let docs = YamlLoader::load_from_str("a: pi / 2\nb: [ 1 / 2, -2 ^ 2, sqrt(9) ]\nc: r / 2\nd: 1 +").unwrap();
+        let root = &docs[0];
+
+        assert!(approx_eq!(Float, read_float(&root["a"]).unwrap(), consts::FRAC_PI_2, ulps = 2));
+        assert_eq!(read_v3(&root["b"]).unwrap(), [ 0.5, -4.0, 3.0 ]);
+        assert_eq!(read_float(&root["c"]).err().unwrap().to_string(), "r is not defined");
+        assert_eq!(read_float(&root["d"]).err().unwrap().to_string(), "expected floating-point value");
+    }
+
+    const REPEAT_SCENE: &str = "
+variables:
+  n: 3
+  spacing: 1.5 * 2
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from: [ 0.0, 0.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+lights:
+  - point:
+      position: [ 0.0, spacing, -5.0 ]
+.mat.white:
+  texture:
+    color: [ 1.0, 1.0, 1.0 ]
+  ambient: 0.1
+  diffuse: 0.9
+  specular: 0.9
+  shininess: 200.0
+shapes:
+  - plane:
+      material: .mat.white
+  - repeat:
+      for: i
+      range: n
+      shapes:
+        - repeat:
+            for: j
+            range: [ 1, 0, -0.5 ]
+            shapes:
+              - sphere:
+                  material:
+                    texture:
+                      color: [ i / (n - 1), j, 0.0 ]
+                    ambient: 0.1
+                    diffuse: 0.9
+                    specular: 0.9
+                    shininess: 200.0
+                  transformations:
+                    - translate: [ i * spacing, j, 0.0 ]
+";
+
+    #[test]
+    fn variables_and_repeat() {
+        let (_, world) = read_yaml_scene_config(REPEAT_SCENE).unwrap();
+
+        assert_eq!(world.lights()[0].pos, V4::new_point(0.0, 3.0, -5.0));
+        assert_eq!(world.shapes().len(), 7);
+
+        let o = V4::new_point(0.0, 0.0, 0.0);
+        let centers: Vec<V4> = world.shapes()[1..].iter().map(|s| *s.transform() * o).collect();
+        assert_eq!(centers, vec![
+                V4::new_point(0.0, 1.0, 0.0), V4::new_point(0.0, 0.5, 0.0),
+                V4::new_point(3.0, 1.0, 0.0), V4::new_point(3.0, 0.5, 0.0),
+                V4::new_point(6.0, 1.0, 0.0), V4::new_point(6.0, 0.5, 0.0)
+            ]);
+
+        assert_eq!(world.shapes()[6].material().color_at(o), Color::new(1.0, 0.5, 0.0));
+
+        // a loop variable shadows a global one
+        let s = REPEAT_SCENE.replace("for: j", "for: n").replace("j, 0.0 ]", "n, 0.0 ]");
+        let (_, world) = read_yaml_scene_config(&s).unwrap();
+        assert_eq!(world.shapes().len(), 7);
+        assert_eq!(*world.shapes()[6].transform() * o, V4::new_point(6.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn repeat_errors() {
+        let error = |from: &str, to: &str| read_yaml_scene_config(&REPEAT_SCENE.replace(from, to)).err().unwrap().to_string();
+
+        assert_eq!(error("spacing: 1.5 * 2", "spacing: m * 2"), "In variables: spacing: m is not defined");
+        assert_eq!(error("for: i", "for: 1i"), "In shapes: In item 2: In repeat: for: expected variable name");
+        assert_eq!(error("range: n", "range: [ 0, n, 0 ]"), "In shapes: In item 2: In repeat: In range: step must not be 0");
+        assert_eq!(error("range: n", "range: 1e9"), "In shapes: In item 2: In repeat: In range: more than 100000 values");
+        assert_eq!(error("range: n", "range: [ 0, 1, 1e-300 ]"), "In shapes: In item 2: In repeat: In range: more than 100000 values");
+        assert_eq!(error("range: n", "range: [ n ]"),
+                   "In shapes: In item 2: In repeat: In range: expected end, or 2 or 3 numbers (start, end, step)");
+        assert_eq!(error("i * spacing", "k * spacing"), "In shapes: In transformations: k is not defined");
+    }
+
+    #[test]
+    fn include_variables() {
+        let main = "
+include: size.yaml
+variables:
+  half: size / 2
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from: [ 0.0, 0.0, -5.0 ]
+  to: [ 0.0, 0.0, 0.0 ]
+lights: []
+shapes:
+  - sphere:
+      material:
+        texture:
+          color: [ 1.0, 1.0, 1.0 ]
+        ambient: 0.1
+        diffuse: 0.9
+        specular: 0.9
+        shininess: 200.0
+      transformations:
+        - scale: [ half, half, half ]
+";
+        let dir = write_files("include-variables", &[
+                ("scene.yaml", main),
+                ("size.yaml", "variables:\n  size: 4\n  half: 1\n")
+            ]);
+
+        let (_, world, text) = read_yaml_scene_file(&dir.join("scene.yaml")).unwrap();
+
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+        assert_eq!(world.shapes()[0].intersect(&r), [ 3.0, 7.0 ]);
+
+        // the expanded text has no variables left
+        assert!(!text.contains("variables") && !text.contains("half"), "{}", text);
+        let (_, world) = read_yaml_scene_config(&text).unwrap();
+        assert_eq!(world.shapes()[0].intersect(&r), [ 3.0, 7.0 ]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    const ANIMATED_SCENE: &str = "
+variables:
+  speed: 0.5
+camera:
+  width: 10
+  height: 10
+  field_of_view: 60.0
+  from:
+    keyframes:
+      0: [ 0.0, 0.0, -5.0 ]
+      10: [ 5.0, 0.0, 0.0 ]
+      20: [ 0.0, 0.0, 5.0 ]
+    interpolation: spline
+  to: [ 0.0, 0.0, 0.0 ]
+lights:
+  - point:
+      position:
+        keyframes: { 0: [ 0.0, 10.0, 0.0 ], 10: [ 0.0, 20.0, speed * 20 ] }
+shapes:
+  - cube:
+      material:
+        texture:
+          color: [ 1.0, 1.0, 1.0 ]
+        ambient: 0.1
+        diffuse: 0.9
+        specular: 0.9
+        shininess: 200.0
+      transformations:
+        - translate: [ frame * speed, 0.0, 0.0 ]
+        - rotate_y:
+            keyframes:
+              0: 0.0
+              20: 90.0
+";
+
+    fn read_frame(s: &str, frame: f64) -> Result<(Camera,World)> {
+        let docs = YamlLoader::load_from_str(s).unwrap();
+        read_scene(&expand_scene(&docs[0], frame)?)
+    }
+
+    #[test]
+    fn keyframes() {
+        let (camera, world) = read_frame(ANIMATED_SCENE, 5.0).unwrap();
+
+        // linear between keyframes, constant after the last
+        assert_eq!(world.lights()[0].pos, V4::new_point(0.0, 15.0, 5.0));
+        let (_, world25) = read_frame(ANIMATED_SCENE, 25.0).unwrap();
+        assert_eq!(world25.lights()[0].pos, V4::new_point(0.0, 20.0, 10.0));
+
+        let reference = Transform::new().translate(2.5, 0.0, 0.0).rotate_y(Float::to_radians(22.5));
+        let p = V4::new_point(1.0, 2.0, 3.0);
+        assert!(approx_eq!(V4, *world.shapes()[0].transform() * p, reference.apply(p), epsilon = 0.0001));
+
+        let same = |a: &M4, b: &M4| [ p, V4::new_vector(0.0, 1.0, 0.0) ].iter().all(|v| approx_eq!(V4, *a * *v, *b * *v, epsilon = 0.0001));
+
+        // the spline passes through the keyframes
+        for (frame, from) in [ (0.0, [ 0.0, 0.0, -5.0 ]), (10.0, [ 5.0, 0.0, 0.0 ]), (20.0, [ 0.0, 0.0, 5.0 ]) ].iter() {
+            let (c, _) = read_frame(ANIMATED_SCENE, *frame).unwrap();
+            let vt = Transform::view_transform(&V4::new_point(from[0], from[1], from[2]), &V4::new_point(0.0, 0.0, 0.0), &V4::new_vector(0.0, 1.0, 0.0));
+            assert!(same(c.transform(), &vt.matrix));
+        }
+
+        // and curves between them
+        let linear = Transform::view_transform(&V4::new_point(2.5, 0.0, -2.5), &V4::new_point(0.0, 0.0, 0.0), &V4::new_vector(0.0, 1.0, 0.0));
+        assert!(!same(camera.transform(), &linear.matrix));
+    }
+
+    #[test]
+    fn keyframe_errors() {
+        let error = |from: &str, to: &str| read_frame(&ANIMATED_SCENE.replace(from, to), 0.0).err().unwrap().to_string();
+
+        assert_eq!(error("interpolation: spline", "interpolation: cubic"), "In camera: In interpolation: unknown value cubic");
+        assert_eq!(error("20: [ 0.0, 0.0, 5.0 ]", "20: [ 0.0, 5.0 ]"),
+                   "In camera: In keyframes: values must all be numbers or lists of the same length");
+        assert_eq!(error("20: 90.0", "20.0: 90.0\n              20: 0.0"), "In shapes: In item 1: In keyframes: frame 20 given twice");
+        assert_eq!(error("speed * 20", "sped * 20"), "In lights: In keyframes: sped is not defined");
+        assert_eq!(error("keyframes: {", "keyframes: [ 0, 1 ]\n        kf: {"), "In lights: keyframes: expected dict of frame: value");
+    }
+
+    #[test]
+    fn motion() {
+        let scene = ANIMATED_SCENE.replace("  to: [ 0.0, 0.0, 0.0 ]\n", "  to: [ 0.0, 0.0, 0.0 ]\n  shutter: [ 0.0, 0.5 ]\n")
+            + "  - sphere:\n      material: { texture: { color: [ 1.0, 0.0, 0.0 ] }, ambient: 0.1, diffuse: 0.9, specular: 0.9, shininess: 200.0 }\n";
+
+        let (camera, world) = read_frame(&scene, 5.0).unwrap();
+        assert_eq!(camera.shutter(), (0.0, 0.5));
+
+        let motion = world.shapes()[0].motion();
+        let times: Vec<Float> = motion.iter().map(|k| k.0).collect();
+        assert_eq!(times, vec![ 0.0, 0.125, 0.25, 0.375, 0.5 ]);
+
+        let p = V4::new_point(1.0, 2.0, 3.0);
+        let end = Transform::new().translate(2.75, 0.0, 0.0).rotate_y(Float::to_radians(24.75));
+        assert!(approx_eq!(V4, motion[4].1 * p, end.apply(p), epsilon = 0.0001));
+        assert!(approx_eq!(V4, motion[0].1 * p, *world.shapes()[0].transform() * p, epsilon = 0.0001));
+
+        // shapes that stand still don't move
+        assert!(world.shapes()[1].motion().is_empty());
+
+        // an explicit motion stays
+        let scene = scene.replace("      material: { texture", "      motion: { 0: [], 1: [ translate: [ 1.0, 0.0, 0.0 ] ] }\n      material: { texture");
+        let (_, world) = read_frame(&scene, 5.0).unwrap();
+        let motion = world.shapes()[1].motion();
+        assert_eq!(motion.len(), 2);
+        assert_eq!(motion[1].1 * V4::new_point(0.0, 0.0, 0.0), V4::new_point(1.0, 0.0, 0.0));
+
+        let error = |from: &str, to: &str| read_frame(&scene.replace(from, to), 0.0).err().unwrap().to_string();
+
+        assert_eq!(error("shutter: [ 0.0, 0.5 ]", "shutter: [ 0.5, 0.0 ]"), "In camera: In shutter: shutter closes before it opens");
+        assert_eq!(error("shutter: [ 0.0, 0.5 ]", "shutter: 0.5"), "In camera: In shutter: expected 2 floating-point values (open, close)");
+        assert_eq!(error("motion: { 0: [], 1:", "motion: { 1: [], 1.0:"), "In shapes: In motion: time 1 given twice");
+        assert_eq!(error("motion: { 0: [], 1: [ translate: [ 1.0, 0.0, 0.0 ] ] }", "motion: []"),
+                   "In shapes: In motion: expected dict of time: transformations");
+    }
+}
@@ -0,0 +1,464 @@
+use crate::camera::{Camera,Projection};
+use crate::color::Color;
+use crate::lighting::LightSource;
+use crate::linalg::{Float,M4,V4};
+use crate::material::{Material,Texture};
+use crate::pattern::{Pattern,PatternKind};
+use crate::shape::{Primitive,Shape};
+use crate::tonemap::{Encoding,ToneOperator};
+use crate::world::World;
+
+use yaml_rust::{yaml,Yaml,YamlEmitter};
+
+use std::collections::HashMap;
+use std::error;
+
+type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+
+fn key(s: &str) -> Yaml {
+    Yaml::String(String::from(s))
+}
+
+fn real(v: Float) -> Yaml {
+    Yaml::Real(format!("{:?}", v))
+}
+
+fn point(v: V4) -> Yaml {
+    Yaml::Array(vec![ real(v.x()), real(v.y()), real(v.z()) ])
+}
+
+fn color(c: Color) -> Yaml {
+    Yaml::Array(vec![ real(c.r), real(c.g), real(c.b) ])
+}
+
+fn single(k: &str, val: Yaml) -> Yaml {
+    let mut h = yaml::Hash::new();
+    h.insert(key(k), val);
+    Yaml::Hash(h)
+}
+
+/// A transformation list for `m`: translate, rotate and scale where
+/// that's all there is, otherwise the full matrix. None for the identity.
+fn write_transformations(m: &M4) -> Option<Yaml> {
+    if *m == M4::identity() {
+        return None
+    }
+
+    let diagonal = (0..3).all(|r| (0..3).all(|c| r == c || m.at(r, c) == 0.0));
+    let affine = m.at(3, 0) == 0.0 && m.at(3, 1) == 0.0 && m.at(3, 2) == 0.0 && m.at(3, 3) == 1.0;
+
+    let (translation, rotation, scale) = if diagonal && affine {
+        (V4::new_vector(m.at(0, 3), m.at(1, 3), m.at(2, 3)), None, V4::new_vector(m.at(0, 0), m.at(1, 1), m.at(2, 2)))
+    } else if let Some((t, r, s)) = m.decompose() {
+        // Decomposing rounds, and scales that should be 1 rarely are.
+        let near_one = (s - V4::new_vector(1.0, 1.0, 1.0)).magnitude() < 0.00001;
+        (t, Some(r), if near_one { V4::new_vector(1.0, 1.0, 1.0) } else { s })
+    } else {
+        let values = m.as_ref().iter().map(|&v| real(v)).collect();
+        return Some(Yaml::Array(vec![ single("matrix", Yaml::Array(values)) ]))
+    };
+
+    let mut list = Vec::new();
+
+    if translation != V4::new_vector(0.0, 0.0, 0.0) {
+        list.push(single("translate", point(translation)));
+    }
+    if let Some((axis, angle)) = rotation.map(|r| r.to_axis_angle()).filter(|r| r.1 != 0.0) {
+        let mut rotate = yaml::Hash::new();
+        rotate.insert(key("axis"), point(axis));
+        rotate.insert(key("angle"), real(angle.to_degrees()));
+        list.push(single("rotate", Yaml::Hash(rotate)));
+    }
+    if scale != V4::new_vector(1.0, 1.0, 1.0) {
+        list.push(single("scale", point(scale)));
+    }
+
+    Some(Yaml::Array(list))
+}
+
+/// A texture entry for `p`, with the transformations of any enclosing
+/// patterns accumulated in `trans`.
+fn write_pattern(p: &dyn Pattern, trans: &M4) -> Result<Yaml> {
+    let (kind, a, b) = match p.kind() {
+        PatternKind::Solid(c) => return Ok(single("color", color(c))),
+        PatternKind::Stripes(a, b) => ("stripes", a, b),
+        PatternKind::Checkerboard(a, b) => ("checkerboard", a, b),
+        PatternKind::Ring(a, b) => ("ring", a, b),
+        PatternKind::Gradient(a, b) => ("gradient", a, b),
+        PatternKind::Blended(a, b) => {
+            let mut pattern = yaml::Hash::new();
+
+            pattern.insert(key("a"), write_pattern(a.as_ref(), &M4::identity())?);
+            pattern.insert(key("b"), write_pattern(b.as_ref(), &M4::identity())?);
+
+            if let Some(t) = write_transformations(trans) {
+                pattern.insert(key("transformations"), t);
+            }
+
+            return Ok(single("blended", Yaml::Hash(pattern)))
+        },
+        PatternKind::Transformed(inner, m) => return write_pattern(inner.as_ref(), &M4::mmul(trans, &m)),
+        PatternKind::Other => return Err("pattern has no scene description".into())
+    };
+
+    let mut pattern = yaml::Hash::new();
+
+    pattern.insert(key("a"), color(a));
+    pattern.insert(key("b"), color(b));
+
+    if let Some(t) = write_transformations(trans) {
+        pattern.insert(key("transformations"), t);
+    }
+
+    Ok(single(kind, Yaml::Hash(pattern)))
+}
+
+fn write_material(mat: &Material) -> Result<Yaml> {
+    let mut h = yaml::Hash::new();
+
+    let texture = match &mat.texture {
+        Texture::Color(c) => single("color", color(*c)),
+        Texture::Pattern(p) => write_pattern(p.as_ref(), &M4::identity())?
+    };
+
+    h.insert(key("texture"), texture);
+    h.insert(key("ambient"), real(mat.ambient));
+    h.insert(key("diffuse"), real(mat.diffuse));
+    h.insert(key("specular"), real(mat.specular));
+    h.insert(key("shininess"), real(mat.shininess));
+
+    // the rest only where it differs from the default
+    if mat.reflective != 0.0 {
+        h.insert(key("reflective"), real(mat.reflective));
+    }
+    if mat.transparency != 0.0 {
+        h.insert(key("transparency"), real(mat.transparency));
+    }
+    if mat.refractive_index != 1.0 {
+        h.insert(key("refractive_index"), real(mat.refractive_index));
+    }
+
+    Ok(Yaml::Hash(h))
+}
+
+fn write_camera(camera: &Camera) -> Yaml {
+    let mut h = yaml::Hash::new();
+
+    // The view transformation's rows are left, true up and -forward, the
+    // first two scaled by sin(angle between forward and the given up).
+    // Any up vector at that angle in the same plane gives the same matrix.
+    let m = camera.transform();
+    let from = m.invert() * V4::new_point(0.0, 0.0, 0.0);
+    let forward = V4::new_vector(-m.at(2, 0), -m.at(2, 1), -m.at(2, 2));
+    let upt = V4::new_vector(m.at(1, 0), m.at(1, 1), m.at(1, 2));
+    let up = upt + forward * (1.0 - upt.magnitude().powi(2)).max(0.0).sqrt();
+    let to = from + forward;
+
+    h.insert(key("width"), Yaml::Integer(camera.width() as i64));
+    h.insert(key("height"), Yaml::Integer(camera.height() as i64));
+    h.insert(key("field_of_view"), real(camera.field_of_view().to_degrees()));
+    h.insert(key("from"), point(from));
+    h.insert(key("to"), point(to));
+    h.insert(key("up"), point(up));
+
+    let tonemap = camera.tonemap();
+
+    if tonemap.exposure != 0.0 {
+        h.insert(key("exposure"), real(tonemap.exposure));
+    }
+    if tonemap.operator != ToneOperator::Clamp {
+        h.insert(key("tone_operator"), key(tonemap.operator.name()));
+    }
+    if tonemap.encoding != Encoding::Linear {
+        h.insert(key("encoding"), key(tonemap.encoding.name()));
+    }
+
+    if let Some(r) = camera.crop() {
+        let crop = [ r.x0, r.y0, r.x1, r.y1 ].iter().map(|&v| Yaml::Integer(v as i64)).collect();
+        h.insert(key("crop"), Yaml::Array(crop));
+    }
+
+    let projection = camera.projection();
+
+    if projection != Projection::Perspective {
+        h.insert(key("projection"), key(projection.name()));
+    }
+
+    match projection {
+        Projection::Orthographic { width } => { h.insert(key("view_width"), real(width)); },
+        Projection::Fisheye(mapping) => { h.insert(key("fisheye_mapping"), key(mapping.name())); },
+        _ => ()
+    }
+
+    if let Some(stereo) = camera.stereo() {
+        let mut st = yaml::Hash::new();
+
+        st.insert(key("layout"), key(stereo.layout.name()));
+        st.insert(key("eye_distance"), real(stereo.eye_distance));
+        if let Some(c) = stereo.convergence {
+            st.insert(key("convergence"), real(c));
+        }
+
+        h.insert(key("stereo"), Yaml::Hash(st));
+    }
+
+    let (open, close) = camera.shutter();
+
+    if open < close {
+        h.insert(key("shutter"), Yaml::Array(vec![ real(open), real(close) ]));
+    }
+
+    Yaml::Hash(h)
+}
+
+fn write_light(light: &LightSource) -> Yaml {
+    let mut h = yaml::Hash::new();
+
+    h.insert(key("position"), point(light.pos));
+    h.insert(key("intensity"), color(light.intensity));
+
+    single("point", Yaml::Hash(h))
+}
+
+fn write_shape(shape: &Shape, material: Yaml) -> Result<Yaml> {
+    let mut body = yaml::Hash::new();
+
+    let kind = match shape.primitive() {
+        Primitive::Sphere => "sphere",
+        Primitive::Cube => "cube",
+        Primitive::Plane => "plane",
+        Primitive::Cylinder { min, max, closed } => {
+            if min != Float::MIN {
+                body.insert(key("min"), real(min));
+            }
+            if max != Float::MAX {
+                body.insert(key("max"), real(max));
+            }
+
+            if closed { "cylinder" } else { "pipe" }
+        },
+        Primitive::Cone { min, max, closed } => {
+            if min != Float::MIN {
+                body.insert(key("min"), real(min));
+            }
+            if max != Float::MAX {
+                body.insert(key("max"), real(max));
+            }
+
+            if closed { "cone" } else { "open_cone" }
+        },
+        Primitive::Triangle { p1, p2, p3 } => {
+            body.insert(key("p1"), point(p1));
+            body.insert(key("p2"), point(p2));
+            body.insert(key("p3"), point(p3));
+            "triangle"
+        },
+        Primitive::Other => return Err("shape has no scene description".into())
+    };
+
+    body.insert(key("material"), material);
+
+    if let Some(t) = write_transformations(shape.transform()) {
+        body.insert(key("transformations"), t);
+    }
+
+    let motion = shape.motion();
+
+    if !motion.is_empty() {
+        let keys = motion.iter()
+            .map(|(t, m)| (real(*t), write_transformations(m).unwrap_or_else(|| Yaml::Array(Vec::new()))))
+            .collect();
+
+        body.insert(key("motion"), Yaml::Hash(keys));
+    }
+
+    Ok(single(kind, Yaml::Hash(body)))
+}
+
+/// Describe a camera and world in the scene format. Materials of shapes
+/// that share a material ID become named definitions.
+pub fn scene_to_yaml(camera: &Camera, world: &World) -> Result<Yaml> {
+    let mut users = HashMap::new();
+
+    for shape in world.shapes().iter().filter(|s| s.material_id() != 0) {
+        *users.entry(shape.material_id()).or_insert(0) += 1;
+    }
+
+    let mut doc = yaml::Hash::new();
+    let mut shapes = Vec::new();
+
+    for shape in world.shapes().iter() {
+        let id = shape.material_id();
+
+        let material = if matches!(users.get(&id), Some(&n) if n > 1) {
+            let name = key(&format!(".mat.{}", id));
+
+            if !doc.contains_key(&name) {
+                doc.insert(name.clone(), write_material(shape.object_material())?);
+            }
+
+            name
+        } else {
+            write_material(shape.object_material())?
+        };
+
+        shapes.push(write_shape(shape, material)?);
+    }
+
+    doc.insert(key("camera"), write_camera(camera));
+    doc.insert(key("lights"), Yaml::Array(world.lights().iter().map(write_light).collect()));
+    doc.insert(key("shapes"), Yaml::Array(shapes));
+
+    Ok(Yaml::Hash(doc))
+}
+
+/// Write a camera and world as YAML text, which `read_yaml_scene_config()`
+/// reads back.
+pub fn write_yaml_scene(camera: &Camera, world: &World) -> Result<String> {
+    let mut text = String::new();
+    YamlEmitter::new(&mut text).dump(&scene_to_yaml(camera, world)?)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{FisheyeMapping,Stereo,StereoLayout};
+    use crate::cone::Cone;
+    use crate::cylinder::Cylinder;
+    use crate::framebuffer::Rect;
+    use crate::mesh::Triangle;
+    use crate::pattern::{Blended,Checkerboard,Gradient,Stripes,TransformedPattern};
+    use crate::sceneparser::{read_yaml_scene_config,read_yaml_scene_file};
+    use crate::sphere::Sphere;
+    use crate::tonemap::ToneMap;
+    use crate::transform::Transform;
+
+    use float_cmp::*;
+
+    use std::path::Path;
+    use std::rc::Rc;
+
+    fn material(texture: Texture) -> Material {
+        Material {
+            texture,
+            ambient: 0.1,
+            diffuse: 0.8,
+            specular: 0.3,
+            shininess: 50.0,
+            reflective: 0.25,
+            transparency: 0.0,
+            refractive_index: 1.5
+        }
+    }
+
+    fn build_scene() -> (Camera, World) {
+        let view = Transform::view_transform(&V4::new_point(1.0, 2.0, -5.0), &V4::new_point(0.0, 1.0, 0.0), &V4::new_vector(0.0, 1.0, 0.0));
+        let mut camera = Camera::new(64, 48, Float::to_radians(50.0), &view.matrix);
+
+        camera.set_tonemap(ToneMap { exposure: 1.5, operator: ToneOperator::Aces, encoding: Encoding::Srgb });
+        camera.set_crop(Some(Rect { x0: 8, y0: 4, x1: 40, y1: 30 }));
+        camera.set_shutter(0.0, 0.5);
+        camera.set_projection(Projection::Equirectangular);
+        camera.set_stereo(Some(Stereo { layout: StereoLayout::OverUnder, eye_distance: 0.1, convergence: Some(3.0) }));
+
+        let stripes = TransformedPattern::new(Stripes::new(Color::WHITE, Color::BLACK), &Transform::new().rotate_y(0.5).matrix);
+        let checks = material(Texture::Pattern(Rc::new(Checkerboard::new(Color::RED, Color::WHITE))));
+        let fine = TransformedPattern::new(Stripes::new(Color::RED, Color::BLACK), &Transform::new().scale(0.25, 1.0, 1.0).matrix);
+        let blend = material(Texture::Pattern(Rc::new(Blended::new(Rc::new(Gradient::new(Color::WHITE, Color::BLACK)), Rc::new(fine)))));
+
+        let tilted = Transform::new().translate(0.0, 1.0, 0.0).rotate_z(0.3).scale(1.0, 2.0, 1.0);
+        let moved = Transform::new().translate(2.0, 0.0, 1.0).scale(0.5, 0.5, 0.5);
+        let motion = [ (0.0, M4::identity()), (0.5, Transform::new().translate(1.0, 0.0, 0.0).matrix) ];
+
+        let shapes = vec![
+            Rc::new(Shape::new(Box::new(Sphere()), &material(Texture::Pattern(Rc::new(stripes))), &tilted.matrix)),
+            Rc::new(Shape::new(Box::new(Cylinder::new_truncated(0.0, 2.0)), &checks, &moved.matrix).with_material_id(2)),
+            Rc::new(Shape::new(Box::new(Cylinder::new_closed(-1.0, 1.0)), &checks, &M4::identity()).with_material_id(2).with_motion(&motion)),
+            Rc::new(Shape::new(Box::new(Cone::new_truncated(-1.0, 0.0)), &blend, &moved.matrix)),
+            Rc::new(Shape::new(Box::new(Triangle::new(V4::new_point(0.0, 1.0, 0.0), V4::new_point(-1.0, 0.0, 0.5), V4::new_point(1.0, 0.0, 0.0))), &blend, &M4::identity()))
+        ];
+
+        let lights = vec![ LightSource { pos: V4::new_point(-10.0, 10.0, -10.0), intensity: Color::new(0.5, 0.5, 0.5) } ];
+
+        (camera, World::new_with(lights, shapes))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let (camera, world) = build_scene();
+
+        let text = write_yaml_scene(&camera, &world).unwrap();
+        let (rt_camera, rt_world) = read_yaml_scene_config(&text).unwrap();
+
+        assert!(text.contains("\".mat.2\":"), "{}", text);
+
+        assert_eq!(rt_camera.width(), 64);
+        assert_eq!(rt_camera.crop(), camera.crop());
+        assert_eq!(rt_camera.tonemap(), camera.tonemap());
+        assert_eq!(rt_camera.shutter(), (0.0, 0.5));
+        assert_eq!(rt_camera.projection(), camera.projection());
+        assert_eq!(rt_camera.stereo(), camera.stereo());
+
+        let mut fisheye = camera;
+        fisheye.set_stereo(None);
+        fisheye.set_projection(Projection::Fisheye(FisheyeMapping::Equisolid));
+        let (rt_fisheye, _) = read_yaml_scene_config(&write_yaml_scene(&fisheye, &world).unwrap()).unwrap();
+        assert_eq!(rt_fisheye.projection(), fisheye.projection());
+        assert!(approx_eq!(Float, rt_camera.field_of_view(), camera.field_of_view(), epsilon = 0.0001));
+        assert!(approx_eq!(&M4, rt_camera.transform(), camera.transform(), epsilon = 0.0001));
+
+        assert_eq!(rt_world.lights()[0].intensity, world.lights()[0].intensity);
+        assert_eq!(rt_world.shapes().len(), 5);
+
+        for (a, b) in world.shapes().iter().zip(rt_world.shapes().iter()) {
+            assert_eq!(a.primitive(), b.primitive());
+            assert!(approx_eq!(&M4, a.transform(), b.transform(), epsilon = 0.0001));
+            assert_eq!(a.motion(), b.motion());
+
+            let (ma, mb) = (a.material(), b.material());
+            assert_eq!((ma.diffuse, ma.reflective, ma.refractive_index), (mb.diffuse, mb.reflective, mb.refractive_index));
+
+            for p in [ V4::new_point(0.3, 0.9, 0.1), V4::new_point(1.7, 0.2, -0.6), V4::new_point(-0.4, 1.8, 1.2) ].iter() {
+                assert_eq!(ma.color_at(*p), mb.color_at(*p));
+            }
+        }
+
+        assert_eq!(rt_world.shapes()[1].material_id(), rt_world.shapes()[2].material_id());
+    }
+
+    #[test]
+    fn examples_roundtrip() {
+        for name in [ "chapter11.yaml", "chapter12.yaml", "chapter13.yaml", "book_format.yaml" ].iter() {
+            let (camera, world, _) = read_yaml_scene_file(&Path::new("examples").join(name)).unwrap();
+
+            let text = write_yaml_scene(&camera, &world).unwrap();
+            let (rt_camera, rt_world) = read_yaml_scene_config(&text).unwrap();
+
+            assert!(approx_eq!(&M4, rt_camera.transform(), camera.transform(), epsilon = 0.0001), "{}", name);
+
+            for (a, b) in world.shapes().iter().zip(rt_world.shapes().iter()) {
+                assert!(approx_eq!(&M4, a.transform(), b.transform(), epsilon = 0.0001), "{}", name);
+                assert_eq!(a.material_id(), b.material_id(), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn transformations() {
+        assert_eq!(write_transformations(&M4::identity()), None);
+
+        let ts = Transform::new().translate(1.0, 2.0, 3.0).scale(2.0, 2.0, 2.0);
+        let list = write_transformations(&ts.matrix).unwrap();
+
+        assert_eq!(list[0]["translate"][2], real(3.0));
+        assert_eq!(list[1]["scale"][0], real(2.0));
+
+        let list = write_transformations(&Transform::new().rotate_x(1.0).matrix).unwrap();
+        assert_eq!(list[0]["rotate"]["axis"][0], real(1.0));
+        assert!(approx_eq!(Float, list[0]["rotate"]["angle"].as_f64().unwrap() as Float, Float::to_degrees(1.0), epsilon = 0.001));
+
+        let list = write_transformations(&Transform::new().shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.0).matrix).unwrap();
+        assert_eq!(list[0]["matrix"].as_vec().unwrap().len(), 16);
+    }
+}
@@ -1,17 +1,113 @@
 use crate::material::Material;
-use crate::linalg::{M4,V4};
+use crate::linalg::{Float,M4,Quat,V4};
 use crate::ray::Ray;
+use crate::transform::Transform;
+
+use std::ops::Deref;
+
+/// The distances along a ray at which it hits a shape. No shape is hit
+/// more than `Hits::MAX` times, so the list is kept inline rather than
+/// on the heap.
+#[derive(Clone,Copy,Debug)]
+pub struct Hits {
+    t: [Float; Hits::MAX],
+    len: usize
+}
+
+impl Hits {
+    pub const MAX: usize = 4;
+
+    pub fn new() -> Hits {
+        Hits { t: [0.0; Hits::MAX], len: 0 }
+    }
+
+    pub fn push(&mut self, t: Float) {
+        assert!(self.len < Hits::MAX, "a shape can't be hit more than {} times", Hits::MAX);
+        self.t[self.len] = t;
+        self.len += 1
+    }
+}
+
+impl Default for Hits {
+    fn default() -> Hits {
+        Hits::new()
+    }
+}
+
+impl Deref for Hits {
+    type Target = [Float];
+
+    fn deref(&self) -> &[Float] {
+        &self.t[..self.len]
+    }
+}
+
+impl IntoIterator for Hits {
+    type Item = Float;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Float, { Hits::MAX }>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self.t).take(self.len)
+    }
+}
+
+impl PartialEq for Hits {
+    fn eq(&self, other: &Hits) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> PartialEq<[Float; N]> for Hits {
+    fn eq(&self, other: &[Float; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+/// What a shape is, so that it can be written back out.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Primitive {
+    Sphere,
+    Cube,
+    Plane,
+    Cylinder { min: Float, max: Float, closed: bool },
+    Cone { min: Float, max: Float, closed: bool },
+    Triangle { p1: V4, p2: V4, p3: V4 },
+    /// A shape the scene format has no description for
+    Other
+}
 
 pub trait BaseShape {
-    fn intersect(&self, r: &Ray) -> Vec<f32>;
+    /// Where `r` hits the shape, in object space. At most `Hits::MAX`
+    /// distances fit, as for a closed cone or cylinder hit on its side
+    /// and on both caps; `Hits::push` panics beyond that.
+    fn intersect(&self, r: &Ray) -> Hits;
     fn normal_at(&self, p: V4) -> V4;
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Other
+    }
+}
+
+/// A shape's transformation at one time of its motion.
+struct MotionKey {
+    time: Float,
+    transform: M4,
+    transform_i: M4,
+    /// Translation, rotation and scale, where the transformation has
+    /// no shear
+    parts: Option<(V4,Quat,V4)>
 }
 
 pub struct Shape {
     base: Box<dyn BaseShape>,
+    transform: M4,
     transform_i: M4,
     transform_i_t: M4,
-    material: Material
+    material: Material,
+    object_material: Material,
+    material_id: u32,
+    /// Transformations over the shutter interval
+    motion: Vec<MotionKey>
 }
 
 impl Shape {
@@ -20,26 +116,134 @@ impl Shape {
 
         Shape {
             base: shape,
+            transform: *trans,
             transform_i: t_i,
             transform_i_t: t_i.transpose(),
-            material: Material::new_transformed(mat, trans)
+            material: Material::new_transformed(mat, trans),
+            object_material: mat.clone(),
+            material_id: 0,
+            motion: Vec::new()
         }
     }
 
-    pub fn intersect(&self, ray: &Ray) -> Vec<f32> {
-        self.base.intersect(&ray.apply(&self.transform_i))
+    /// Tag the shape with an ID for its material, for the material ID
+    /// render pass. Shapes sharing a material definition should share
+    /// the ID.
+    pub fn with_material_id(mut self, id: u32) -> Shape {
+        self.material_id = id;
+        self
+    }
+
+    /// Make the shape move: `keys` are its transformations at times
+    /// (in frames) over the shutter interval, sorted by time. Between
+    /// them, translation and scale are interpolated linearly and
+    /// rotation spherically, so that turning shapes keep their size;
+    /// transformations that shear are interpolated element-wise. Outside,
+    /// the transformation stays that of the first or last key. Patterns
+    /// keep the transformation given to `new()` and move along with the
+    /// shape.
+    pub fn with_motion(mut self, keys: &[(Float,M4)]) -> Shape {
+        self.motion = keys.iter().map(|(t, m)| MotionKey {
+            time: *t,
+            transform: *m,
+            transform_i: m.invert(),
+            parts: m.decompose()
+        }).collect();
+        self
+    }
+
+    /// The transformations over the shutter interval, by time; empty if
+    /// the shape doesn't move.
+    pub fn motion(&self) -> Vec<(Float,M4)> {
+        self.motion.iter().map(|k| (k.time, k.transform)).collect()
+    }
+
+    fn transform_i_at(&self, time: Float) -> M4 {
+        let keys = &self.motion;
+
+        match keys.iter().position(|k| k.time > time) {
+            None if keys.is_empty() => self.transform_i,
+            None => keys[keys.len() - 1].transform_i,
+            Some(0) => keys[0].transform_i,
+            Some(i) => {
+                let (k0, k1) = (&keys[i - 1], &keys[i]);
+                let s = (time - k0.time) / (k1.time - k0.time);
+
+                match (k0.parts, k1.parts) {
+                    (Some((t0, r0, s0)), Some((t1, r1, s1))) => {
+                        let t = t0 + (t1 - t0) * s;
+                        let r = Quat::slerp(&r0, &r1, s);
+                        let sc = s0 + (s1 - s0) * s;
+
+                        Transform::new()
+                            .scale(1.0 / sc.x(), 1.0 / sc.y(), 1.0 / sc.z())
+                            .rotate_quat(&r.conjugate())
+                            .translate(-t.x(), -t.y(), -t.z())
+                            .matrix
+                    },
+                    _ => M4::lerp(&k0.transform_i, &k1.transform_i, s)
+                }
+            }
+        }
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Hits {
+        if self.motion.is_empty() {
+            self.base.intersect(&ray.apply_transposed(&self.transform_i_t))
+        } else {
+            self.base.intersect(&ray.apply(&self.transform_i_at(ray.time)))
+        }
     }
 
     pub fn normal_at(&self, p: V4) -> V4 {
-        let p = &self.transform_i * p;
-        let n = &self.transform_i_t * self.base.normal_at(p);
+        self.normal_at_time(p, 0.0)
+    }
+
+    /// Normal at `p`, with the shape where it is at `time`.
+    pub fn normal_at_time(&self, p: V4, time: Float) -> V4 {
+        let n = if self.motion.is_empty() {
+            // each matrix is the other's transpose
+            let p = self.transform_i_t.transpose_mul(p);
+            self.transform_i.transpose_mul(self.base.normal_at(p))
+        } else {
+            let t_i = self.transform_i_at(time);
+            t_i.transpose() * self.base.normal_at(t_i * p)
+        };
 
         V4::new_vector(n.x(), n.y(), n.z()).normalize()
     }
 
+    /// Where `p`, on the shape at `time`, would be with the shape's
+    /// `new()` transformation, so that patterns move along with it.
+    pub fn pattern_point(&self, p: V4, time: Float) -> V4 {
+        if self.motion.is_empty() {
+            p
+        } else {
+            self.transform * (self.transform_i_at(time) * p)
+        }
+    }
+
     pub fn material(&self) -> &Material {
         &self.material
     }
+
+    /// The material as passed to `new()`, with patterns in object space
+    /// rather than world space.
+    pub fn object_material(&self) -> &Material {
+        &self.object_material
+    }
+
+    pub fn transform(&self) -> &M4 {
+        &self.transform
+    }
+
+    pub fn primitive(&self) -> Primitive {
+        self.base.primitive()
+    }
+
+    pub fn material_id(&self) -> u32 {
+        self.material_id
+    }
 }
 
 #[cfg(test)]
@@ -47,6 +251,7 @@ mod tests {
     use super::*;
 
     use crate::color::Color;
+    use crate::linalg::consts;
     use crate::material::Texture;
     use crate::transform::Transform;
 
@@ -71,9 +276,9 @@ mod tests {
     }
 
     impl BaseShape for TestShape {
-        fn intersect(&self, r: &Ray) -> Vec<f32> {
+        fn intersect(&self, r: &Ray) -> Hits {
             *self.ray.borrow_mut() = *r;
-            vec![]
+            Hits::new()
         }
 
         fn normal_at(&self, p: V4) -> V4 {
@@ -81,11 +286,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hits() {
+        let mut xs = Hits::new();
+
+        assert!(xs.is_empty());
+
+        xs.push(4.0);
+        xs.push(-1.0);
+
+        assert_eq!(xs, [ 4.0, -1.0 ]);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs.into_iter().collect::<Vec<_>>(), vec![ 4.0, -1.0 ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be hit more than 4 times")]
+    fn too_many_hits() {
+        let mut xs = Hits::new();
+
+        for t in 0..5 {
+            xs.push(t as Float);
+        }
+    }
+
     #[test]
     fn transform_intersect() {
         let res = Ray {
             origin: V4::new_point(0.0, 0.0, 0.0),
-            direction: V4::new_vector(0.0, 0.0, 0.0)
+            direction: V4::new_vector(0.0, 0.0, 0.0),
+            time: 0.0
         };
         let res = Rc::new(RefCell::new(res));
 
@@ -95,7 +325,8 @@ mod tests {
 
             let r = Ray {
                 origin: V4::new_point(0.0, 0.0, -5.0),
-                direction: V4::new_vector(0.0, 0.0, 1.0)
+                direction: V4::new_vector(0.0, 0.0, 1.0),
+                time: 0.0
             };
 
             s.intersect(&r);
@@ -109,7 +340,8 @@ mod tests {
     fn transform_normal() {
         let ray = Ray {
             origin: V4::new_point(0.0, 0.0, 0.0),
-            direction: V4::new_vector(0.0, 0.0, 0.0)
+            direction: V4::new_vector(0.0, 0.0, 0.0),
+            time: 0.0
         };
 
         let t = Transform::new().translate(0.0, 1.0, 0.0);
@@ -118,4 +350,42 @@ mod tests {
         let n = s.normal_at(V4::new_point(0.0, 1.70711, -0.70711));
         assert!(approx_eq!(V4, n, V4::new_vector(0.0, 0.70711, -0.70711), epsilon = 0.0001));
     }
+
+    #[test]
+    fn motion() {
+        let res = Rc::new(RefCell::new(Ray::new(V4::new_point(0.0, 0.0, 0.0), V4::new_vector(0.0, 0.0, 0.0))));
+
+        let keys = [
+            (0.0, Transform::new().translate(0.0, 0.0, 0.0).matrix),
+            (1.0, Transform::new().translate(2.0, 0.0, 0.0).matrix),
+            (2.0, Transform::new().translate(2.0, 4.0, 0.0).matrix)
+        ];
+        let s = Shape::new(Box::new(TestShape { ray: Rc::clone(&res) }), &DEFAULT_MAT, &keys[1].1)
+            .with_motion(&keys);
+
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+
+        for (time, x, y) in [ (-1.0, 0.0, 0.0), (0.5, -1.0, 0.0), (1.5, -2.0, -2.0), (3.0, -2.0, -4.0) ].iter() {
+            s.intersect(&r.with_time(*time));
+            assert!(approx_eq!(V4, res.borrow().origin, V4::new_point(*x, *y, -5.0)));
+        }
+
+        // patterns follow the shape
+        let p = s.pattern_point(V4::new_point(1.0, 0.0, 0.0), 0.5);
+        assert!(approx_eq!(V4, p, V4::new_point(2.0, 0.0, 0.0)));
+
+        assert_eq!(s.motion().len(), 3);
+        assert_eq!(s.motion()[2].1, keys[2].1);
+
+        // turning shapes keep their size halfway through
+        let keys = [
+            (0.0, Transform::new().scale(2.0, 2.0, 2.0).matrix),
+            (1.0, Transform::new().rotate_y(consts::PI * 0.9).scale(2.0, 2.0, 2.0).matrix)
+        ];
+        let s = Shape::new(Box::new(TestShape { ray: Rc::clone(&res) }), &DEFAULT_MAT, &keys[0].1)
+            .with_motion(&keys);
+
+        s.intersect(&Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0)).with_time(0.5));
+        assert!(approx_eq!(Float, res.borrow().direction.magnitude(), 0.5, epsilon = 0.0001));
+    }
 }
@@ -1,11 +1,11 @@
 use crate::linalg::V4;
 use crate::ray::Ray;
-use crate::shape::BaseShape;
+use crate::shape::{BaseShape,Hits,Primitive};
 
 pub struct Sphere ();
 
 impl BaseShape for Sphere {
-    fn intersect(&self, r: &Ray) -> Vec<f32> {
+    fn intersect(&self, r: &Ray) -> Hits {
         let s2r = r.origin - V4::new_point(0.0, 0.0, 0.0);
 
         let a = V4::dot(&r.direction, &r.direction);
@@ -14,7 +14,7 @@ impl BaseShape for Sphere {
 
         let d = b*b - 4.0*a*c;
 
-        let mut v: Vec<f32> = vec![];
+        let mut v = Hits::new();
 
         if d < 0.0 {
             return v;
@@ -29,6 +29,10 @@ impl BaseShape for Sphere {
     fn normal_at(&self, p: V4) -> V4 {
         p - V4::new_point(0.0, 0.0, 0.0).normalize()
     }
+
+    fn primitive(&self) -> Primitive {
+        Primitive::Sphere
+    }
 }
 
 #[cfg(test)]
@@ -60,14 +64,14 @@ mod tests {
     fn sphere_intersect() {
         let z = V4::new_vector(0.0, 0.0, 1.0);
         let r = Ray {
-            origin: V4::new_point(0.0, 0.0, -5.0), direction: z
+            origin: V4::new_point(0.0, 0.0, -5.0), direction: z, time: 0.0
         };
         let s = default_sphere();
 
         assert_eq!(s.intersect(&r), [ 4.0, 6.0 ]);
 
         let r = Ray {
-            origin: V4::new_point(0.0, 1.0, -5.0), direction: z
+            origin: V4::new_point(0.0, 1.0, -5.0), direction: z, time: 0.0
         };
 
         assert_eq!(s.intersect(&r), [ 5.0, 5.0 ]);
@@ -0,0 +1,166 @@
+use crate::color::Color;
+use crate::linalg::Float;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum ToneOperator {
+    Clamp,
+    Reinhard,
+    Aces
+}
+
+impl ToneOperator {
+    pub fn from_name(name: &str) -> Option<ToneOperator> {
+        match name {
+            "clamp"    => Some(ToneOperator::Clamp),
+            "reinhard" => Some(ToneOperator::Reinhard),
+            "aces" | "filmic" => Some(ToneOperator::Aces),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneOperator::Clamp    => "clamp",
+            ToneOperator::Reinhard => "reinhard",
+            ToneOperator::Aces     => "aces"
+        }
+    }
+
+    fn apply(&self, v: Float) -> Float {
+        let v = v.max(0.0);
+
+        match self {
+            ToneOperator::Clamp    => v.min(1.0),
+            ToneOperator::Reinhard => v / (1.0 + v),
+            ToneOperator::Aces     => {
+                // Narkowicz' curve fit of the ACES reference rendering transform
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                ((v * (a*v + b)) / (v * (c*v + d) + e)).min(1.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Encoding {
+    Linear,
+    Srgb
+}
+
+impl Encoding {
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "linear" => Some(Encoding::Linear),
+            "srgb"   => Some(Encoding::Srgb),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Linear => "linear",
+            Encoding::Srgb   => "srgb"
+        }
+    }
+
+    fn apply(&self, v: Float) -> Float {
+        match self {
+            Encoding::Linear => v,
+            Encoding::Srgb   => {
+                if v <= 0.0031308 {
+                    12.92 * v
+                } else {
+                    1.055 * v.powf(1.0 / 2.4) - 0.055
+                }
+            }
+        }
+    }
+}
+
+/// Post-render stage that turns linear radiance values into displayable
+/// colors in [0,1]: exposure scaling, tone operator, transfer encoding.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct ToneMap {
+    pub exposure: Float,
+    pub operator: ToneOperator,
+    pub encoding: Encoding
+}
+
+impl ToneMap {
+    /// The identity mapping: no exposure change, hard clamp, linear output.
+    pub fn new() -> ToneMap {
+        ToneMap {
+            exposure: 0.0,
+            operator: ToneOperator::Clamp,
+            encoding: Encoding::Linear
+        }
+    }
+
+    pub fn map(&self, c: Color) -> Color {
+        let scale = Float::powf(2.0, self.exposure);
+        let f = |v: Float| self.encoding.apply(self.operator.apply(v * scale));
+
+        Color::new(f(c.r), f(c.g), f(c.b))
+    }
+}
+
+impl Default for ToneMap {
+    fn default() -> ToneMap {
+        ToneMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+
+    #[test]
+    fn identity() {
+        let tm = ToneMap::new();
+
+        assert_eq!(tm.map(Color::new(0.5, 1.5, -0.5)), Color::new(0.5, 1.0, 0.0));
+    }
+
+    #[test]
+    fn exposure() {
+        let tm = ToneMap { exposure: 1.0, ..ToneMap::new() };
+
+        assert_eq!(tm.map(Color::new(0.25, 0.5, 1.0)), Color::new(0.5, 1.0, 1.0));
+
+        let tm = ToneMap { exposure: -2.0, ..ToneMap::new() };
+
+        assert_eq!(tm.map(Color::WHITE), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(ToneOperator::Reinhard.apply(1.0), 0.5);
+        assert_eq!(ToneOperator::Reinhard.apply(3.0), 0.75);
+
+        assert_eq!(ToneOperator::Aces.apply(0.0), 0.0);
+        assert_eq!(ToneOperator::Aces.apply(100.0), 1.0);
+        assert!(ToneOperator::Aces.apply(0.5) < ToneOperator::Aces.apply(0.6));
+    }
+
+    #[test]
+    fn srgb() {
+        assert_eq!(Encoding::Srgb.apply(0.0), 0.0);
+        assert!(approx_eq!(Float, Encoding::Srgb.apply(1.0), 1.0, epsilon = 0.0001));
+        assert!(approx_eq!(Float, Encoding::Srgb.apply(0.18), 0.46135, epsilon = 0.0001));
+        assert!(approx_eq!(Float, Encoding::Srgb.apply(0.001), 0.01292, epsilon = 0.0001));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(ToneOperator::from_name("filmic"), Some(ToneOperator::Aces));
+        assert_eq!(ToneOperator::from_name("reinhard"), Some(ToneOperator::Reinhard));
+        assert_eq!(ToneOperator::from_name("bogus"), None);
+        assert_eq!(Encoding::from_name("srgb"), Some(Encoding::Srgb));
+
+        for op in [ ToneOperator::Clamp, ToneOperator::Reinhard, ToneOperator::Aces ].iter() {
+            assert_eq!(ToneOperator::from_name(op.name()), Some(*op));
+        }
+        assert_eq!(Encoding::from_name(Encoding::Linear.name()), Some(Encoding::Linear));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::linalg;
-use crate::linalg::{M4,V4};
+use crate::linalg::{Float,M4,Quat,V4};
 
 #[derive(Clone,Copy,Debug)]
 pub struct Transform {
@@ -37,7 +37,7 @@ impl Transform {
         }
     }
 
-    pub fn translate(&self, x: f32, y: f32, z: f32) -> Transform {
+    pub fn translate(&self, x: Float, y: Float, z: Float) -> Transform {
         let transmatrix = [
             1.0, 0.0, 0.0, x,
             0.0, 1.0, 0.0, y,
@@ -50,7 +50,7 @@ impl Transform {
         }
     }
 
-    pub fn scale(&self, x: f32, y: f32, z: f32) -> Transform {
+    pub fn scale(&self, x: Float, y: Float, z: Float) -> Transform {
         let transmatrix = [
             x,   0.0, 0.0, 0.0,
             0.0, y,   0.0, 0.0,
@@ -63,7 +63,7 @@ impl Transform {
         }
     }
 
-    pub fn rotate_x(&self, rad: f32) -> Transform {
+    pub fn rotate_x(&self, rad: Float) -> Transform {
         let transmatrix = [
             1.0, 0.0,        0.0,       0.0,
             0.0, rad.cos(), -rad.sin(), 0.0,
@@ -76,7 +76,7 @@ impl Transform {
         }
     }
 
-    pub fn rotate_y(&self, rad: f32) -> Transform {
+    pub fn rotate_y(&self, rad: Float) -> Transform {
         let transmatrix = [
              rad.cos(), 0.0, rad.sin(), 0.0,
              0.0,       1.0, 0.0,       0.0,
@@ -89,7 +89,7 @@ impl Transform {
         }
     }
 
-    pub fn rotate_z(&self, rad: f32) -> Transform {
+    pub fn rotate_z(&self, rad: Float) -> Transform {
         let transmatrix = [
             rad.cos(), -rad.sin(), 0.0, 0.0,
             rad.sin(),  rad.cos(), 0.0, 0.0,
@@ -102,6 +102,74 @@ impl Transform {
         }
     }
 
+    /// The book's shearing transform: `xy` moves x in proportion to y, and
+    /// so on.
+    pub fn shear(&self, xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> Transform {
+        let transmatrix = [
+            1.0, xy,  xz,  0.0,
+            yx,  1.0, yz,  0.0,
+            zx,  zy,  1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0
+        ].into();
+
+        Transform {
+            matrix: M4::mmul(&self.matrix, &transmatrix)
+        }
+    }
+
+    /// Rotation by `rad` around `axis`, which need not be normalized.
+    pub fn rotate(&self, axis: &V4, rad: Float) -> Transform {
+        let a = axis.normalize();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (s, c) = rad.sin_cos();
+        let t = 1.0 - c;
+
+        let transmatrix = [
+            t*x*x + c,   t*x*y - s*z, t*x*z + s*y, 0.0,
+            t*x*y + s*z, t*y*y + c,   t*y*z - s*x, 0.0,
+            t*x*z - s*y, t*y*z + s*x, t*z*z + c,   0.0,
+            0.0,         0.0,         0.0,         1.0
+        ].into();
+
+        Transform {
+            matrix: M4::mmul(&self.matrix, &transmatrix)
+        }
+    }
+
+    /// The rotation given by the unit quaternion `q`.
+    pub fn rotate_quat(&self, q: &Quat) -> Transform {
+        Transform {
+            matrix: M4::mmul(&self.matrix, &q.to_m4())
+        }
+    }
+
+    /// Place an object at `from` and turn it like a camera looking at `to`:
+    /// its -z axis points at `to` and its y axis as close to `up` as
+    /// possible. Unlike `view_transform`, this never scales.
+    pub fn look_at(&self, from: &V4, to: &V4, up: &V4) -> Transform {
+        let forward = (*to - *from).normalize();
+        let left = V4::cross(&forward, &up.normalize()).normalize();
+        let upt = V4::cross(&left, &forward);
+
+        let transmatrix = [
+            left.x(), upt.x(), -forward.x(), from.x(),
+            left.y(), upt.y(), -forward.y(), from.y(),
+            left.z(), upt.z(), -forward.z(), from.z(),
+            0.0,      0.0,      0.0,         1.0
+        ].into();
+
+        Transform {
+            matrix: M4::mmul(&self.matrix, &transmatrix)
+        }
+    }
+
+    /// Apply an arbitrary matrix, like the other operations.
+    pub fn matrix(&self, m: &M4) -> Transform {
+        Transform {
+            matrix: M4::mmul(&self.matrix, m)
+        }
+    }
+
     pub fn invert(&self) -> Transform {
         Transform {
             matrix: self.matrix.invert()
@@ -113,6 +181,12 @@ impl Transform {
     }
 }
 
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::new()
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -155,10 +229,10 @@ mod tests {
 
     #[test]
     fn rotate_x() {
-        let half_q = Transform::new().rotate_x(std::f32::consts::FRAC_PI_4);
-        let full_q = Transform::new().rotate_x(std::f32::consts::FRAC_PI_2);
+        let half_q = Transform::new().rotate_x(consts::FRAC_PI_4);
+        let full_q = Transform::new().rotate_x(consts::FRAC_PI_2);
 
-        let sq2half = std::f32::consts::SQRT_2 / 2.0;
+        let sq2half = consts::SQRT_2 / 2.0;
 
         let p = V4::new_point(0.0, 1.0, 0.0);
 
@@ -171,10 +245,10 @@ mod tests {
 
     #[test]
     fn rotate_y() {
-        let half_q = Transform::new().rotate_y(std::f32::consts::FRAC_PI_4);
-        let full_q = Transform::new().rotate_y(std::f32::consts::FRAC_PI_2);
+        let half_q = Transform::new().rotate_y(consts::FRAC_PI_4);
+        let full_q = Transform::new().rotate_y(consts::FRAC_PI_2);
 
-        let sq2half = std::f32::consts::SQRT_2 / 2.0;
+        let sq2half = consts::SQRT_2 / 2.0;
 
         let p = V4::new_point(0.0, 0.0, 1.0);
 
@@ -187,10 +261,10 @@ mod tests {
 
     #[test]
     fn rotate_z() {
-        let half_q = Transform::new().rotate_z(std::f32::consts::FRAC_PI_4);
-        let full_q = Transform::new().rotate_z(std::f32::consts::FRAC_PI_2);
+        let half_q = Transform::new().rotate_z(consts::FRAC_PI_4);
+        let full_q = Transform::new().rotate_z(consts::FRAC_PI_2);
 
-        let sq2half = std::f32::consts::SQRT_2 / 2.0;
+        let sq2half = consts::SQRT_2 / 2.0;
 
         let p = V4::new_point(0.0, 1.0, 0.0);
 
@@ -205,7 +279,7 @@ mod tests {
     fn chain() {
         let p = V4::new_point(1.0, 0.0, 1.0);
 
-        let a = Transform::new().rotate_x(std::f32::consts::FRAC_PI_2);
+        let a = Transform::new().rotate_x(consts::FRAC_PI_2);
         let b = Transform::new().scale(5.0, 5.0, 5.0);
         let c = Transform::new().translate(10.0, 5.0, 7.0);
 
@@ -220,7 +294,7 @@ mod tests {
         let t = Transform::new()
             .translate(10.0, 5.0, 7.0)
             .scale(5.0, 5.0, 5.0)
-            .rotate_x(std::f32::consts::FRAC_PI_2);
+            .rotate_x(consts::FRAC_PI_2);
 
         let q = t.apply(p);
 
@@ -279,4 +353,74 @@ mod tests {
 
         assert!(approx_eq!(&M4, &tv.matrix, &m, epsilon=0.0001));
     }
+
+    #[test]
+    fn shear() {
+        let p = V4::new_point(2.0, 3.0, 4.0);
+        let cases = [
+            (Transform::new().shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.0), V4::new_point(5.0, 3.0, 4.0)),
+            (Transform::new().shear(0.0, 1.0, 0.0, 0.0, 0.0, 0.0), V4::new_point(6.0, 3.0, 4.0)),
+            (Transform::new().shear(0.0, 0.0, 1.0, 0.0, 0.0, 0.0), V4::new_point(2.0, 5.0, 4.0)),
+            (Transform::new().shear(0.0, 0.0, 0.0, 1.0, 0.0, 0.0), V4::new_point(2.0, 7.0, 4.0)),
+            (Transform::new().shear(0.0, 0.0, 0.0, 0.0, 1.0, 0.0), V4::new_point(2.0, 3.0, 6.0)),
+            (Transform::new().shear(0.0, 0.0, 0.0, 0.0, 0.0, 1.0), V4::new_point(2.0, 3.0, 7.0))
+        ];
+
+        for (t, res) in cases.iter() {
+            assert!(approx_eq!(V4, t.apply(p), *res, epsilon = 0.0001));
+        }
+    }
+
+    #[test]
+    fn rotate_axis() {
+        let angle = 0.7;
+
+        let x = Transform::new().rotate(&V4::new_vector(2.0, 0.0, 0.0), angle);
+        let y = Transform::new().rotate(&V4::new_vector(0.0, 1.0, 0.0), angle);
+        let z = Transform::new().rotate(&V4::new_vector(0.0, 0.0, 1.0), angle);
+
+        assert!(approx_eq!(&M4, &x.matrix, &Transform::new().rotate_x(angle).matrix, epsilon = 0.0001));
+        assert!(approx_eq!(&M4, &y.matrix, &Transform::new().rotate_y(angle).matrix, epsilon = 0.0001));
+        assert!(approx_eq!(&M4, &z.matrix, &Transform::new().rotate_z(angle).matrix, epsilon = 0.0001));
+
+        // a third of a turn around the diagonal cycles the axes
+        let d = Transform::new().rotate(&V4::new_vector(1.0, 1.0, 1.0), Float::to_radians(120.0));
+        let p = V4::new_point(1.0, 0.0, 0.0);
+
+        assert!(approx_eq!(V4, d.apply(p), V4::new_point(0.0, 1.0, 0.0), epsilon = 0.0001));
+    }
+
+    #[test]
+    fn rotate_quat() {
+        let axis = V4::new_vector(1.0, -2.0, 0.5);
+        let q = Quat::from_axis_angle(&axis, 1.2);
+
+        let t = Transform::new().translate(1.0, 0.0, 0.0).rotate_quat(&q);
+        let r = Transform::new().translate(1.0, 0.0, 0.0).rotate(&axis, 1.2);
+
+        assert!(approx_eq!(&M4, &t.matrix, &r.matrix, epsilon = 0.0001));
+    }
+
+    #[test]
+    fn look_at() {
+        let from = V4::new_point(1.0, 3.0, 2.0);
+        let to = V4::new_point(4.0, -2.0, 8.0);
+        let up = V4::new_vector(1.0, 1.0, 0.0);
+
+        let t = Transform::new().look_at(&from, &to, &up);
+
+        assert!(approx_eq!(V4, t.apply(V4::new_point(0.0, 0.0, 0.0)), from, epsilon = 0.0001));
+        assert!(approx_eq!(V4, t.apply(V4::new_vector(0.0, 0.0, -1.0)), (to - from).normalize(), epsilon = 0.0001));
+
+        // with up perpendicular to the view, the inverse is the camera's
+        // view transform
+        let from = V4::new_point(0.0, 0.0, 8.0);
+        let to = V4::new_point(0.0, 0.0, 0.0);
+        let up = V4::new_vector(0.0, 1.0, 0.0);
+
+        let t = Transform::new().look_at(&from, &to, &up).invert();
+        let tv = Transform::view_transform(&from, &to, &up);
+
+        assert!(approx_eq!(&M4, &t.matrix, &tv.matrix, epsilon = 0.0001));
+    }
 }
@@ -1,53 +1,50 @@
 use crate::color::Color;
 use crate::lighting::LightSource;
 use crate::lighting;
-use crate::linalg::V4;
+use crate::linalg::{EPSILON,Float,V4};
 use crate::ray::Ray;
+use crate::raytree::{HitNode,RayKind,RayNode,ShadowRecord};
 use crate::shape::Shape;
 
+use std::cell::RefCell;
+use std::ops::Deref;
 use std::rc::Rc;
 
+/// A hit of the world's shape at index `object`.
+#[derive(Clone,Copy,Debug)]
 struct Intersection {
-    distance: f32,
-    object: Rc<Shape>
+    distance: Float,
+    object: usize
 }
 
-fn hit(xs: &[Intersection]) -> Option<&Intersection> {
-    xs.iter().find(|&x| x.distance >= 0.0)
+/// A sorted intersection list in one of the world's scratch buffers. The
+/// buffer goes back to the world when the list is dropped, so that tracing
+/// doesn't allocate once there are enough buffers of sufficient size.
+struct Intersections<'a> {
+    xs: Vec<Intersection>,
+    pool: &'a RefCell<Vec<Vec<Intersection>>>
 }
 
-fn refraction_index_pair(hit: &Intersection, xs: &[Intersection]) -> (f32,f32) {
-    let mut n1 = 1.0;
-    let mut n2 = 1.0;
-
-    let mut containers: Vec<Rc<Shape>> = Vec::new();
-
-    for i in xs.iter() {
-        if i.distance == hit.distance {
-            n1 = match containers.last() {
-                Some(obj) => obj.material().refractive_index,
-                None => 1.0
-            };
-        }
+impl Deref for Intersections<'_> {
+    type Target = [Intersection];
 
-        match containers.iter().position(|x| Rc::ptr_eq(&x, &i.object)) {
-            Some(p) => { containers.remove(p); () },
-            None => containers.push(Rc::clone(&i.object))
-        }
+    fn deref(&self) -> &[Intersection] {
+        &self.xs
+    }
+}
 
-        if i.distance == hit.distance {
-            n2 = match containers.last() {
-                Some(obj) => obj.material().refractive_index,
-                None => 1.0
-            };
-            break
-        }
+impl Drop for Intersections<'_> {
+    fn drop(&mut self) {
+        self.xs.clear();
+        self.pool.borrow_mut().push(std::mem::take(&mut self.xs))
     }
+}
 
-    (n1,n2)
+fn hit(xs: &[Intersection]) -> Option<&Intersection> {
+    xs.iter().find(|&x| x.distance >= 0.0)
 }
 
-fn schlick(n1: f32, n2: f32, normalv: V4, eyev: V4) -> f32 {
+fn schlick(n1: Float, n2: Float, normalv: V4, eyev: V4) -> Float {
     let mut cos = V4::dot(&eyev, &normalv);
 
     if n1 > n2 {
@@ -66,11 +63,26 @@ fn schlick(n1: f32, n2: f32, normalv: V4, eyev: V4) -> f32 {
     return r0 + (1.0 - r0) * (1.0 - cos).powi(5)
 }
 
+/// Surface information about the first hit of a ray, for auxiliary
+/// render passes.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct HitRecord {
+    pub distance: Float,
+    pub point: V4,
+    pub normal: V4,
+    pub albedo: Color,
+    pub object_id: u32,
+    pub material_id: u32
+}
+
 pub struct World {
     lights: Vec<LightSource>,
     shapes: Vec< Rc<Shape> >,
 
-    max_depth: u32
+    max_depth: u32,
+
+    /// Intersection lists to reuse, see `Intersections`
+    scratch: RefCell<Vec<Vec<Intersection>>>
 }
 
 impl World {
@@ -78,7 +90,8 @@ impl World {
         World {
             lights: vec![],
             shapes: vec![],
-            max_depth: 5
+            max_depth: 5,
+            scratch: RefCell::new(Vec::new())
         }
     }
 
@@ -86,83 +99,203 @@ impl World {
         World {
             lights: lights,
             shapes: shapes,
-            max_depth: 5
+            max_depth: 5,
+            scratch: RefCell::new(Vec::new())
         }
     }
 
-    fn intersections(&self, ray: &Ray) -> Vec<Intersection> {
-        let mut xs = Vec::new();
+    fn intersections(&self, ray: &Ray) -> Intersections<'_> {
+        let mut xs = self.scratch.borrow_mut().pop().unwrap_or_default();
 
-        for shape in self.shapes.iter() {
-            for t in shape.intersect(ray) {
-                xs.push( Intersection { distance: t, object: Rc::clone(&shape) } )
+        for (object, shape) in self.shapes.iter().enumerate() {
+            for distance in shape.intersect(ray) {
+                xs.push( Intersection { distance, object } )
             }
         }
 
         xs.sort_unstable_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
-        xs
+
+        Intersections { xs, pool: &self.scratch }
     }
 
-    fn is_shadowed(&self, light: &LightSource, point: &V4) -> bool {
+    fn object_id(&self, object: usize) -> u32 {
+        object as u32 + 1
+    }
+
+    /// Refractive indices on either side of the surface at `hit`. Walking
+    /// `xs` up to the hit, the objects the ray is inside are kept on a stack
+    /// in the order they were entered; the top is the one it's in.
+    fn refraction_index_pair(&self, hit: &Intersection, xs: &[Intersection]) -> (Float,Float) {
+        let n = match xs.iter().position(|i| i.distance == hit.distance) {
+            Some(n) => n,
+            None => return (1.0, 1.0)
+        };
+
+        let mut inside = Intersections { xs: self.scratch.borrow_mut().pop().unwrap_or_default(), pool: &self.scratch };
+
+        let index = |inside: &[Intersection]| {
+            inside.last().map_or(1.0, |i| self.shapes[i.object].material().refractive_index)
+        };
+
+        let mut n1 = 1.0;
+
+        for (k, i) in xs[..=n].iter().enumerate() {
+            if k == n {
+                n1 = index(&inside);
+            }
+
+            match inside.iter().position(|j| j.object == i.object) {
+                Some(p) => { inside.xs.remove(p); },
+                None => inside.xs.push(*i)
+            }
+        }
+
+        (n1, index(&inside))
+    }
+
+    /// The closest object between `point` and the light at `time`, if any.
+    fn shadow_blocker(&self, light: &LightSource, point: &V4, time: Float) -> Option<Intersection> {
         let v = light.pos - *point;
         let r = Ray {
             origin: *point,
-            direction: v.normalize()
+            direction: v.normalize(),
+            time
         };
+        let max = v.magnitude();
+
+        let mut blocker: Option<Intersection> = None;
 
-        match hit(self.intersections(&r).as_slice()) {
-            Some(i) => i.distance < v.magnitude(),
-            None => false
+        for (object, shape) in self.shapes.iter().enumerate() {
+            for distance in shape.intersect(&r) {
+                if distance >= 0.0 && distance < blocker.map_or(max, |b| b.distance) {
+                    blocker = Some(Intersection { distance, object })
+                }
+            }
         }
+
+        blocker
+    }
+
+    #[cfg(test)]
+    fn is_shadowed(&self, light: &LightSource, point: &V4) -> bool {
+        self.shadow_blocker(light, point, 0.0).is_some()
+    }
+
+    #[cfg(test)]
+    fn refraction(&self, n_ratio: Float, point: V4, normalv: V4, eyev: V4, recurse: u32) -> V4 {
+        self.refraction_traced(n_ratio, &Ray::new(point, -eyev), normalv, recurse, None)
     }
 
-    fn refraction(&self, n_ratio: f32, point: V4, normalv: V4, eyev: V4, recurse: u32) -> V4
+    /// Color of the ray refracted where `ray` enters or leaves a surface at
+    /// `ray.origin`.
+    fn refraction_traced(&self, n_ratio: Float, ray: &Ray, normalv: V4, recurse: u32, log: Option<&mut HitNode>) -> V4
     {
+        let eyev = -ray.direction;
         let cos_i   = V4::dot(&eyev, &normalv);
         let sin_2t  = n_ratio*n_ratio * (1.0 - cos_i*cos_i);
 
         if sin_2t > 1.0 {
+            if let Some(node) = log {
+                node.total_internal_reflection = true
+            }
             return V4::from(Color::BLACK)
         }
 
         let cos_t = (1.0 - sin_2t).sqrt();
         let direction = normalv * (n_ratio * cos_i - cos_t) - eyev * n_ratio;
 
-        self.recursive_color_at(&Ray::new(point, direction), recurse)
+        self.cast(RayKind::Refracted, &Ray::new(ray.origin, direction).with_time(ray.time), recurse, log)
+    }
+
+    /// Color of a secondary ray; if we're tracing, the ray's subtree is
+    /// attached to `parent`.
+    fn cast(&self, kind: RayKind, ray: &Ray, recurse: u32, parent: Option<&mut HitNode>) -> V4 {
+        match parent {
+            Some(parent) => {
+                let mut node = RayNode::new(kind, ray, recurse);
+                let colorv = self.recursive_color_at_traced(ray, recurse, Some(&mut node));
+
+                if kind == RayKind::Refracted {
+                    parent.refracted = Some(Box::new(node))
+                } else {
+                    parent.reflected = Some(Box::new(node))
+                }
+
+                colorv
+            },
+            None => self.recursive_color_at(ray, recurse)
+        }
     }
 
     fn shade(&self, ray: &Ray, hit: &Intersection, xs: &[Intersection], recurse: u32) -> V4 {
+        self.shade_traced(ray, hit, xs, recurse, None)
+    }
+
+    /// Color at a hit. With `log`, records how it was computed in the
+    /// ray's node.
+    fn shade_traced(&self, ray: &Ray, hit: &Intersection, xs: &[Intersection], recurse: u32, log: Option<&mut RayNode>) -> V4 {
+        let object = &self.shapes[hit.object];
         let point = ray.position(hit.distance);
         let eyev  = -ray.direction;
-        let mut normalv = hit.object.normal_at(point);
+        let mut normalv = object.normal_at_time(point, ray.time);
+        let inside = V4::dot(&normalv, &eyev) < 0.0;
 
-        if V4::dot(&normalv, &eyev) < 0.0 {
+        if inside {
             normalv = -normalv
         }
 
+        let mut node = log.as_ref().map(|_| HitNode {
+                distance: hit.distance,
+                object_id: self.object_id(hit.object),
+                point,
+                normal: normalv,
+                inside,
+                shadows: Vec::new(),
+                n1: None,
+                n2: None,
+                reflectance: None,
+                total_internal_reflection: false,
+                reflected: None,
+                refracted: None
+            });
+
         // push point in direction of normal to avoid peppering
-        let opoint = point + normalv * 0.0001;
+        let opoint = point + normalv * EPSILON;
 
-        let material = hit.object.material();
+        let material = object.material();
+        let surface = material.color_at(object.pattern_point(opoint, ray.time));
 
         let mut colorv = V4::from(Color::BLACK);
 
-        for light in self.lights.iter() {
-            colorv +=
-                lighting::lighting(
+        for (l, light) in self.lights.iter().enumerate() {
+            let blocker = self.shadow_blocker(light, &opoint, ray.time);
+
+            let contribution =
+                lighting::lighting_with_color(
+                    surface,
                     material,
                     light,
                     &opoint,
                     &eyev,
                     &normalv,
-                    self.is_shadowed(light, &opoint)
+                    blocker.is_some()
                 );
+
+            if let Some(node) = node.as_mut() {
+                node.shadows.push(ShadowRecord {
+                    light: l,
+                    blocker: blocker.map(|b| (self.object_id(b.object), b.distance)),
+                    contribution: Color::from(contribution)
+                });
+            }
+
+            colorv += contribution;
         }
 
         if recurse > 0 {
             let reflected = if material.reflective > 0.0 {
-                let rfl_ray = Ray::new(opoint, V4::reflect(ray.direction, normalv));
-                let rfl_clr = self.recursive_color_at(&rfl_ray, recurse-1);
+                let rfl_ray = Ray::new(opoint, V4::reflect(ray.direction, normalv)).with_time(ray.time);
+                let rfl_clr = self.cast(RayKind::Reflected, &rfl_ray, recurse-1, node.as_mut());
 
                 rfl_clr * material.reflective
             } else {
@@ -170,12 +303,23 @@ impl World {
             };
 
             if material.transparency > 0.0 {
-                let (n1, n2) = refraction_index_pair(hit, xs);
-                let refracted = self.refraction(n1/n2, point-normalv*0.0001, normalv, eyev, recurse-1) * material.transparency;
+                let (n1, n2) = self.refraction_index_pair(hit, xs);
+
+                if let Some(node) = node.as_mut() {
+                    node.n1 = Some(n1);
+                    node.n2 = Some(n2);
+                }
+
+                let refr_ray = Ray::new(point - normalv * EPSILON, ray.direction).with_time(ray.time);
+                let refracted = self.refraction_traced(n1/n2, &refr_ray, normalv, recurse-1, node.as_mut()) * material.transparency;
 
                 if material.reflective > 0.0 {
                     let reflectance = schlick(n1, n2, normalv, eyev);
 
+                    if let Some(node) = node.as_mut() {
+                        node.reflectance = Some(reflectance)
+                    }
+
                     colorv += reflected * reflectance +
                               refracted * (1.0 - reflectance)
                 } else {
@@ -186,22 +330,86 @@ impl World {
             }
         }
 
+        if let Some(log) = log {
+            log.hit = node
+        }
+
         colorv
     }
 
     fn recursive_color_at(&self, ray: &Ray, recurse: u32) -> V4 {
+        self.recursive_color_at_traced(ray, recurse, None)
+    }
+
+    fn recursive_color_at_traced(&self, ray: &Ray, recurse: u32, mut log: Option<&mut RayNode>) -> V4 {
         let xs = self.intersections(ray);
 
-        match hit(xs.as_slice()) {
-            Some(i) => self.shade(ray, i, &xs, recurse),
+        if let Some(node) = log.as_mut() {
+            node.intersections = xs.iter().map(|i| (i.distance, self.object_id(i.object))).collect();
+        }
+
+        let colorv = match hit(&xs) {
+            Some(i) => self.shade_traced(ray, i, &xs, recurse, log.as_deref_mut()),
             None => V4::from(Color::BLACK)
+        };
+
+        if let Some(node) = log {
+            node.color = Color::from(colorv)
         }
+
+        colorv
+    }
+
+    /// Trace `ray` like `color_at()` and record the whole ray tree: every
+    /// intersection list, hit, shadow ray and secondary ray.
+    pub fn debug_trace(&self, ray: &Ray) -> RayNode {
+        let mut node = RayNode::new(RayKind::Primary, ray, self.max_depth);
+        self.recursive_color_at_traced(ray, self.max_depth, Some(&mut node));
+        node
     }
 
     pub fn color_at(&self, ray: &Ray) -> Color {
         Color::from(self.recursive_color_at(ray, self.max_depth))
     }
 
+    /// Like `color_at()`, but also returns the hit information of the ray.
+    /// Object IDs are the 1-based index of the shape in the world.
+    pub fn trace(&self, ray: &Ray) -> (Color, Option<HitRecord>) {
+        let xs = self.intersections(ray);
+
+        match hit(&xs) {
+            Some(i) => {
+                let object = &self.shapes[i.object];
+                let point = ray.position(i.distance);
+                let mut normalv = object.normal_at_time(point, ray.time);
+
+                if V4::dot(&normalv, &ray.direction) > 0.0 {
+                    normalv = -normalv
+                }
+
+                let rec = HitRecord {
+                    distance: i.distance,
+                    point,
+                    normal: normalv,
+                    albedo: object.material().color_at(object.pattern_point(point, ray.time)),
+                    object_id: self.object_id(i.object),
+                    material_id: object.material_id()
+                };
+
+                (Color::from(self.shade(ray, i, &xs, self.max_depth)), Some(rec))
+            },
+            None => (Color::BLACK, None)
+        }
+    }
+
+    pub fn lights(&self) -> &[LightSource] {
+        &self.lights
+    }
+
+    pub fn shapes(&self) -> &[Rc<Shape>] {
+        &self.shapes
+    }
+
     pub fn add_shape(&mut self, obj: Rc<Shape>) {
         self.shapes.push(Rc::clone(&obj));
     }
@@ -211,11 +419,17 @@ impl World {
     }
 }
 
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use crate::camera::Camera;
-    use crate::linalg::{M4,V4};
+    use crate::linalg::{consts,M4,V4};
     use crate::material::{Material,Texture};
     use crate::sphere::Sphere;
     use crate::lighting::*;
@@ -281,7 +495,8 @@ mod tests {
         let w = make_world();
         let r = Ray {
             origin: V4::new_point(0.0, 0.0, -5.0),
-            direction: V4::new_vector(0.0, 0.0, 1.0)
+            direction: V4::new_vector(0.0, 0.0, 1.0),
+            time: 0.0
         };
 
         let xs = w.intersections(&r);
@@ -294,18 +509,31 @@ mod tests {
         assert_eq!(xs[3].distance, 6.0)
     }
 
+    #[test]
+    fn scratch_buffers() {
+        let w = make_world();
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+
+        let c = w.color_at(&r);
+        let buffers = w.scratch.borrow().len();
+
+        assert!(buffers > 0);
+        assert_eq!(w.color_at(&r), c);
+        assert_eq!(w.scratch.borrow().len(), buffers);
+    }
+
     #[test]
     fn shade_hit() {
         let w = make_world();
         let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
 
-        let xs = vec![ Intersection { distance: 4.0, object: Rc::clone(&w.shapes[0]) } ];
+        let xs = vec![ Intersection { distance: 4.0, object: 0 } ];
 
         let c = Color::from(w.shade(&r, &xs[0], &xs, 1));
 
-        assert!(approx_eq!(f32, c.r, 0.38066, epsilon = 0.0001));
-        assert!(approx_eq!(f32, c.g, 0.47583, epsilon = 0.0001));
-        assert!(approx_eq!(f32, c.b, 0.2855,  epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.r, 0.38066, epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.g, 0.47583, epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.b, 0.2855,  epsilon = 0.0001));
     }
 
     #[test]
@@ -313,14 +541,14 @@ mod tests {
         let mut w = make_world();
         w.lights[0].pos = V4::new_point(0.0, 0.25, 0.0);
 
-        let xs = vec![ Intersection { distance: 0.5, object: Rc::clone(&w.shapes[1]) } ];
+        let xs = vec![ Intersection { distance: 0.5, object: 1 } ];
 
         let r = Ray::new(V4::new_point(0.0, 0.0, 0.0), V4::new_vector(0.0, 0.0, 1.0));
         let c = Color::from(w.shade(&r, &xs[0], &xs, 1));
 
-        assert!(approx_eq!(f32, c.r, 0.90498, epsilon = 0.0001));
-        assert!(approx_eq!(f32, c.g, 0.90498, epsilon = 0.0001));
-        assert!(approx_eq!(f32, c.b, 0.90498, epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.r, 0.90498, epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.g, 0.90498, epsilon = 0.0001));
+        assert!(approx_eq!(Float, c.b, 0.90498, epsilon = 0.0001));
     }
 
     #[test]
@@ -336,7 +564,8 @@ mod tests {
         let w = make_world();
         let r = Ray {
             origin: V4::new_point(0.0, 0.0, -5.0),
-            direction: V4::new_vector(0.0, 0.0, 1.0)
+            direction: V4::new_vector(0.0, 0.0, 1.0),
+            time: 0.0
         };
 
         let c = w.color_at(&r);
@@ -353,13 +582,58 @@ mod tests {
         let up = V4::new_vector(0.0, 1.0, 0.0);
 
         let t = Transform::view_transform(&from, &to, &up);
-        let c = Camera::new(11, 11, std::f32::consts::FRAC_PI_2, &t.matrix);
+        let c = Camera::new(11, 11, consts::FRAC_PI_2, &t.matrix);
+
+        let v = c.render(&w).at(5, 5);
+
+        assert!(approx_eq!(Float, v.r, 0.38066, epsilon = 0.0001));
+        assert!(approx_eq!(Float, v.g, 0.47583, epsilon = 0.0001));
+        assert!(approx_eq!(Float, v.b, 0.2855, epsilon = 0.0001));
+    }
 
-        let v = c.render_to_canvas(&w).at(5, 5);
+    #[test]
+    fn trace() {
+        let w = make_world();
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+
+        let (c, rec) = w.trace(&r);
+        let rec = rec.unwrap();
+
+        assert_eq!(c, w.color_at(&r));
+        assert_eq!(rec.distance, 4.0);
+        assert_eq!(rec.object_id, 1);
+        assert_eq!(rec.albedo, Color::new(0.8, 1.0, 0.6));
+        assert!(approx_eq!(V4, rec.normal, V4::new_vector(0.0, 0.0, -1.0), epsilon = 0.0001));
 
-        assert!(approx_eq!(f32, v.r, 0.38066, epsilon = 0.0001));
-        assert!(approx_eq!(f32, v.g, 0.47583, epsilon = 0.0001));
-        assert!(approx_eq!(f32, v.b, 0.2855, epsilon = 0.0001));
+        let r = Ray::new(V4::new_point(0.0, 0.0, -5.0), V4::new_vector(0.0, 1.0, 0.0));
+
+        assert_eq!(w.trace(&r), (Color::BLACK, None));
+    }
+
+    #[test]
+    fn motion() {
+        let keys = [
+            (0.0, Transform::new().matrix),
+            (1.0, Transform::new().translate(4.0, 0.0, 0.0).matrix)
+        ];
+        let s = Shape::new(Box::new(Sphere()), &MATERIAL, &keys[0].1).with_motion(&keys);
+        let w = World::new_with(vec![], vec![ Rc::new(s) ]);
+
+        let r = Ray::new(V4::new_point(4.0, 0.0, -5.0), V4::new_vector(0.0, 0.0, 1.0));
+
+        assert_eq!(w.trace(&r).1, None);
+
+        let rec = w.trace(&r.with_time(1.0)).1.unwrap();
+
+        assert_eq!(rec.distance, 4.0);
+        assert!(approx_eq!(V4, rec.normal, V4::new_vector(0.0, 0.0, -1.0), epsilon = 0.0001));
+
+        // the shadow of the moving sphere moves along with it
+        let light = LightSource { intensity: Color::WHITE, pos: V4::new_point(0.0, 10.0, 0.0) };
+        let p = V4::new_point(0.0, -2.0, 0.0);
+
+        assert!(w.shadow_blocker(&light, &p, 0.0).is_some());
+        assert!(w.shadow_blocker(&light, &p, 1.0).is_none());
     }
 
     #[test]
@@ -387,11 +661,11 @@ mod tests {
         let s = Rc::new(Shape::new(Box::new(Plane()), &m, &t.matrix));
         w.add_shape(Rc::clone(&s));
 
-        let sqrth = std::f32::consts::SQRT_2 * 0.5;
+        let sqrth = consts::SQRT_2 * 0.5;
 
         let r = Ray::new(V4::new_point(0.0, 0.0, -3.0), V4::new_vector(0.0, -sqrth, sqrth));
 
-        let xs = vec![ Intersection { distance: std::f32::consts::SQRT_2, object: s } ];
+        let xs = vec![ Intersection { distance: consts::SQRT_2, object: 2 } ];
 
         let c = w.shade(&r, &xs[0], &xs, 4);
 
@@ -443,21 +717,23 @@ mod tests {
         let t = Transform::new().translate(0.0, 0.0, 0.25);
         let c = Rc::new(Shape::new(Box::new(Sphere()), &m3, &t.matrix));
 
+        let w = World::new_with(vec![], vec![ a, b, c ]);
+
         let xs = vec![
-            Intersection { distance: 2.0,  object: Rc::clone(&a) },
-            Intersection { distance: 2.75, object: Rc::clone(&b) },
-            Intersection { distance: 3.25, object: Rc::clone(&c) },
-            Intersection { distance: 4.75, object: Rc::clone(&b) },
-            Intersection { distance: 5.25, object: Rc::clone(&c) },
-            Intersection { distance: 6.0,  object: Rc::clone(&a) }
+            Intersection { distance: 2.0,  object: 0 },
+            Intersection { distance: 2.75, object: 1 },
+            Intersection { distance: 3.25, object: 2 },
+            Intersection { distance: 4.75, object: 1 },
+            Intersection { distance: 5.25, object: 2 },
+            Intersection { distance: 6.0,  object: 0 }
         ];
 
-        assert_eq!(super::refraction_index_pair(&xs[0], &xs), (1.0, 1.5));
-        assert_eq!(super::refraction_index_pair(&xs[1], &xs), (1.5, 2.0));
-        assert_eq!(super::refraction_index_pair(&xs[2], &xs), (2.0, 2.5));
-        assert_eq!(super::refraction_index_pair(&xs[3], &xs), (2.5, 2.5));
-        assert_eq!(super::refraction_index_pair(&xs[4], &xs), (2.5, 1.5));
-        assert_eq!(super::refraction_index_pair(&xs[5], &xs), (1.5, 1.0));
+        assert_eq!(w.refraction_index_pair(&xs[0], &xs), (1.0, 1.5));
+        assert_eq!(w.refraction_index_pair(&xs[1], &xs), (1.5, 2.0));
+        assert_eq!(w.refraction_index_pair(&xs[2], &xs), (2.0, 2.5));
+        assert_eq!(w.refraction_index_pair(&xs[3], &xs), (2.5, 2.5));
+        assert_eq!(w.refraction_index_pair(&xs[4], &xs), (2.5, 1.5));
+        assert_eq!(w.refraction_index_pair(&xs[5], &xs), (1.5, 1.0));
     }
 
     #[test]
@@ -483,18 +759,18 @@ mod tests {
 
         w.shapes.push(Rc::new(Shape::new(Box::new(Sphere()), &m, &t.matrix)));
 
-        let sqrt2half = 0.5 * std::f32::consts::SQRT_2;
+        let sqrt2half = 0.5 * consts::SQRT_2;
 
         let xs = vec![
-            Intersection { distance: -sqrt2half, object: Rc::clone(&w.shapes[0]) },
-            Intersection { distance:  sqrt2half, object: Rc::clone(&w.shapes[0]) }
+            Intersection { distance: -sqrt2half, object: 0 },
+            Intersection { distance:  sqrt2half, object: 0 }
         ];
 
         let ray = Ray::new(V4::new_point(0.0, 0.0, sqrt2half), V4::new_vector(0.0, 1.0, 0.0));
         let point = ray.position(xs[1].distance);
         let eyev = -ray.direction;
-        let normalv = xs[1].object.normal_at(point);
-        let (n1,n2) = super::refraction_index_pair(&xs[1], &xs);
+        let normalv = w.shapes[xs[1].object].normal_at(point);
+        let (n1,n2) = w.refraction_index_pair(&xs[1], &xs);
         let c = w.refraction(n1/n2, point, normalv, eyev, 5);
 
         assert_eq!(c, V4::from(Color::BLACK))
@@ -548,23 +824,23 @@ mod tests {
         w.shapes.push(Rc::new(Shape::new(Box::new(Sphere()), &m, &t.matrix)));
 
         let xs = vec![
-            Intersection { distance: -0.9899, object: Rc::clone(&w.shapes[0]) },
-            Intersection { distance: -0.4899, object: Rc::clone(&w.shapes[1]) },
-            Intersection { distance:  0.4899, object: Rc::clone(&w.shapes[1]) },
-            Intersection { distance:  0.9899, object: Rc::clone(&w.shapes[0]) }
+            Intersection { distance: -0.9899, object: 0 },
+            Intersection { distance: -0.4899, object: 1 },
+            Intersection { distance:  0.4899, object: 1 },
+            Intersection { distance:  0.9899, object: 0 }
         ];
 
         let ray = Ray::new(V4::new_point(0.0, 0.0, 0.1), V4::new_vector(0.0, 1.0, 0.0));
         let point = ray.position(xs[2].distance);
         let eyev = -ray.direction;
-        let mut normalv = xs[2].object.normal_at(point);
+        let mut normalv = w.shapes[xs[2].object].normal_at(point);
 
         if V4::dot(&normalv, &eyev) < 0.0 {
             normalv = -normalv
         }
 
-        let (n1,n2) = super::refraction_index_pair(&xs[2], &xs);
-        let c = w.refraction(n1/n2, point - normalv * 0.0001, normalv, eyev, 5);
+        let (n1,n2) = w.refraction_index_pair(&xs[2], &xs);
+        let c = w.refraction(n1/n2, point - normalv * EPSILON, normalv, eyev, 5);
 
         assert!(approx_eq!(V4, c, V4::new_vector(0.0, 0.99888, 0.04725), epsilon = 0.0001))
     }
@@ -608,10 +884,10 @@ mod tests {
         w.shapes.push(Rc::new(Shape::new(Box::new(Sphere()), &m, &t.matrix)));
 
         let xs = vec![
-            Intersection { distance: std::f32::consts::SQRT_2, object: Rc::clone(&w.shapes[0]) },
+            Intersection { distance: consts::SQRT_2, object: 0 },
         ];
 
-        let sqrt2half = 0.5 * std::f32::consts::SQRT_2;
+        let sqrt2half = 0.5 * consts::SQRT_2;
         let ray = Ray::new(V4::new_point(0.0, 0.0, -3.0), V4::new_vector(0.0, -sqrt2half, sqrt2half));
 
         let c = w.shade(&ray, &xs[0], &xs, 5);
@@ -625,19 +901,20 @@ mod tests {
         m1.refractive_index = 1.5;
 
         let s = Rc::new(Shape::new(Box::new(Sphere()), &m1, &M4::identity()));
+        let w = World::new_with(vec![], vec![ s ]);
 
-        let sqrt2half = 0.5 * std::f32::consts::SQRT_2;
+        let sqrt2half = 0.5 * consts::SQRT_2;
         let ray = Ray::new(V4::new_point(0.0, 0.0, sqrt2half), V4::new_vector(0.0, 1.0, 0.0));
 
         let xs = vec![
-            Intersection { distance: -sqrt2half, object: Rc::clone(&s) },
-            Intersection { distance:  sqrt2half, object: Rc::clone(&s) }
+            Intersection { distance: -sqrt2half, object: 0 },
+            Intersection { distance:  sqrt2half, object: 0 }
         ];
 
         let point = ray.position(xs[1].distance);
         let eyev = -ray.direction;
-        let normalv = xs[1].object.normal_at(point);
-        let (n1,n2) = super::refraction_index_pair(&xs[1], &xs);
+        let normalv = w.shapes[xs[1].object].normal_at(point);
+        let (n1,n2) = w.refraction_index_pair(&xs[1], &xs);
         assert_eq!(schlick(n1, n2, normalv, eyev), 1.0)
     }
 
@@ -648,23 +925,24 @@ mod tests {
         m1.refractive_index = 1.5;
 
         let s = Rc::new(Shape::new(Box::new(Sphere()), &m1, &M4::identity()));
+        let w = World::new_with(vec![], vec![ s ]);
 
         let xs = vec![
-            Intersection { distance: -1.0, object: Rc::clone(&s) },
-            Intersection { distance:  1.0, object: Rc::clone(&s) }
+            Intersection { distance: -1.0, object: 0 },
+            Intersection { distance:  1.0, object: 0 }
         ];
 
         let ray = Ray::new(V4::new_point(0.0, 0.0, 0.0), V4::new_vector(0.0, 1.0, 0.0));
         let point = ray.position(xs[1].distance);
         let eyev = -ray.direction;
-        let mut normalv = xs[1].object.normal_at(point);
+        let mut normalv = w.shapes[xs[1].object].normal_at(point);
 
         if V4::dot(&normalv, &eyev) < 0.0 {
             normalv = -normalv
         }
 
-        let (n1,n2) = super::refraction_index_pair(&xs[1], &xs);
-        assert!(approx_eq!(f32, schlick(n1, n2, normalv, eyev), 0.04, epsilon=0.0001))
+        let (n1,n2) = w.refraction_index_pair(&xs[1], &xs);
+        assert!(approx_eq!(Float, schlick(n1, n2, normalv, eyev), 0.04, epsilon=0.0001))
     }
 
     #[test]
@@ -673,22 +951,23 @@ mod tests {
         m1.refractive_index = 1.5;
 
         let s = Rc::new(Shape::new(Box::new(Sphere()), &m1, &M4::identity()));
+        let w = World::new_with(vec![], vec![ s ]);
 
         let xs = vec![
-            Intersection { distance:  1.8589, object: Rc::clone(&s) }
+            Intersection { distance:  1.8589, object: 0 }
         ];
 
         let ray = Ray::new(V4::new_point(0.0, 0.99, -2.0), V4::new_vector(0.0, 0.0, 1.0));
         let point = ray.position(xs[0].distance);
         let eyev = -ray.direction;
-        let mut normalv = xs[0].object.normal_at(point);
+        let mut normalv = w.shapes[xs[0].object].normal_at(point);
 
         if V4::dot(&normalv, &eyev) < 0.0 {
             normalv = -normalv
         }
 
-        let (n1,n2) = super::refraction_index_pair(&xs[0], &xs);
-        assert!(approx_eq!(f32, schlick(n1, n2, normalv, eyev), 0.48873, epsilon=0.0001))
+        let (n1,n2) = w.refraction_index_pair(&xs[0], &xs);
+        assert!(approx_eq!(Float, schlick(n1, n2, normalv, eyev), 0.48873, epsilon=0.0001))
     }
 
     #[test]
@@ -724,13 +1003,65 @@ mod tests {
 
         w.shapes.push(Rc::new(Shape::new(Box::new(Sphere()), &m, &t.matrix)));
 
-        let sqrth = std::f32::consts::SQRT_2 * 0.5;
+        let sqrth = consts::SQRT_2 * 0.5;
 
         let r = Ray::new(V4::new_point(0.0, 0.0, -3.0), V4::new_vector(0.0, -sqrth, sqrth));
 
-        let xs = vec![ Intersection { distance: std::f32::consts::SQRT_2, object: Rc::clone(&floor) } ];
+        let xs = vec![ Intersection { distance: consts::SQRT_2, object: 2 } ];
 
         let c = w.shade(&r, &xs[0], &xs, 5);
         assert!(approx_eq!(V4, c, V4::new_vector(0.93391, 0.69643, 0.69243), epsilon = 0.0001))
     }
+
+    #[test]
+    fn debug_trace() {
+        let mut w = World::new();
+
+        w.lights.push( LightSource {
+                intensity: Color::WHITE,
+                pos: V4::new_point(-10.0, 10.0, -10.0)
+            } );
+
+        let mut m = MATERIAL;
+        m.reflective = 0.5;
+        m.transparency = 0.5;
+        m.refractive_index = 1.5;
+
+        let t = Transform::new().translate(0.0, -1.0, 0.0);
+        w.shapes.push(Rc::new(Shape::new(Box::new(Plane()), &m, &t.matrix)));
+
+        let t = Transform::new().translate(0.0, -3.5, -0.5);
+        w.shapes.push(Rc::new(Shape::new(Box::new(Sphere()), &MATERIAL, &t.matrix)));
+
+        let sqrt2half = 0.5 * consts::SQRT_2;
+        let ray = Ray::new(V4::new_point(0.0, 0.0, -3.0), V4::new_vector(0.0, -sqrt2half, sqrt2half));
+
+        let tree = w.debug_trace(&ray);
+
+        assert_eq!(tree.kind, RayKind::Primary);
+        assert_eq!(tree.color, w.color_at(&ray));
+        assert_eq!(tree.intersections.len(), 3);
+
+        let hit = tree.hit.as_ref().unwrap();
+
+        assert_eq!(hit.object_id, 1);
+        assert!(approx_eq!(Float, hit.distance, consts::SQRT_2, epsilon = 0.0001));
+        assert!(!hit.inside);
+        assert_eq!(hit.shadows.len(), 1);
+        assert_eq!(hit.shadows[0].blocker, None);
+        assert_eq!((hit.n1, hit.n2), (Some(1.0), Some(1.5)));
+        assert!(hit.reflectance.is_some());
+
+        let refracted = hit.refracted.as_ref().unwrap();
+
+        assert_eq!(refracted.kind, RayKind::Refracted);
+        assert_eq!(refracted.depth, 4);
+        assert_eq!(refracted.hit.as_ref().unwrap().object_id, 2);
+        assert_eq!(hit.reflected.as_ref().unwrap().kind, RayKind::Reflected);
+
+        let text = tree.to_string();
+
+        assert!(text.contains("n1/n2: 1/1.5"));
+        assert!(text.contains("refracted ray"));
+    }
 }